@@ -2,15 +2,20 @@ use anyhow::Result;
 use clap::Parser;
 
 pub mod log_util {
+    use crate::capture::SharedCapture;
     use anyhow::Context;
     use clap::ColorChoice;
     use colored::Colorize;
-    use log::{debug, LevelFilter};
+    use log::{debug, info, log_enabled, Level, LevelFilter};
     use simple_logger::SimpleLogger;
-    use std::process::Output;
-    use tokio::process::Command;
+    use std::process::{ExitStatus, Output, Stdio};
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::process::{Child, Command};
 
-    pub(crate) async fn log_and_run_command(command: &mut Command) -> anyhow::Result<Output> {
+    /// The longest piece of a line that is read, and logged, at once.
+    const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+    fn log_command(command: &Command) {
         // Get the program and arguments
         let program = command.as_std().get_program().to_str().unwrap_or("");
         let args: Vec<String> = command
@@ -31,6 +36,10 @@ pub mod log_util {
 
         // Log the command
         debug!("{} {}", "❯".green(), full_command);
+    }
+
+    pub(crate) async fn log_and_run_command(command: &mut Command) -> anyhow::Result<Output> {
+        log_command(command);
 
         // Execute the command
         let output = command
@@ -49,23 +58,141 @@ pub mod log_util {
         Ok(output)
     }
 
-    struct CustomLogger;
+    /// Like `log_and_run_command`, but with `input` written to the command's stdin.
+    pub(crate) async fn log_and_run_command_with_input(
+        command: &mut Command,
+        input: &[u8],
+    ) -> anyhow::Result<Output> {
+        log_command(command);
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute command")?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .context("Failed to write command input")?;
+        // Written while the output is read, so that a command answering each line of input
+        // can't fill its stdout pipe and stall.
+        let write = async move { stdin.write_all(input).await };
+        let (written, output) = tokio::join!(write, child.wait_with_output());
+        let output = output.context("Failed to execute command")?;
+        // A command that failed early may not have read all its input; its status tells why.
+        if output.status.success() {
+            written.context("Failed to write command input")?;
+        }
+
+        if !output.stdout.is_empty() {
+            debug!("{}", String::from_utf8_lossy(&output.stdout).dimmed());
+        }
+        if !output.stderr.is_empty() {
+            debug!("{}", String::from_utf8_lossy(&output.stderr).red());
+        }
+
+        Ok(output)
+    }
+
+    /// Logs and starts `command` with its output piped, for `stream_child`.
+    pub(crate) fn log_and_spawn_command(command: &mut Command) -> anyhow::Result<Child> {
+        log_command(command);
+
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute command")
+    }
+
+    /// Waits for `child` like `log_and_run_command`, but reads its stdout and stderr as it
+    /// runs rather than all at once at the end, into the shared captures, which keep what was
+    /// read even if this is cancelled. With `echo` set, each line is logged as soon as it
+    /// arrives, after the given prefix; lines of stderr are echoed to stderr.
+    pub(crate) async fn stream_child(
+        mut child: Child,
+        echo: Option<&str>,
+        stdout_capture: SharedCapture,
+        stderr_capture: SharedCapture,
+    ) -> anyhow::Result<ExitStatus> {
+        let stdout = child
+            .stdout
+            .take()
+            .context("Failed to read command output")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("Failed to read command output")?;
+
+        let (_, _, status) = tokio::try_join!(
+            read_lines(stdout, echo, false, stdout_capture),
+            read_lines(stderr, echo, true, stderr_capture),
+            async { child.wait().await.context("Failed to execute command") },
+        )?;
+        Ok(status)
+    }
+
+    async fn read_lines(
+        stream: impl AsyncRead + Unpin,
+        echo: Option<&str>,
+        is_stderr: bool,
+        capture: SharedCapture,
+    ) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = (&mut reader)
+                .take(MAX_LINE_LENGTH)
+                .read_until(b'\n', &mut line)
+                .await?;
+            if read == 0 {
+                return Ok(());
+            }
+            if let Some(prefix) = echo {
+                let text = String::from_utf8_lossy(&line);
+                let text = format!("{}{}", prefix, text.trim_end_matches(['\n', '\r']));
+                if !is_stderr {
+                    info!("{}", text);
+                } else if log_enabled!(Level::Info) {
+                    crate::progress::eprintln(&text);
+                }
+            }
+            capture
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(&line)?;
+        }
+    }
+
+    struct CustomLogger {
+        to_stderr: bool,
+    }
 
     impl log::Log for CustomLogger {
-        fn enabled(&self, metadata: &log::Metadata) -> bool {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
             true
         }
 
         fn log(&self, record: &log::Record) {
-            if self.enabled(record.metadata()) {
-                println!("{}", record.args());
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            if self.to_stderr {
+                eprintln!("{}", record.args());
+            } else {
+                crate::progress::println(&record.args().to_string());
             }
         }
 
         fn flush(&self) {}
     }
 
-    pub fn init_logging(verbosity: i8, choice: ColorChoice) -> anyhow::Result<()> {
+    /// Sets up logging. With `to_stderr`, log lines go to stderr rather than stdout, so that
+    /// they don't get mixed into machine-readable output.
+    pub fn init_logging(verbosity: i8, choice: ColorChoice, to_stderr: bool) -> anyhow::Result<()> {
         let log_level = match verbosity {
             i8::MIN..=-2 => LevelFilter::Error,
             -1 => LevelFilter::Warn,
@@ -74,10 +201,10 @@ pub mod log_util {
             2..=i8::MAX => LevelFilter::Trace,
         };
 
-        if verbosity >= 3 {
+        if verbosity >= 3 && !to_stderr {
             SimpleLogger::new().with_level(log_level).init()?;
         } else {
-            log::set_boxed_logger(Box::new(CustomLogger))?;
+            log::set_boxed_logger(Box::new(CustomLogger { to_stderr }))?;
             log::set_max_level(log_level);
         }
 
@@ -94,779 +221,6849 @@ pub mod log_util {
     }
 }
 
-pub mod git {
-    use crate::log_util::log_and_run_command;
+pub mod capture {
     use anyhow::{Context, Result};
-    use regex::Regex;
-    use std::path::{Path, PathBuf};
-    use std::process::Output;
-    use tokio::process::Command;
-
-    async fn run_git_with_output(root: &Path, args: &[&str]) -> Result<Output> {
-        let mut cmd = Command::new("git");
-        cmd.arg("-C").arg(root).args(args);
-        log_and_run_command(&mut cmd).await
-    }
-
-    pub async fn run_git_with_string(root: &Path, args: &[&str]) -> Result<String> {
-        let output = run_git_with_output(root, args).await?;
-        if output.status.success() {
-            Ok(String::from_utf8(output.stdout)?.trim().to_string())
-        } else {
-            Err(anyhow::anyhow!("Git command failed: {:?}", args))
-        }
-    }
-
-    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-    pub struct GitSha(String);
-
-    impl GitSha {
-        pub fn new(sha: String) -> Self {
-            GitSha(sha)
-        }
-
-        pub fn as_str(&self) -> &str {
-            &self.0
-        }
+    use std::collections::VecDeque;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    /// Bytes of output kept at each end of a stream when `test.outputLimit` is unset. The
+    /// config value is a size for `parse_size`; `0` keeps all of the output.
+    pub const DEFAULT_OUTPUT_LIMIT: usize = 256 * 1024;
+
+    /// Parses a size such as `65536`, `64k` or `1m`, as git does for integer config values: a
+    /// plain number is in bytes.
+    pub fn parse_size(value: &str) -> Result<usize> {
+        let value = value.trim();
+        let (digits, multiplier) = match value.chars().last().map(|c| c.to_ascii_lowercase()) {
+            Some('k') => (&value[..value.len() - 1], 1024),
+            Some('m') => (&value[..value.len() - 1], 1024 * 1024),
+            Some('g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+            _ => (value, 1),
+        };
+        let number: usize = digits
+            .parse()
+            .with_context(|| format!("Invalid size '{}'", value))?;
+        number
+            .checked_mul(multiplier)
+            .with_context(|| format!("Size '{}' is too large", value))
     }
 
-    #[derive(Clone, Debug)]
-    pub struct GitRepository {
-        root: PathBuf,
+    /// Collects one output stream of a test, keeping at most `limit` bytes from its start and
+    /// `limit` from its end, and optionally copying all of it to a file.
+    #[derive(Debug)]
+    pub struct Capture {
+        limit: Option<usize>,
+        head: Vec<u8>,
+        tail: VecDeque<u8>,
+        total: u64,
+        spill: Option<(PathBuf, std::fs::File)>,
     }
 
-    #[derive(Clone)]
-    pub struct GitTestCommand {
-        pub repo: GitRepository,
-        pub test_name: String,
-        pub test_command: String,
+    /// A `Capture` filled by a reader that may be cancelled, as when a test times out, while
+    /// whoever started it can still finish it.
+    pub type SharedCapture = Arc<Mutex<Capture>>;
+
+    /// What a `Capture` kept of a stream.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CapturedOutput {
+        /// The retained output, with a marker where anything was elided.
+        pub text: String,
+        /// How many bytes were left out of `text`.
+        pub elided: u64,
+        /// The file holding the complete stream, if it was spilled.
+        pub file: Option<PathBuf>,
     }
 
-    impl GitRepository {
-        pub fn new(root: PathBuf) -> Self {
-            GitRepository { root }
+    impl Capture {
+        /// A capture that keeps `limit` bytes at each end, or everything if `limit` is `None`.
+        pub fn new(limit: Option<usize>) -> Self {
+            Capture {
+                limit,
+                head: Vec::new(),
+                tail: VecDeque::new(),
+                total: 0,
+                spill: None,
+            }
         }
 
-        pub fn test_command(&self, test_name: String, test_command: String) -> GitTestCommand {
-            GitTestCommand {
-                repo: self.clone(),
-                test_name,
-                test_command,
-            }
+        /// Also writes the complete stream to `path`.
+        pub fn spill_to(mut self, path: PathBuf) -> Result<Self> {
+            let file = std::fs::File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            self.spill = Some((path, file));
+            Ok(self)
         }
 
-        pub async fn get_repo_root(dir: &Path) -> Result<Self> {
-            let root = run_git_with_string(dir, &["rev-parse", "--show-toplevel"])
-                .await
-                .context("Failed to execute git rev-parse --show-toplevel")?;
+        pub fn push(&mut self, bytes: &[u8]) -> Result<()> {
+            self.total += bytes.len() as u64;
+            if let Some((path, file)) = &mut self.spill {
+                file.write_all(bytes)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
 
-            let root = PathBuf::from(root.trim());
-            Ok(GitRepository::new(root))
+            let Some(limit) = self.limit else {
+                self.head.extend_from_slice(bytes);
+                return Ok(());
+            };
+            let to_head = bytes.len().min(limit - self.head.len());
+            self.head.extend_from_slice(&bytes[..to_head]);
+            let rest = &bytes[to_head..];
+            let rest = &rest[rest.len().saturating_sub(limit)..];
+            let overflow = (self.tail.len() + rest.len()).saturating_sub(limit);
+            self.tail.drain(..overflow);
+            self.tail.extend(rest);
+            Ok(())
         }
 
-        pub fn root(&self) -> &Path {
-            &self.root
+        /// Shares the capture, for `finish_shared`.
+        pub fn shared(self) -> SharedCapture {
+            Arc::new(Mutex::new(self))
         }
 
-        pub async fn run_git(&self, args: &[&str]) -> Result<String> {
-            run_git_with_string(self.root(), args).await
+        /// Finishes a shared capture with what was pushed so far, leaving it empty.
+        pub fn finish_shared(capture: &SharedCapture) -> CapturedOutput {
+            let mut capture = capture.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *capture, Capture::new(None)).finish()
         }
 
-        pub async fn get_config_value(&self, key: &str) -> Result<String> {
-            self.run_git(&["config", "--get", key])
-                .await
-                .context("Failed to get git config value")
+        pub fn finish(self) -> CapturedOutput {
+            let kept = (self.head.len() + self.tail.len()) as u64;
+            let elided = self.total - kept;
+            let mut text = String::from_utf8_lossy(&self.head).into_owned();
+            if elided > 0 {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[... {} bytes elided ...]\n", elided));
+            }
+            let (front, back) = self.tail.as_slices();
+            text.push_str(&String::from_utf8_lossy(&[front, back].concat()));
+            CapturedOutput {
+                text,
+                elided,
+                file: self.spill.map(|(path, _)| path),
+            }
         }
+    }
+}
 
-        pub async fn set_config_value(&self, key: &str, value: &str) -> Result<()> {
-            self.run_git(&["config", key, value])
-                .await
-                .with_context(|| format!("Failed to set git config value for key '{}'", key))?;
-            Ok(())
+pub mod shell {
+    /// Quotes `value` for use as a single word in a POSIX shell command line.
+    pub fn quote(value: &str) -> String {
+        if !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+        {
+            value.to_string()
+        } else {
+            format!("'{}'", value.replace('\'', r"'\''"))
         }
+    }
 
-        pub async fn get_test_command(&self, test_name: &str) -> Result<GitTestCommand> {
-            let key = format!("test.{}.command", test_name);
-            self.get_config_value(&key)
-                .await
-                .map(|test_command| self.test_command(test_name.to_string(), test_command))
-                .with_context(|| format!("Test '{}' is not defined", test_name))
+    /// Replaces each `{name}` in `template` with the quoted value of `name` in `values`.
+    /// Braces that don't form a known placeholder, as in `${test}` or `{a,b}`, are left alone.
+    pub fn expand(template: &str, values: &[(&str, &str)]) -> String {
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let placeholder = rest.find('}').filter(|_| !expanded.ends_with('$'));
+            let placeholder = placeholder.and_then(|end| {
+                let name = &rest[1..end];
+                values
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| (end, *value))
+            });
+            match placeholder {
+                Some((end, value)) => {
+                    expanded.push_str(&quote(value));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &rest[1..];
+                }
+            }
         }
+        expanded.push_str(rest);
+        expanded
+    }
+}
 
-        pub async fn set_test_command(&self, test: &str, command: &str) -> Result<()> {
-            self.set_config_value(&format!("test.{}.command", test), command)
-                .await
-        }
+pub mod progress {
+    use crate::git::GitSha;
+    use colored::Colorize;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
 
-        pub async fn list_tests(&self) -> Result<Vec<GitTestCommand>> {
-            let output = self
-                .run_git(&["config", "--get-regexp", "--null", r"^test\..*\.command$"])
-                .await?;
+    /// The status lines currently drawn at the bottom of the terminal, while a live display is
+    /// active. Everything else printed in the meantime has to go above them.
+    static LIVE_AREA: Mutex<Option<Vec<String>>> = Mutex::new(None);
 
-            let test_config_re = Regex::new(r"^test\.(?P<name>.*)\.command$")
-                .context("Failed to compile test config regex")?;
+    fn live_area() -> MutexGuard<'static, Option<Vec<String>>> {
+        LIVE_AREA.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-            output
-                .split('\0')
-                .filter_map(|entry| {
-                    let mut parts = entry.splitn(2, '\n');
-                    match (parts.next(), parts.next()) {
-                        (Some(key), Some(test_command)) => {
-                            test_config_re.captures(key).map(|captures| {
-                                captures.name("name").map(|name_match| {
-                                    self.test_command(
-                                        name_match.as_str().to_string(),
-                                        test_command.to_string(),
-                                    )
-                                })
-                            })
-                        }
-                        _ => None,
-                    }
-                })
-                .collect::<Option<Vec<GitTestCommand>>>()
-                .context("Failed to parse git config output")
+    /// Prints a line to stdout, above the live status area if one is being displayed.
+    pub fn println(line: &str) {
+        let area = live_area();
+        let mut stdout = std::io::stdout().lock();
+        if let Some(lines) = area.as_ref() {
+            erase(&mut stdout, lines.len());
         }
-
-        pub async fn get_head_commit(&self) -> Result<String> {
-            self.run_git(&["rev-parse", "HEAD"])
-                .await
-                .context("Failed to get HEAD commit")
+        let _ = writeln!(stdout, "{}", line);
+        if let Some(lines) = area.as_ref() {
+            draw(&mut stdout, lines);
         }
+        let _ = stdout.flush();
+    }
 
-        pub async fn add_note(&self, ref_name: &str, object: &str, content: &str) -> Result<()> {
-            self.run_git(&[
-                "notes", "--ref", ref_name, "add", "-f", "-m", content, object,
-            ])
-            .await?;
-            Ok(())
+    /// Prints a line to stderr, moving the live status area out of its way.
+    pub fn eprintln(line: &str) {
+        let area = live_area();
+        let mut stdout = std::io::stdout().lock();
+        if let Some(lines) = area.as_ref() {
+            erase(&mut stdout, lines.len());
+            let _ = stdout.flush();
+        }
+        let mut stderr = std::io::stderr().lock();
+        let _ = writeln!(stderr, "{}", line);
+        let _ = stderr.flush();
+        if let Some(lines) = area.as_ref() {
+            draw(&mut stdout, lines);
         }
+        let _ = stdout.flush();
     }
 
-    pub async fn get_repo_root(dir: &Path) -> Result<GitRepository> {
-        let mut cmd = Command::new("git");
-        cmd.arg("-C")
-            .arg(dir)
-            .args(["rev-parse", "--show-toplevel"]);
+    fn redraw(lines: Vec<String>) {
+        let mut area = live_area();
+        let mut stdout = std::io::stdout().lock();
+        erase(&mut stdout, area.as_ref().map_or(0, Vec::len));
+        draw(&mut stdout, &lines);
+        let _ = stdout.flush();
+        *area = Some(lines);
+    }
 
-        let output = log_and_run_command(&mut cmd)
-            .await
-            .context("Failed to execute git rev-parse --show-toplevel")?;
+    fn clear_live_area() {
+        let mut area = live_area();
+        if let Some(lines) = area.take() {
+            let mut stdout = std::io::stdout().lock();
+            erase(&mut stdout, lines.len());
+            let _ = stdout.flush();
+        }
+    }
 
-        if output.status.success() {
-            let root = PathBuf::from(String::from_utf8(output.stdout)?.trim());
-            Ok(GitRepository::new(root))
-        } else {
-            Err(anyhow::anyhow!("Not in a git repository"))
+    fn erase(out: &mut impl Write, line_count: usize) {
+        if line_count > 0 {
+            let _ = write!(out, "\x1b[{}A\r\x1b[J", line_count);
         }
     }
 
-    // Enumeration for worktree configuration
-    #[derive(Debug, Clone)]
-    pub enum WorktreeConfig {
-        Main(GitRepository),
-        Linked { repo: GitRepository, path: PathBuf },
+    fn draw(out: &mut impl Write, lines: &[String]) {
+        for line in lines {
+            let _ = writeln!(out, "{}", line);
+        }
     }
 
-    // Enumeration for actual worktree
-    #[derive(Debug, Clone)]
-    pub enum Worktree {
-        Main(GitRepository),
-        Linked {
-            repo: GitRepository,
-            path_prefix: PathBuf,
-            sha: GitSha,
-            test_name: String,
-        },
+    #[derive(Default)]
+    struct State {
+        total: usize,
+        done: usize,
+        good: usize,
+        bad: usize,
+        known: usize,
+        in_flight: Vec<(GitSha, String, Instant)>,
+        finished: usize,
+        finished_duration: Duration,
     }
 
-    impl WorktreeConfig {
-        pub fn to_worktree(&self, sha: GitSha, test_name: &str) -> Worktree {
-            match self {
-                WorktreeConfig::Main(repo) => Worktree::Main(repo.clone()),
-                WorktreeConfig::Linked { repo, path } => Worktree::Linked {
-                    repo: repo.clone(),
-                    path_prefix: path.clone(),
-                    sha,
-                    test_name: test_name.to_string(),
-                },
+    struct Inner {
+        state: Mutex<State>,
+        live: bool,
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            if self.live {
+                clear_live_area();
             }
         }
     }
 
-    impl Worktree {
-        pub async fn create(&self) -> Result<()> {
-            if let Worktree::Linked {
-                repo,
-                path_prefix,
-                sha,
-                test_name,
-            } = self
-            {
-                let worktree_path = self.get_path();
-                tokio::fs::create_dir_all(&worktree_path).await?;
-                repo.run_git(&[
-                    "worktree",
-                    "add",
-                    "--detach",
-                    worktree_path.to_str().unwrap(),
-                    sha.as_str(),
-                ])
-                .await?;
+    /// Tracks the progress of a `git test run`. When live, a status area at the bottom of the
+    /// terminal shows the tests in flight, running totals and an ETA; it is removed once the
+    /// last clone of the `Progress` is dropped.
+    #[derive(Clone)]
+    pub struct Progress {
+        inner: Arc<Inner>,
+    }
+
+    impl Progress {
+        /// `total` is the number of (commit, test) pairs the run covers.
+        pub fn new(total: usize, live: bool) -> Self {
+            let inner = Arc::new(Inner {
+                state: Mutex::new(State {
+                    total,
+                    ..State::default()
+                }),
+                live,
+            });
+
+            if live {
+                let weak = Arc::downgrade(&inner);
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(Duration::from_millis(200));
+                    loop {
+                        interval.tick().await;
+                        let Some(inner) = weak.upgrade() else {
+                            break;
+                        };
+                        redraw(Progress { inner }.status_lines());
+                    }
+                });
             }
-            Ok(())
+
+            Progress { inner }
         }
 
-        pub async fn delete(&self) -> Result<()> {
-            if let Worktree::Linked { repo, .. } = self {
-                let worktree_path = self.get_path();
-                repo.run_git(&[
-                    "worktree",
-                    "remove",
-                    "--force",
-                    worktree_path.to_str().unwrap(),
-                ])
-                .await?;
-            }
-            Ok(())
+        /// Whether a live display can be used: stdout must be a terminal and colors must not
+        /// have been turned off with `--color never`.
+        pub fn can_be_live() -> bool {
+            colored::control::SHOULD_COLORIZE.should_colorize() && atty::is(atty::Stream::Stdout)
         }
 
-        pub fn get_path(&self) -> PathBuf {
-            match self {
-                Worktree::Main(repo) => repo.root().to_path_buf(),
-                Worktree::Linked {
-                    path_prefix,
-                    sha,
-                    test_name,
-                    ..
-                } => path_prefix.join(format!("{}/{}", sha.as_str(), test_name)),
-            }
+        pub fn is_live(&self) -> bool {
+            self.inner.live
         }
-    }
 
-    // Extension trait for GitRepository to support worktree operations
-    pub trait GitRepositoryWorktreeExt {
-        fn to_worktree_config(&self) -> WorktreeConfig;
-        fn to_linked_worktree_config(&self, path: &Path) -> WorktreeConfig;
-    }
+        fn state(&self) -> MutexGuard<'_, State> {
+            self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+        }
 
-    impl GitRepositoryWorktreeExt for GitRepository {
-        fn to_worktree_config(&self) -> WorktreeConfig {
-            WorktreeConfig::Main(self.clone())
+        /// A test didn't need to run because its result was already known.
+        pub fn record_known(&self) {
+            let mut state = self.state();
+            state.known += 1;
+            state.done += 1;
         }
 
-        fn to_linked_worktree_config(&self, path: &Path) -> WorktreeConfig {
-            let absolute_path = if path.is_absolute() {
-                path.to_path_buf()
+        /// A test was neither run nor known (e.g. with `--dry-run` or `--forget`).
+        pub fn record_skipped(&self) {
+            self.state().done += 1;
+        }
+
+        pub fn start_test(&self, sha: &GitSha, test_name: &str) {
+            self.state()
+                .in_flight
+                .push((sha.clone(), test_name.to_string(), Instant::now()));
+        }
+
+        pub fn finish_test(&self, sha: &GitSha, test_name: &str, success: bool) {
+            let mut state = self.state();
+            if let Some(index) = state
+                .in_flight
+                .iter()
+                .position(|(s, name, _)| s == sha && name == test_name)
+            {
+                let (_, _, started) = state.in_flight.remove(index);
+                state.finished += 1;
+                state.finished_duration += started.elapsed();
+            }
+            if success {
+                state.good += 1;
             } else {
-                self.root().join(path)
-            };
-            WorktreeConfig::Linked {
-                repo: self.clone(),
-                path: absolute_path,
+                state.bad += 1;
             }
+            state.done += 1;
         }
-    }
-}
 
-pub mod cli {
-    use clap::{Args, ColorChoice, Parser, Subcommand};
-    use std::path::PathBuf;
+        /// The lines of the status area: a summary line, then one line per test in flight.
+        pub fn status_lines(&self) -> Vec<String> {
+            let state = self.state();
+            let remaining = state.total.saturating_sub(state.done);
+
+            let mut summary = format!(
+                "{} good, {} bad, {} known, {} remaining",
+                state.good.to_string().green(),
+                state.bad.to_string().red(),
+                state.known,
+                remaining
+            );
+            if state.finished > 0 && remaining > 0 {
+                let mean = state.finished_duration / state.finished as u32;
+                let parallel = state.in_flight.len().max(1) as u32;
+                let eta = mean * remaining as u32 / parallel;
+                summary.push_str(&format!(", ETA {}", format_duration(eta)));
+            }
 
-    #[derive(Parser)]
-    #[command(
-        name = "git-test",
-        author,
-        version,
-        about,
-        long_about = "Run tests within a Git project and remember the test results.\n\
-    `git test` consists of a few things:\n\
-    * A way of defining tests for a Git project. The commands to be run\n\
-      for a particular test are stored in the repository's Git\n\
-      configuration.\n\
-    * Tools for running such tests against single Git commits or against\n\
-      ranges of commits.\n\
-    * A scheme for storing the results of such tests as git notes. The\n\
-      results are connected to the tree of the commit that was tested, so\n\
-      the test results remain valid across some types of merges, rebases,\n\
-      etc.\n\
-    * The intelligence not to re-run a test whose results are already\n\
-      known.\n\
-    \n\
-    Example: make sure that all commits on a feature branch pass the tests\n\
-    implied by `make -j16 test` (run the tests in a worktree to avoid\n\
-    tying up your main repository):\n\
-        $ git config test.full.command 'make -j16 test'\n\
-        $ git worktree add --detach ../tests feature\n\
-        $ cd ../tests\n\
-        $ git test run --test=full master..feature\n\
-    Any time you make changes to the feature branch in your main\n\
-    repository, you can re-run the last command in the `tests` worktree.\n\
-    It will only test commits with trees that it hasn't been seen before."
-    )]
-    pub struct Cli {
-        #[command(subcommand)]
-        pub command: Commands,
+            let mut lines = vec![summary];
+            for (sha, test_name, started) in &state.in_flight {
+                lines.push(format!(
+                    "  {} {} {}",
+                    sha.short().yellow(),
+                    test_name,
+                    format_duration(started.elapsed()).dimmed()
+                ));
+            }
+            lines
+        }
+    }
 
-        #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "generate more verbose output (may be specified multiple times)")]
-        pub verbose: u8,
+    fn format_duration(duration: Duration) -> String {
+        let secs = duration.as_secs();
+        if secs >= 3600 {
+            format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        } else {
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }
+    }
+}
 
-        #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "generate less verbose output (may be specified multiple times)")]
-        pub quiet: u8,
+pub mod definitions {
+    use anyhow::{Context, Result};
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    /// The file, at the root of the repository, holding test definitions shared by everyone
+    /// who clones it.
+    pub const DEFINITIONS_FILE: &str = ".gittest.toml";
+
+    /// Parses a duration such as `90`, `90s`, `15m`, `2h` or `90d`; bare numbers are seconds.
+    pub fn parse_duration(value: &str) -> Result<Duration> {
+        let value = value.trim();
+        let (digits, unit) = match value.chars().last() {
+            Some('s') => (&value[..value.len() - 1], 1),
+            Some('m') => (&value[..value.len() - 1], 60),
+            Some('h') => (&value[..value.len() - 1], 60 * 60),
+            Some('d') => (&value[..value.len() - 1], 24 * 60 * 60),
+            _ => (value, 1),
+        };
+        let number: u64 = digits
+            .parse()
+            .with_context(|| format!("Invalid duration '{}'", value))?;
+        number
+            .checked_mul(unit)
+            .map(Duration::from_secs)
+            .with_context(|| format!("Duration '{}' is too long", value))
+    }
 
-        #[arg(
-        long,
-        global = true,
-        help = "Control when to use colors: auto, always, never",
-        default_value_t = ColorChoice::Auto
-        )]
-        pub color: ColorChoice,
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct DefinitionsFile {
+        #[serde(default)]
+        test: Vec<FileTest>,
     }
 
-    #[derive(Subcommand)]
-    pub enum Commands {
-        #[command(about = "define a new test")]
-        Add(AddArgs),
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    enum Timeout {
+        Seconds(u64),
+        Text(String),
+    }
 
-        #[command(about = "run a test against one or more commits")]
-        Run(RunArgs),
+    /// One `[[test]]` table of `.gittest.toml`.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct FileTest {
+        name: String,
+        command: String,
+        timeout: Option<Timeout>,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        #[serde(default)]
+        paths: Vec<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        matrix: BTreeMap<String, Vec<String>>,
+    }
 
-        #[command(about = "obsolete command; please use \"git test run\" instead")]
-        Range(RunArgs),
+    /// A test defined in `.gittest.toml`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FileDefinition {
+        pub name: String,
+        pub command: String,
+        pub timeout: Option<Duration>,
+        pub env: Vec<(String, String)>,
+        /// The subdirectory the test depends on, which its results are keyed on, like
+        /// `test.<name>.tree`.
+        pub tree: Option<String>,
+        pub tags: Vec<String>,
+        /// Each parameter the test is run with, and the values it takes.
+        pub matrix: Vec<(String, Vec<String>)>,
+    }
 
-        #[command(about = "show any stored test results for the specified commits")]
-        Results(ResultsArgs),
+    /// A parameter of a matrix test: a name and the values it takes.
+    pub type MatrixAxis = (String, Vec<String>);
+
+    /// Parses a `test.<name>.matrix` value such as `features=default,full,minimal`.
+    pub fn parse_matrix_axis(value: &str) -> Result<MatrixAxis> {
+        let invalid = || format!("Invalid matrix '{}'; expected <name>=<value>,...", value);
+        let (name, values) = value.split_once('=').with_context(invalid)?;
+        let name = name.trim();
+        let values: Vec<String> = values.split(',').map(|v| v.trim().to_string()).collect();
+        check_matrix_axis(name, &values).with_context(invalid)?;
+        Ok((name.to_string(), values))
+    }
 
-        #[command(about = "permanently forget stored results for a test")]
-        ForgetResults(ForgetResultsArgs),
+    /// The placeholders every test command can use, which matrix parameters may not shadow.
+    const BUILTIN_PLACEHOLDERS: [&str; 5] = ["sha", "short", "tree", "worktree", "test"];
 
-        #[command(about = "list the tests that are currently defined")]
-        List,
+    fn check_matrix_axis(name: &str, values: &[String]) -> Result<()> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("Matrix parameter names may only contain letters, digits and '_'");
+        }
+        if BUILTIN_PLACEHOLDERS.contains(&name) {
+            anyhow::bail!(
+                "Matrix parameter '{}' would shadow the {{{}}} placeholder",
+                name,
+                name
+            );
+        }
+        if values.is_empty() || values.iter().any(|value| value.is_empty()) {
+            anyhow::bail!("Matrix parameter '{}' has an empty value", name);
+        }
+        // Values become part of the variant's name, and with it of its notes ref.
+        if let Some(value) = values.iter().find(|value| {
+            !value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._+-".contains(c))
+        }) {
+            anyhow::bail!(
+                "Matrix value '{}' of '{}' may only contain letters, digits, '.', '_', '+' and '-'",
+                value,
+                name
+            );
+        }
+        Ok(())
+    }
 
-        #[command(about = "remove a test definition and all of its stored results")]
-        Remove(RemoveArgs),
+    /// Every combination of one value per axis, in order, each as `(name, value)` pairs.
+    pub fn matrix_combinations(axes: &[MatrixAxis]) -> Vec<Vec<(String, String)>> {
+        axes.iter()
+            .fold(vec![Vec::new()], |combinations, (name, values)| {
+                combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        values.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push((name.clone(), value.clone()));
+                            combination
+                        })
+                    })
+                    .collect()
+            })
     }
 
-    #[derive(Args)]
-    pub struct AddArgs {
-        #[arg(
-            short,
-            long,
-            default_value = "default",
-            help = "name of test to add (default is 'default')"
-        )]
-        pub test: String,
+    /// The name of the variant of matrix test `base` run with `params`, such as
+    /// `cargo@features=full`. It is used as is for the variant's notes ref.
+    pub fn variant_name(base: &str, params: &[(String, String)]) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        format!("{}@{}", base, params.join(","))
+    }
 
-        #[arg(long, help = "forget any existing results", conflicts_with = "keep")]
-        pub forget: bool,
+    /// Parses the contents of a `.gittest.toml`.
+    pub fn parse_definitions(contents: &str) -> Result<Vec<FileDefinition>> {
+        let file: DefinitionsFile = toml::from_str(contents)?;
 
-        #[arg(
-            long,
-            help = "keep any existing results (default)",
-            conflicts_with = "forget",
-            default_value_t = true
-        )]
-        pub keep: bool,
+        let mut definitions: Vec<FileDefinition> = Vec::new();
+        for test in file.test {
+            if definitions.iter().any(|d| d.name == test.name) {
+                anyhow::bail!("Test '{}' is defined more than once", test.name);
+            }
+            let timeout = match test.timeout {
+                None => None,
+                Some(Timeout::Seconds(seconds)) => Some(Duration::from_secs(seconds)),
+                Some(Timeout::Text(text)) => Some(
+                    parse_duration(&text)
+                        .with_context(|| format!("Invalid timeout for test '{}'", test.name))?,
+                ),
+            };
+            // Results are keyed on a single tree, so a test can only depend on one path.
+            if test.paths.len() > 1 {
+                anyhow::bail!(
+                    "Test '{}' lists more than one path; only a single subdirectory is supported",
+                    test.name
+                );
+            }
+            for (name, values) in &test.matrix {
+                check_matrix_axis(name, values)
+                    .with_context(|| format!("Invalid matrix for test '{}'", test.name))?;
+            }
+            definitions.push(FileDefinition {
+                name: test.name,
+                command: test.command,
+                timeout,
+                env: test.env.into_iter().collect(),
+                tree: test.paths.into_iter().next(),
+                tags: test.tags,
+                matrix: test.matrix.into_iter().collect(),
+            });
+        }
+        Ok(definitions)
+    }
+}
 
-        #[arg(help = "command to run")]
-        pub command: String,
+pub mod git {
+    use crate::definitions::{
+        matrix_combinations, parse_definitions, parse_matrix_axis, variant_name, FileDefinition,
+        MatrixAxis, DEFINITIONS_FILE,
+    };
+    use crate::log_util::{log_and_run_command, log_and_run_command_with_input};
+    use anyhow::{Context, Result};
+    use async_trait::async_trait;
+    use regex::Regex;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{HashMap, HashSet};
+    use std::path::{Path, PathBuf};
+    use std::process::Output;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio::process::Command;
+
+    async fn run_git_with_output(root: &Path, args: &[&str]) -> Result<Output> {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(root).args(args);
+        log_and_run_command(&mut cmd).await
     }
 
-    #[derive(Args)]
-    pub struct RunArgs {
-        #[arg(
-            short,
-            long,
-            default_value = "default",
-            help = "name of test (default is 'default')",
-            conflicts_with = "all"
-        )]
-        pub test: Option<String>,
+    pub async fn run_git_with_string(root: &Path, args: &[&str]) -> Result<String> {
+        let output = run_git_with_output(root, args).await?;
+        if output.status.success() {
+            Ok(String::from_utf8(output.stdout)?.trim().to_string())
+        } else {
+            Err(anyhow::anyhow!("Git command failed: {:?}", args))
+        }
+    }
 
-        #[arg(long, help = "run all defined tests", conflicts_with = "test")]
-        pub all: bool,
+    /// Runs git with `input` on its stdin, returning its trimmed stdout.
+    pub async fn run_git_with_input(root: &Path, args: &[&str], input: &str) -> Result<String> {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(root).args(args);
+        let output = log_and_run_command_with_input(&mut cmd, input.as_bytes()).await?;
+        if output.status.success() {
+            Ok(String::from_utf8(output.stdout)?.trim().to_string())
+        } else {
+            Err(anyhow::anyhow!("Git command failed: {:?}", args))
+        }
+    }
 
-        #[arg(
-            short,
-            long,
-            help = "forget any existing test results for the specified commits and test them again"
-        )]
-        pub force: bool,
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+    pub struct GitSha(String);
 
-        #[arg(
-            long,
-            help = "forget any existing test results for the specified commits"
-        )]
-        pub forget: bool,
+    impl GitSha {
+        pub fn new(sha: String) -> Self {
+            GitSha(sha)
+        }
 
-        #[arg(
-            long,
-            help = "if a commit is already marked as \"bad\", try testing it again"
-        )]
-        pub retest: bool,
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
 
-        #[arg(
-            short,
-            long,
-            help = "if a commit fails the test, continue testing other commits rather than aborting"
-        )]
-        pub keep_going: bool,
+        /// The first ten characters, for display.
+        pub fn short(&self) -> &str {
+            &self.0[..self.0.len().min(10)]
+        }
+    }
 
-        #[arg(
-            short = 'n',
-            long,
-            help = "show known results, without running any new tests"
-        )]
-        pub dry_run: bool,
+    /// The repository operations that git-test performs over and over: resolving revisions,
+    /// reading config, reading and writing result notes, and managing worktrees.
+    /// `GitRepository` goes through a backend for all of these, and runs `git` directly only
+    /// for one-off operations such as pushing and fetching.
+    #[async_trait]
+    pub trait GitBackend: Send + Sync + std::fmt::Debug {
+        /// Resolves a revision such as `HEAD`, `<sha>^{tree}` or `<sha>:<path>` to an object
+        /// name, or returns `None` if it doesn't name an object.
+        async fn rev_parse(&self, spec: &str) -> Result<Option<String>>;
 
-        #[arg(
-            long,
-            help = "read the list of commits to test from standard input, one per line"
-        )]
-        pub stdin: bool,
+        /// The commits in a range such as `main..feature`, oldest first.
+        async fn rev_list(&self, range: &str) -> Result<Vec<String>>;
 
-        #[arg(
-            long,
-            help = "run tests in git worktrees",
-            default_value = ".worktrees"
-        )]
-        pub worktree: Option<PathBuf>,
+        async fn config_get(&self, key: &str) -> Result<Option<String>>;
 
-        #[arg(help = "commits or ranges of commits to test")]
-        pub commits: Vec<String>,
-    }
+        /// All `(key, value)` pairs whose key matches `pattern`, in config file order.
+        async fn config_get_regexp(&self, pattern: &str) -> Result<Vec<(String, String)>>;
 
-    #[derive(Args)]
-    pub struct ResultsArgs {
-        #[arg(
-            short,
-            long,
-            default_value = "default",
-            help = "name of test (default is 'default')"
-        )]
-        pub test: String,
+        async fn config_set(&self, key: &str, value: &str) -> Result<()>;
 
-        #[arg(
-            long,
-            help = "read the list of commits from standard input, one per line"
-        )]
-        pub stdin: bool,
+        /// Adds another value for `key`, keeping any it already has.
+        async fn config_add(&self, key: &str, value: &str) -> Result<()>;
 
-        #[arg(help = "commits or ranges of commits")]
-        pub commits: Vec<String>,
-    }
+        /// Removes a whole section, such as `test.default`, failing if it doesn't exist.
+        async fn config_remove_section(&self, section: &str) -> Result<()>;
 
-    #[derive(Args)]
-    pub struct ForgetResultsArgs {
-        #[arg(
-            short,
-            long,
-            default_value = "default",
-            help = "name of test whose results should be forgotten (default is 'default')"
-        )]
-        pub test: String,
-    }
+        /// Renames a whole section in one go, failing if it doesn't exist.
+        async fn config_rename_section(&self, section: &str, new_section: &str) -> Result<()>;
 
-    #[derive(Args)]
-    pub struct RemoveArgs {
-        #[arg(
-            short,
-            long,
-            default_value = "default",
-            help = "name of test to remove (default is 'default')"
-        )]
-        pub test: String,
-    }
-}
+        /// The note attached to `object` in `ref_name`, or `None` if there isn't one.
+        async fn note_read(&self, ref_name: &str, object: &str) -> Result<Option<String>>;
 
-pub mod commands {
-    use crate::git::GitRepository;
-    use anyhow::Result;
-    use log::{info, warn};
+        /// Attaches `content` to `object` in `ref_name`, replacing any existing note.
+        async fn note_write(&self, ref_name: &str, object: &str, content: &str) -> Result<()>;
 
-    pub mod add {
-        use super::*;
-        use crate::commands::forget_results::forget_results;
+        /// Removes the note attached to `object` in `ref_name`, if there is one.
+        async fn note_remove(&self, ref_name: &str, object: &str) -> Result<()>;
 
-        use anyhow::{Context, Result};
-        use log::{info, warn};
+        /// Removes the notes attached to each of `objects` in `ref_name`, in a single notes
+        /// commit. Objects without a note are skipped.
+        async fn note_remove_many(&self, ref_name: &str, objects: &[String]) -> Result<()>;
 
-        pub async fn cmd_add(
-            repo: &GitRepository,
-            test: &str,
-            forget: bool,
-            keep: bool,
-            command: &str,
+        /// Writes each `(object, content)` note into `ref_name` in a single notes commit that
+        /// also has `merged_commit` as a parent, so that `ref_name` then contains its history
+        /// and can be pushed over it without forcing. `object` names are full object IDs.
+        async fn note_merge(
+            &self,
+            ref_name: &str,
+            merged_commit: &str,
+            notes: &[(String, String)],
+        ) -> Result<()>;
+
+        /// Every `(note blob, annotated object)` pair in `ref_name`, which need not exist.
+        async fn note_list(&self, ref_name: &str) -> Result<Vec<(String, String)>>;
+
+        async fn read_blob(&self, blob: &str) -> Result<String>;
+
+        /// The contents of each of `blobs`, untrimmed, in the order given.
+        async fn read_blobs(&self, blobs: &[String]) -> Result<Vec<String>>;
+
+        /// The size in bytes of each of `blobs`, in the order given.
+        async fn blob_sizes(&self, blobs: &[String]) -> Result<Vec<u64>>;
+
+        async fn ref_exists(&self, ref_name: &str) -> Result<bool>;
+
+        /// Deletes `ref_name` outright, if it exists.
+        async fn delete_ref(&self, ref_name: &str) -> Result<()>;
+
+        /// Whether the commit `ancestor` is `descendant` or one of its ancestors.
+        async fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool>;
+
+        /// Creates a worktree at `path` with `commit` checked out on a detached HEAD.
+        async fn worktree_add(&self, path: &Path, commit: &str) -> Result<()>;
+
+        /// Switches the existing worktree at `path` to `commit`, discarding local changes.
+        async fn worktree_checkout(&self, path: &Path, commit: &str) -> Result<()>;
+
+        async fn worktree_remove(&self, path: &Path) -> Result<()>;
+    }
+
+    /// The backend used by `GitRepository::new`: in-process when built with the `gitoxide`
+    /// feature and the repository can be opened that way, and the `git` binary otherwise.
+    pub fn default_backend(root: &Path) -> Arc<dyn GitBackend> {
+        #[cfg(feature = "gitoxide")]
+        if let Ok(backend) = gitoxide::GixBackend::open(root) {
+            return Arc::new(backend);
+        }
+        Arc::new(SubprocessBackend::new(root.to_path_buf()))
+    }
+
+    /// Runs the `git` binary for every operation.
+    #[derive(Debug, Clone)]
+    pub struct SubprocessBackend {
+        root: PathBuf,
+    }
+
+    impl SubprocessBackend {
+        pub fn new(root: PathBuf) -> Self {
+            SubprocessBackend { root }
+        }
+
+        async fn run_git(&self, args: &[&str]) -> Result<String> {
+            run_git_with_string(&self.root, args).await
+        }
+    }
+
+    #[async_trait]
+    impl GitBackend for SubprocessBackend {
+        async fn rev_parse(&self, spec: &str) -> Result<Option<String>> {
+            let output =
+                run_git_with_output(&self.root, &["rev-parse", "--verify", "--quiet", spec])
+                    .await?;
+            match output.status.code() {
+                Some(0) => Ok(Some(String::from_utf8(output.stdout)?.trim().to_string())),
+                Some(1) => Ok(None),
+                _ => Err(anyhow::anyhow!("Failed to resolve '{}'", spec)),
+            }
+        }
+
+        async fn rev_list(&self, range: &str) -> Result<Vec<String>> {
+            let output = self.run_git(&["rev-list", "--reverse", range]).await?;
+            Ok(output.lines().map(str::to_string).collect())
+        }
+
+        async fn config_get(&self, key: &str) -> Result<Option<String>> {
+            let output = run_git_with_output(&self.root, &["config", "--get", key]).await?;
+            match output.status.code() {
+                Some(0) => Ok(Some(String::from_utf8(output.stdout)?.trim().to_string())),
+                Some(1) => Ok(None),
+                _ => Err(anyhow::anyhow!(
+                    "Failed to get git config value for key '{}'",
+                    key
+                )),
+            }
+        }
+
+        async fn config_get_regexp(&self, pattern: &str) -> Result<Vec<(String, String)>> {
+            let output =
+                run_git_with_output(&self.root, &["config", "--get-regexp", "--null", pattern])
+                    .await?;
+            match output.status.code() {
+                Some(0) => {}
+                Some(1) => return Ok(Vec::new()),
+                _ => anyhow::bail!("Failed to read git config matching '{}'", pattern),
+            }
+
+            Ok(String::from_utf8(output.stdout)?
+                .split('\0')
+                .filter_map(|entry| {
+                    let mut parts = entry.splitn(2, '\n');
+                    match (parts.next(), parts.next()) {
+                        (Some(key), Some(value)) => Some((key.to_string(), value.to_string())),
+                        _ => None,
+                    }
+                })
+                .collect())
+        }
+
+        async fn config_set(&self, key: &str, value: &str) -> Result<()> {
+            self.run_git(&["config", key, value]).await?;
+            Ok(())
+        }
+
+        async fn config_add(&self, key: &str, value: &str) -> Result<()> {
+            self.run_git(&["config", "--add", key, value]).await?;
+            Ok(())
+        }
+
+        async fn config_remove_section(&self, section: &str) -> Result<()> {
+            self.run_git(&["config", "--remove-section", section])
+                .await?;
+            Ok(())
+        }
+
+        async fn config_rename_section(&self, section: &str, new_section: &str) -> Result<()> {
+            self.run_git(&["config", "--rename-section", section, new_section])
+                .await?;
+            Ok(())
+        }
+
+        async fn note_read(&self, ref_name: &str, object: &str) -> Result<Option<String>> {
+            let output =
+                run_git_with_output(&self.root, &["notes", "--ref", ref_name, "show", object])
+                    .await?;
+            match output.status.code() {
+                Some(0) => Ok(Some(String::from_utf8(output.stdout)?.trim().to_string())),
+                // git exits with 1 when there is no note, and fails harder otherwise.
+                Some(1) => Ok(None),
+                _ if self.rev_parse(object).await?.is_none() => Ok(None),
+                _ => Err(anyhow::anyhow!(
+                    "Failed to read the note for '{}' in '{}'",
+                    object,
+                    ref_name
+                )),
+            }
+        }
+
+        async fn note_write(&self, ref_name: &str, object: &str, content: &str) -> Result<()> {
+            // On stdin rather than with `-m`, since a note holding test output can be larger
+            // than the system allows a single argument to be.
+            run_git_with_input(
+                &self.root,
+                &["notes", "--ref", ref_name, "add", "-f", "-F", "-", object],
+                content,
+            )
+            .await?;
+            Ok(())
+        }
+
+        async fn note_remove(&self, ref_name: &str, object: &str) -> Result<()> {
+            self.run_git(&[
+                "notes",
+                "--ref",
+                ref_name,
+                "remove",
+                "--ignore-missing",
+                object,
+            ])
+            .await?;
+            Ok(())
+        }
+
+        async fn note_remove_many(&self, ref_name: &str, objects: &[String]) -> Result<()> {
+            if objects.is_empty() {
+                return Ok(());
+            }
+            let input: String = objects
+                .iter()
+                .map(|object| format!("{}\n", object))
+                .collect();
+            run_git_with_input(
+                &self.root,
+                &[
+                    "notes",
+                    "--ref",
+                    ref_name,
+                    "remove",
+                    "--ignore-missing",
+                    "--stdin",
+                ],
+                &input,
+            )
+            .await?;
+            Ok(())
+        }
+
+        /// Builds the merge commit with one `git fast-import`, which can write the new note
+        /// blobs, the notes tree and the commit, and move the ref, all at once. The tree is
+        /// written without fan-out; `git notes` reads that fine and fans it out again as needed.
+        async fn note_merge(
+            &self,
+            ref_name: &str,
+            merged_commit: &str,
+            notes: &[(String, String)],
+        ) -> Result<()> {
+            let committer = self.run_git(&["var", "GIT_COMMITTER_IDENT"]).await?;
+            let message = format!("Notes merged from {} by 'git test'\n", merged_commit);
+            let mut input = format!(
+                "commit {}\ncommitter {}\ndata {}\n{}",
+                ref_name,
+                committer,
+                message.len(),
+                message
+            );
+            let parent = self.rev_parse(ref_name).await?;
+            if let Some(parent) = &parent {
+                input.push_str(&format!("from {}\n", parent));
+            }
+            input.push_str(&format!("merge {}\n", merged_commit));
+            if parent.is_some() {
+                input.push_str("deleteall\n");
+                let replaced: HashSet<&str> =
+                    notes.iter().map(|(object, _)| object.as_str()).collect();
+                for (blob, object) in self.note_list(ref_name).await? {
+                    if !replaced.contains(object.as_str()) {
+                        input.push_str(&format!("M 100644 {} {}\n", blob, object));
+                    }
+                }
+            }
+            for (object, content) in notes {
+                let content = format!("{}\n", content.trim_end());
+                input.push_str(&format!(
+                    "M 100644 inline {}\ndata {}\n{}",
+                    object,
+                    content.len(),
+                    content
+                ));
+            }
+            input.push('\n');
+            run_git_with_input(&self.root, &["fast-import", "--quiet"], &input).await?;
+            Ok(())
+        }
+
+        async fn note_list(&self, ref_name: &str) -> Result<Vec<(String, String)>> {
+            if !self.ref_exists(ref_name).await? {
+                return Ok(Vec::new());
+            }
+            let output = self.run_git(&["notes", "--ref", ref_name, "list"]).await?;
+            Ok(output
+                .lines()
+                .filter_map(|line| {
+                    line.split_once(' ')
+                        .map(|(blob, object)| (blob.to_string(), object.to_string()))
+                })
+                .collect())
+        }
+
+        async fn read_blob(&self, blob: &str) -> Result<String> {
+            self.run_git(&["cat-file", "blob", blob]).await
+        }
+
+        /// Reads all of `blobs` with a single `git cat-file --batch`.
+        async fn read_blobs(&self, blobs: &[String]) -> Result<Vec<String>> {
+            if blobs.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut cmd = Command::new("git");
+            cmd.arg("-C").arg(&self.root).args(["cat-file", "--batch"]);
+            let input: String = blobs.iter().map(|blob| format!("{}\n", blob)).collect();
+            let output = log_and_run_command_with_input(&mut cmd, input.as_bytes()).await?;
+            anyhow::ensure!(output.status.success(), "Failed to read blobs");
+
+            // Each blob comes as `<sha> blob <size>\n<content>\n`.
+            let mut rest = output.stdout.as_slice();
+            let mut contents = Vec::with_capacity(blobs.len());
+            for blob in blobs {
+                let header_end = rest
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .context("Failed to parse git cat-file output")?;
+                let header = std::str::from_utf8(&rest[..header_end])?;
+                let size: usize = match header.split(' ').collect::<Vec<_>>()[..] {
+                    [_, "blob", size] => size.parse()?,
+                    _ => anyhow::bail!("Failed to read blob {}", blob),
+                };
+                let content = rest
+                    .get(header_end + 1..header_end + 1 + size)
+                    .context("Failed to parse git cat-file output")?;
+                contents.push(String::from_utf8(content.to_vec())?);
+                rest = rest.get(header_end + size + 2..).unwrap_or_default();
+            }
+            Ok(contents)
+        }
+
+        /// Sizes all of `blobs` with a single `git cat-file --batch-check`.
+        async fn blob_sizes(&self, blobs: &[String]) -> Result<Vec<u64>> {
+            if blobs.is_empty() {
+                return Ok(Vec::new());
+            }
+            let input: String = blobs.iter().map(|blob| format!("{}\n", blob)).collect();
+            let output =
+                run_git_with_input(&self.root, &["cat-file", "--batch-check"], &input).await?;
+            output
+                .lines()
+                .zip(blobs)
+                .map(
+                    |(line, blob)| match line.split(' ').collect::<Vec<_>>()[..] {
+                        [_, "blob", size] => Ok(size.parse()?),
+                        _ => anyhow::bail!("Failed to read blob {}", blob),
+                    },
+                )
+                .collect()
+        }
+
+        async fn ref_exists(&self, ref_name: &str) -> Result<bool> {
+            let output =
+                run_git_with_output(&self.root, &["show-ref", "--verify", "--quiet", ref_name])
+                    .await?;
+            Ok(output.status.success())
+        }
+
+        async fn delete_ref(&self, ref_name: &str) -> Result<()> {
+            if self.ref_exists(ref_name).await? {
+                self.run_git(&["update-ref", "-d", ref_name]).await?;
+            }
+            Ok(())
+        }
+
+        async fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+            let output = run_git_with_output(
+                &self.root,
+                &["merge-base", "--is-ancestor", ancestor, descendant],
+            )
+            .await?;
+            match output.status.code() {
+                Some(0) => Ok(true),
+                Some(1) => Ok(false),
+                _ => Err(anyhow::anyhow!(
+                    "Failed to tell whether {} is an ancestor of {}",
+                    ancestor,
+                    descendant
+                )),
+            }
+        }
+
+        async fn worktree_add(&self, path: &Path, commit: &str) -> Result<()> {
+            tokio::fs::create_dir_all(path).await?;
+            self.run_git(&[
+                "worktree",
+                "add",
+                "--detach",
+                path.to_str().unwrap(),
+                commit,
+            ])
+            .await?;
+            Ok(())
+        }
+
+        async fn worktree_checkout(&self, path: &Path, commit: &str) -> Result<()> {
+            run_git_with_string(
+                path,
+                &["checkout", "--quiet", "--force", "--detach", commit],
+            )
+            .await?;
+            Ok(())
+        }
+
+        async fn worktree_remove(&self, path: &Path) -> Result<()> {
+            self.run_git(&["worktree", "remove", "--force", path.to_str().unwrap()])
+                .await?;
+            Ok(())
+        }
+    }
+
+    /// A backend that reads and writes config, notes and revisions in-process with gitoxide,
+    /// instead of forking `git` for each one. Worktrees are still managed with the `git`
+    /// binary, since gitoxide can't create them.
+    #[cfg(feature = "gitoxide")]
+    pub mod gitoxide {
+        use super::{GitBackend, SubprocessBackend};
+        use anyhow::{Context, Result};
+        use async_trait::async_trait;
+        use gix::bstr::{BStr, ByteSlice};
+        use gix::objs::tree::EntryKind;
+        use gix::ObjectId;
+        use regex::Regex;
+        use std::path::{Path, PathBuf};
+
+        #[derive(Debug)]
+        pub struct GixBackend {
+            root: PathBuf,
+            repo: gix::ThreadSafeRepository,
+            subprocess: SubprocessBackend,
+        }
+
+        impl GixBackend {
+            pub fn open(root: &Path) -> Result<Self> {
+                let repo = gix::ThreadSafeRepository::open(root)
+                    .with_context(|| format!("Failed to open {}", root.display()))?;
+                Ok(GixBackend {
+                    root: root.to_path_buf(),
+                    repo,
+                    subprocess: SubprocessBackend::new(root.to_path_buf()),
+                })
+            }
+
+            fn repo(&self) -> gix::Repository {
+                self.repo.to_thread_local()
+            }
+
+            /// A freshly opened repository, for reading config that may have changed since
+            /// this backend was opened.
+            fn reopen(&self) -> Result<gix::Repository> {
+                gix::open(&self.root)
+                    .with_context(|| format!("Failed to open {}", self.root.display()))
+            }
+
+            fn resolve(repo: &gix::Repository, spec: &str) -> Result<ObjectId> {
+                Ok(repo
+                    .rev_parse_single(spec)
+                    .with_context(|| format!("Failed to resolve '{}'", spec))?
+                    .detach())
+            }
+
+            fn notes_tree<'repo>(
+                repo: &'repo gix::Repository,
+                ref_name: &str,
+            ) -> Result<Option<(ObjectId, gix::Tree<'repo>)>> {
+                let Some(mut reference) = repo.try_find_reference(ref_name)? else {
+                    return Ok(None);
+                };
+                let commit = reference.peel_to_commit()?;
+                Ok(Some((commit.id, commit.tree()?)))
+            }
+
+            /// Finds the note for `object` in a notes tree, following git's fan-out of note
+            /// paths into `ab/cdef...` subtrees. Returns the note's path and blob.
+            fn find_note(
+                tree: &gix::Tree<'_>,
+                object: &ObjectId,
+            ) -> Result<Option<(String, ObjectId)>> {
+                let hex = object.to_string();
+                let mut tree = tree.clone();
+                let mut path = String::new();
+                let mut rest = hex.as_str();
+                loop {
+                    if let Some(entry) = tree.find_entry(rest) {
+                        if entry.mode().is_blob() {
+                            return Ok(Some((path + rest, entry.object_id())));
+                        }
+                    }
+                    if rest.len() <= 2 {
+                        return Ok(None);
+                    }
+                    let (fanout, remainder) = rest.split_at(2);
+                    let subtree = match tree.find_entry(fanout) {
+                        Some(entry) if entry.mode().is_tree() => entry.object()?.into_tree(),
+                        _ => return Ok(None),
+                    };
+                    path.push_str(fanout);
+                    path.push('/');
+                    tree = subtree;
+                    rest = remainder;
+                }
+            }
+
+            /// Commits a new version of the notes tree made by `edit`, as `git notes` would,
+            /// with `merged` as an additional parent if given.
+            fn edit_notes(
+                repo: &gix::Repository,
+                ref_name: &str,
+                message: &str,
+                merged: Option<ObjectId>,
+                edit: impl FnOnce(
+                    &mut gix::object::tree::Editor<'_>,
+                    Option<&gix::Tree<'_>>,
+                ) -> Result<bool>,
+            ) -> Result<()> {
+                let notes = Self::notes_tree(repo, ref_name)?;
+                let (parent, tree) = match &notes {
+                    Some((parent, tree)) => (Some(*parent), tree.clone()),
+                    None => (None, repo.empty_tree()),
+                };
+                let mut editor = tree.edit()?;
+                if !edit(&mut editor, notes.as_ref().map(|(_, tree)| tree))? {
+                    return Ok(());
+                }
+                let new_tree = editor.write()?;
+                repo.commit(
+                    ref_name,
+                    message,
+                    new_tree,
+                    parent.into_iter().chain(merged),
+                )
+                .with_context(|| format!("Failed to update '{}'", ref_name))?;
+                Ok(())
+            }
+
+            /// The commits in `from..to`, oldest first, or `None` for other kinds of range
+            /// such as `a...b`, which are left to `git rev-list`.
+            fn walk_range(&self, range: &str) -> Result<Option<Vec<String>>> {
+                use gix::revision::plumbing::Spec;
+                use gix::revision::walk::Sorting;
+                use gix::traverse::commit::simple::CommitTimeOrder;
+
+                let repo = self.repo();
+                let (from, to) = match repo.rev_parse(range)?.detach() {
+                    Spec::Range { from, to } => (from, to),
+                    _ => return Ok(None),
+                };
+                let mut commits = repo
+                    .rev_walk([to])
+                    .with_hidden([from])
+                    .sorting(Sorting::ByCommitTime(CommitTimeOrder::NewestFirst))
+                    .all()?
+                    .map(|info| Ok(info?.id.to_string()))
+                    .collect::<Result<Vec<_>>>()?;
+                commits.reverse();
+                Ok(Some(commits))
+            }
+
+            /// Splits a config key like `test.my.name.command` into its section, subsection
+            /// and value name.
+            fn split_key(key: &str) -> Result<(&str, Option<&BStr>, &str)> {
+                let (section, rest) = key
+                    .split_once('.')
+                    .with_context(|| format!("Invalid config key '{}'", key))?;
+                Ok(match rest.rsplit_once('.') {
+                    Some((subsection, name)) => (section, Some(subsection.into()), name),
+                    None => (section, None, rest),
+                })
+            }
+
+            /// Loads the repository's own config file for editing and writes it back after
+            /// `edit` changes it. Like git, it holds `config.lock` meanwhile and renames it over
+            /// the config file, so concurrent writers fail rather than clobber each other.
+            fn edit_local_config(
+                &self,
+                edit: impl FnOnce(&mut gix::config::File<'static>) -> Result<()>,
+            ) -> Result<()> {
+                use std::io::Write;
+
+                let path = self.repo().common_dir().join("config");
+                let mut lock = gix::lock::File::acquire_to_update_resource(
+                    &path,
+                    gix::lock::acquire::Fail::Immediately,
+                    None,
+                )
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+                let mut file = gix::config::File::from_path_no_includes(
+                    path.clone(),
+                    gix::config::Source::Local,
+                )?;
+                edit(&mut file)?;
+                lock.write_all(&file.to_bstring())
+                    .and_then(|()| lock.flush())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                lock.commit()
+                    .map_err(|err| err.error)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(())
+            }
+        }
+
+        #[async_trait]
+        impl GitBackend for GixBackend {
+            async fn rev_parse(&self, spec: &str) -> Result<Option<String>> {
+                let resolved = self.repo().rev_parse_single(spec).map(|id| id.to_string());
+                match resolved {
+                    Ok(id) => Ok(Some(id)),
+                    // gitoxide's errors don't tell a missing object apart from a broken
+                    // repository, so let git decide which it is.
+                    Err(_) => self.subprocess.rev_parse(spec).await,
+                }
+            }
+
+            async fn rev_list(&self, range: &str) -> Result<Vec<String>> {
+                match self.walk_range(range)? {
+                    Some(commits) => Ok(commits),
+                    None => self.subprocess.rev_list(range).await,
+                }
+            }
+
+            async fn config_get(&self, key: &str) -> Result<Option<String>> {
+                let repo = self.reopen()?;
+                let value = repo.config_snapshot().string(key);
+                Ok(value.map(|value| value.to_str_lossy().into_owned()))
+            }
+
+            async fn config_get_regexp(&self, pattern: &str) -> Result<Vec<(String, String)>> {
+                let pattern = Regex::new(pattern)
+                    .with_context(|| format!("Invalid config pattern '{}'", pattern))?;
+                let repo = self.reopen()?;
+                let snapshot = repo.config_snapshot();
+
+                let mut entries = Vec::new();
+                for section in snapshot.plumbing().sections() {
+                    let header = section.header();
+                    let mut prefix = header.name().to_str_lossy().to_lowercase();
+                    if let Some(subsection) = header.subsection_name() {
+                        prefix.push('.');
+                        prefix.push_str(&subsection.to_str_lossy());
+                    }
+                    let mut names: Vec<String> = Vec::new();
+                    for name in section.body().value_names() {
+                        let name = name.to_string().to_lowercase();
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                    for name in names {
+                        let key = format!("{}.{}", prefix, name);
+                        if !pattern.is_match(&key) {
+                            continue;
+                        }
+                        for value in section.body().values(&name) {
+                            entries.push((key.clone(), value.to_str_lossy().into_owned()));
+                        }
+                    }
+                }
+                Ok(entries)
+            }
+
+            async fn config_set(&self, key: &str, value: &str) -> Result<()> {
+                let (section, subsection, name) = Self::split_key(key)?;
+                self.edit_local_config(|file| {
+                    file.set_raw_value_by(section, subsection, name.to_string(), value)?;
+                    Ok(())
+                })
+            }
+
+            async fn config_add(&self, key: &str, value: &str) -> Result<()> {
+                let (section, subsection, name) = Self::split_key(key)?;
+                let name = gix::config::parse::section::ValueName::try_from(name.to_string())?;
+                self.edit_local_config(|file| {
+                    file.section_mut_or_create_new(section, subsection)?
+                        .push(name, Some(value.into()));
+                    Ok(())
+                })
+            }
+
+            async fn config_remove_section(&self, section: &str) -> Result<()> {
+                let (name, subsection) = match section.split_once('.') {
+                    Some((name, subsection)) => (name, Some(subsection.into())),
+                    None => (section, None),
+                };
+                self.edit_local_config(|file| {
+                    file.remove_section(name, subsection)
+                        .with_context(|| format!("No such section '{}'", section))?;
+                    Ok(())
+                })
+            }
+
+            async fn config_rename_section(&self, section: &str, new_section: &str) -> Result<()> {
+                // gitoxide renames only the last of several sections with the same header, and
+                // leaves its lookup table pointing at the old name, so let git do it.
+                self.subprocess
+                    .config_rename_section(section, new_section)
+                    .await
+            }
+
+            async fn note_read(&self, ref_name: &str, object: &str) -> Result<Option<String>> {
+                let repo = self.repo();
+                let Ok(object) = Self::resolve(&repo, object) else {
+                    return Ok(None);
+                };
+                let Some((_, tree)) = Self::notes_tree(&repo, ref_name)? else {
+                    return Ok(None);
+                };
+                match Self::find_note(&tree, &object)? {
+                    Some((_, blob)) => {
+                        let blob = repo.find_blob(blob)?;
+                        Ok(Some(blob.data.to_str_lossy().trim().to_string()))
+                    }
+                    None => Ok(None),
+                }
+            }
+
+            async fn note_write(&self, ref_name: &str, object: &str, content: &str) -> Result<()> {
+                let repo = self.repo();
+                let object = Self::resolve(&repo, object)?;
+                let blob = repo.write_blob(format!("{}\n", content))?.detach();
+                Self::edit_notes(
+                    &repo,
+                    ref_name,
+                    "Notes added by 'git notes add'",
+                    None,
+                    |editor, tree| {
+                        if let Some(tree) = tree {
+                            if let Some((path, _)) = Self::find_note(tree, &object)? {
+                                editor.remove(path.as_str())?;
+                            }
+                        }
+                        editor.upsert(object.to_string(), EntryKind::Blob, blob)?;
+                        Ok(true)
+                    },
+                )
+            }
+
+            async fn note_remove(&self, ref_name: &str, object: &str) -> Result<()> {
+                let repo = self.repo();
+                let object = Self::resolve(&repo, object)?;
+                Self::edit_notes(
+                    &repo,
+                    ref_name,
+                    "Notes removed by 'git notes remove'",
+                    None,
+                    |editor, tree| match tree
+                        .map(|tree| Self::find_note(tree, &object))
+                        .transpose()?
+                        .flatten()
+                    {
+                        Some((path, _)) => {
+                            editor.remove(path.as_str())?;
+                            Ok(true)
+                        }
+                        None => Ok(false),
+                    },
+                )
+            }
+
+            async fn note_remove_many(&self, ref_name: &str, objects: &[String]) -> Result<()> {
+                let repo = self.repo();
+                let objects = objects
+                    .iter()
+                    .map(|object| Self::resolve(&repo, object))
+                    .collect::<Result<Vec<_>>>()?;
+                Self::edit_notes(
+                    &repo,
+                    ref_name,
+                    "Notes removed by 'git notes remove'",
+                    None,
+                    |editor, tree| {
+                        let Some(tree) = tree else {
+                            return Ok(false);
+                        };
+                        let mut removed = false;
+                        for object in &objects {
+                            if let Some((path, _)) = Self::find_note(tree, object)? {
+                                editor.remove(path.as_str())?;
+                                removed = true;
+                            }
+                        }
+                        Ok(removed)
+                    },
+                )
+            }
+
+            async fn note_merge(
+                &self,
+                ref_name: &str,
+                merged_commit: &str,
+                notes: &[(String, String)],
+            ) -> Result<()> {
+                let repo = self.repo();
+                let merged = Self::resolve(&repo, merged_commit)?;
+                let mut blobs = Vec::with_capacity(notes.len());
+                for (object, content) in notes {
+                    let object = ObjectId::from_hex(object.as_bytes())?;
+                    let content = format!("{}\n", content.trim_end());
+                    blobs.push((object, repo.write_blob(content)?.detach()));
+                }
+                Self::edit_notes(
+                    &repo,
+                    ref_name,
+                    &format!("Notes merged from {} by 'git test'", merged_commit),
+                    Some(merged),
+                    |editor, tree| {
+                        for (object, blob) in &blobs {
+                            if let Some(tree) = tree {
+                                if let Some((path, _)) = Self::find_note(tree, object)? {
+                                    editor.remove(path.as_str())?;
+                                }
+                            }
+                            editor.upsert(object.to_string(), EntryKind::Blob, *blob)?;
+                        }
+                        Ok(true)
+                    },
+                )
+            }
+
+            async fn note_list(&self, ref_name: &str) -> Result<Vec<(String, String)>> {
+                let repo = self.repo();
+                let Some((_, tree)) = Self::notes_tree(&repo, ref_name)? else {
+                    return Ok(Vec::new());
+                };
+                let mut recorder = gix::traverse::tree::Recorder::default();
+                tree.traverse().breadthfirst(&mut recorder)?;
+                let mut notes: Vec<(String, String)> = recorder
+                    .records
+                    .into_iter()
+                    .filter(|entry| entry.mode.is_blob())
+                    .map(|entry| {
+                        let object = entry.filepath.to_str_lossy().replace('/', "");
+                        (entry.oid.to_string(), object)
+                    })
+                    .collect();
+                notes.sort_by(|a, b| a.1.cmp(&b.1));
+                Ok(notes)
+            }
+
+            async fn read_blob(&self, blob: &str) -> Result<String> {
+                let repo = self.repo();
+                let blob = repo.find_blob(ObjectId::from_hex(blob.as_bytes())?)?;
+                Ok(blob.data.to_str_lossy().trim().to_string())
+            }
+
+            async fn read_blobs(&self, blobs: &[String]) -> Result<Vec<String>> {
+                let repo = self.repo();
+                blobs
+                    .iter()
+                    .map(|blob| {
+                        let blob = repo.find_blob(ObjectId::from_hex(blob.as_bytes())?)?;
+                        Ok(String::from_utf8(blob.data.clone())?)
+                    })
+                    .collect()
+            }
+
+            async fn blob_sizes(&self, blobs: &[String]) -> Result<Vec<u64>> {
+                let repo = self.repo();
+                blobs
+                    .iter()
+                    .map(|blob| {
+                        let header = repo.find_header(ObjectId::from_hex(blob.as_bytes())?)?;
+                        anyhow::ensure!(
+                            header.kind() == gix::object::Kind::Blob,
+                            "Failed to read blob {}",
+                            blob
+                        );
+                        Ok(header.size())
+                    })
+                    .collect()
+            }
+
+            async fn ref_exists(&self, ref_name: &str) -> Result<bool> {
+                Ok(self.repo().try_find_reference(ref_name)?.is_some())
+            }
+
+            async fn delete_ref(&self, ref_name: &str) -> Result<()> {
+                if let Some(reference) = self.repo().try_find_reference(ref_name)? {
+                    reference.delete()?;
+                }
+                Ok(())
+            }
+
+            async fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+                let repo = self.repo();
+                let ancestor = Self::resolve(&repo, ancestor)?;
+                let descendant = Self::resolve(&repo, descendant)?;
+                match repo.merge_base(ancestor, descendant) {
+                    Ok(base) => Ok(base == ancestor),
+                    Err(gix::repository::merge_base::Error::NotFound { .. }) => Ok(false),
+                    Err(err) => Err(err.into()),
+                }
+            }
+
+            async fn worktree_add(&self, path: &Path, commit: &str) -> Result<()> {
+                self.subprocess.worktree_add(path, commit).await
+            }
+
+            async fn worktree_checkout(&self, path: &Path, commit: &str) -> Result<()> {
+                self.subprocess.worktree_checkout(path, commit).await
+            }
+
+            async fn worktree_remove(&self, path: &Path) -> Result<()> {
+                self.subprocess.worktree_remove(path).await
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct GitRepository {
+        root: PathBuf,
+        backend: Arc<dyn GitBackend>,
+    }
+
+    #[derive(Clone)]
+    pub struct GitTestCommand {
+        pub repo: GitRepository,
+        pub test_name: String,
+        pub test_command: String,
+        /// Subdirectory whose tree the results are keyed on (`test.<name>.tree`). When unset,
+        /// results are keyed on the commit's whole tree.
+        pub tree: Option<String>,
+        /// How long the test may run before it is killed and counted as failed.
+        pub timeout: Option<Duration>,
+        /// Extra environment variables the test runs with.
+        pub env: Vec<(String, String)>,
+        pub tags: Vec<String>,
+        pub source: TestSource,
+        /// For a variant of a matrix test, the matrix test's name.
+        pub variant_of: Option<String>,
+        /// For a variant of a matrix test, the value of each parameter, available to the command
+        /// as `{<name>}` and `GIT_TEST_PARAM_<NAME>`.
+        pub params: Vec<(String, String)>,
+    }
+
+    /// Where a test's definition came from.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum TestSource {
+        /// Local git config (`test.<name>.command`).
+        Config,
+        /// The repository's `.gittest.toml`.
+        File,
+        /// `.gittest.toml`, with its command or tree overridden by local git config.
+        ConfigOverFile,
+    }
+
+    impl TestSource {
+        pub fn describe(&self) -> &'static str {
+            match self {
+                TestSource::Config => "git config",
+                TestSource::File => ".gittest.toml",
+                TestSource::ConfigOverFile => ".gittest.toml, overridden by git config",
+            }
+        }
+    }
+
+    pub const TESTS_NOTES_PREFIX: &str = "refs/notes/tests/";
+
+    pub fn test_notes_ref(test_name: &str) -> String {
+        format!("{}{}", TESTS_NOTES_PREFIX, test_name)
+    }
+
+    /// The outcome of a test as recorded in the notes for a tree.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TestStatus {
+        Good,
+        Bad,
+        /// Different runs of the test against the same tree disagreed.
+        Flaky,
+    }
+
+    impl TestStatus {
+        pub fn from_success(success: bool) -> Self {
+            if success {
+                TestStatus::Good
+            } else {
+                TestStatus::Bad
+            }
+        }
+
+        pub fn as_symbol(&self) -> &'static str {
+            match self {
+                TestStatus::Good => "✓",
+                TestStatus::Bad => "✗",
+                TestStatus::Flaky => "~",
+            }
+        }
+
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                TestStatus::Good => "good",
+                TestStatus::Bad => "bad",
+                TestStatus::Flaky => "flaky",
+            }
+        }
+
+        pub fn parse_note(note: &str) -> Option<Self> {
+            match note.trim() {
+                "✓" | "good" => Some(TestStatus::Good),
+                "✗" | "bad" => Some(TestStatus::Bad),
+                "~" | "flaky" => Some(TestStatus::Flaky),
+                _ => None,
+            }
+        }
+    }
+
+    /// A test result as stored in a note under `refs/notes/tests/<name>`, serialized as JSON.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct TestNote {
+        pub status: TestStatus,
+        /// When the result was recorded, in seconds since the Unix epoch.
+        pub timestamp: u64,
+        /// The end of the output of a failing run, kept so that reports can show why it failed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub stdout: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub stderr: Option<String>,
+        /// Files on the machine that ran the test holding its complete output, when it was
+        /// spilled.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub stdout_file: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub stderr_file: Option<String>,
+        /// The commit the test ran on. Other commits with the same tree share its result.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub commit: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub exit_code: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub duration_ms: Option<u64>,
+        /// The machine the test ran on.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub host: Option<String>,
+        /// `GitTestCommand::fingerprint` of the definition the test ran with.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub fingerprint: Option<String>,
+    }
+
+    impl TestNote {
+        pub fn new(status: TestStatus) -> Self {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            TestNote {
+                status,
+                timestamp,
+                stdout: None,
+                stderr: None,
+                stdout_file: None,
+                stderr_file: None,
+                commit: None,
+                exit_code: None,
+                duration_ms: None,
+                host: None,
+                fingerprint: None,
+            }
+        }
+
+        /// Parses a stored note. Notes written before results were structured contain only a
+        /// status symbol; they are treated as infinitely old.
+        pub fn parse(note: &str) -> Option<Self> {
+            serde_json::from_str(note).ok().or_else(|| {
+                TestStatus::parse_note(note).map(|status| TestNote {
+                    timestamp: 0,
+                    ..TestNote::new(status)
+                })
+            })
+        }
+
+        pub fn to_note_string(&self) -> Result<String> {
+            serde_json::to_string(self).context("Failed to serialize test result")
+        }
+
+        /// Combines two results recorded for the same tree, e.g. on different machines. The
+        /// outcome doesn't depend on which side is local. In order of precedence:
+        ///
+        /// 1. if either side is flaky, the result is flaky;
+        /// 2. if one is good and the other bad, the result is flaky as well, rather than bad:
+        ///    the tree passed at least once. Like bad, flaky counts as a failure, and
+        ///    `--retest` tests the tree again;
+        /// 3. otherwise both agree, and the newer timestamp wins.
+        ///
+        /// The merged note keeps the output and other details of the newer side.
+        pub fn merge(&self, other: &TestNote) -> TestNote {
+            let newer = if (other.timestamp, other.status.as_str())
+                > (self.timestamp, self.status.as_str())
+            {
+                other
+            } else {
+                self
+            };
+
+            if self.status == other.status {
+                newer.clone()
+            } else {
+                TestNote {
+                    status: TestStatus::Flaky,
+                    ..newer.clone()
+                }
+            }
+        }
+    }
+
+    /// A line of `git log --graph` output: the graph drawing, followed by a commit unless the
+    /// line only connects others.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct GraphLine {
+        pub graph: String,
+        pub commit: Option<GitSha>,
+        pub subject: String,
+    }
+
+    /// What `GitRepository::merge_result_notes` did.
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct NotesMergeSummary {
+        /// Results that only existed on the incoming side.
+        pub added: usize,
+        /// Results present on both sides whose stored note changed as a result of merging.
+        pub merged: usize,
+        /// Results that were already identical, or whose local note won outright.
+        pub unchanged: usize,
+    }
+
+    impl GitTestCommand {
+        pub fn notes_ref(&self) -> String {
+            test_notes_ref(&self.test_name)
+        }
+
+        /// A short hash of what the test runs: its command, environment and parameters. It is
+        /// stored with each result, so that results from an older definition can be recognized.
+        pub fn fingerprint(&self) -> String {
+            // 64-bit FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+            let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+            let mut feed = |bytes: &[u8]| {
+                for byte in bytes.iter().chain([&0]) {
+                    hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+                }
+            };
+            feed(self.test_command.as_bytes());
+            for (key, value) in self.env.iter().chain(&self.params) {
+                feed(key.as_bytes());
+                feed(value.as_bytes());
+            }
+            format!("{:016x}", hash)
+        }
+
+        /// The object that results for `commit` are attached to: either `<commit>^{tree}` or,
+        /// for a path-scoped test, `<commit>:<subdir>`, so that results carry across commits
+        /// in which that tree is unchanged. Commits that don't have the subdirectory fall back
+        /// to their whole tree.
+        pub async fn result_object(&self, commit: &str) -> Result<String> {
+            if let Some(subdir) = &self.tree {
+                let object = format!("{}:{}", commit, subdir.trim_matches('/'));
+                if self.repo.backend().rev_parse(&object).await?.is_some() {
+                    return Ok(object);
+                }
+            }
+            Ok(format!("{}^{{tree}}", commit))
+        }
+
+        pub async fn get_result(&self, commit: &str) -> Result<Option<TestStatus>> {
+            Ok(self.get_result_note(commit).await?.map(|note| note.status))
+        }
+
+        pub async fn get_result_note(&self, commit: &str) -> Result<Option<TestNote>> {
+            let note = self
+                .repo
+                .get_note(&self.notes_ref(), &self.result_object(commit).await?)
+                .await?;
+            Ok(note.as_deref().and_then(TestNote::parse))
+        }
+
+        pub async fn set_result(&self, commit: &str, status: TestStatus) -> Result<()> {
+            self.set_result_note(commit, &TestNote::new(status)).await
+        }
+
+        pub async fn set_result_note(&self, commit: &str, note: &TestNote) -> Result<()> {
+            self.repo
+                .add_note(
+                    &self.notes_ref(),
+                    &self.result_object(commit).await?,
+                    &note.to_note_string()?,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to record result of '{}' for {}",
+                        self.test_name, commit
+                    )
+                })
+        }
+
+        pub async fn forget_result(&self, commit: &str) -> Result<()> {
+            self.repo
+                .remove_note(&self.notes_ref(), &self.result_object(commit).await?)
+                .await
+        }
+    }
+
+    impl GitRepository {
+        pub fn new(root: PathBuf) -> Self {
+            let backend = default_backend(&root);
+            GitRepository { root, backend }
+        }
+
+        pub fn with_backend(root: PathBuf, backend: Arc<dyn GitBackend>) -> Self {
+            GitRepository { root, backend }
+        }
+
+        pub fn backend(&self) -> &dyn GitBackend {
+            self.backend.as_ref()
+        }
+
+        pub fn test_command(&self, test_name: String, test_command: String) -> GitTestCommand {
+            GitTestCommand {
+                repo: self.clone(),
+                test_name,
+                test_command,
+                tree: None,
+                timeout: None,
+                env: Vec::new(),
+                tags: Vec::new(),
+                source: TestSource::Config,
+                variant_of: None,
+                params: Vec::new(),
+            }
+        }
+
+        pub async fn get_repo_root(dir: &Path) -> Result<Self> {
+            let root = run_git_with_string(dir, &["rev-parse", "--show-toplevel"])
+                .await
+                .context("Failed to execute git rev-parse --show-toplevel")?;
+
+            let root = PathBuf::from(root.trim());
+            Ok(GitRepository::new(root))
+        }
+
+        pub fn root(&self) -> &Path {
+            &self.root
+        }
+
+        pub async fn run_git(&self, args: &[&str]) -> Result<String> {
+            run_git_with_string(self.root(), args).await
+        }
+
+        pub async fn run_git_with_input(&self, args: &[&str], input: &str) -> Result<String> {
+            run_git_with_input(self.root(), args, input).await
+        }
+
+        pub async fn get_config_value(&self, key: &str) -> Result<String> {
+            self.get_optional_config_value(key)
+                .await?
+                .context("Failed to get git config value")
+        }
+
+        /// Like `get_config_value`, but returns `None` rather than an error if the key is unset.
+        pub async fn get_optional_config_value(&self, key: &str) -> Result<Option<String>> {
+            self.backend.config_get(key).await
+        }
+
+        /// All `(key, value)` pairs whose key matches `pattern`, in config file order.
+        pub async fn get_config_regexp(&self, pattern: &str) -> Result<Vec<(String, String)>> {
+            self.backend.config_get_regexp(pattern).await
+        }
+
+        /// Adds a value for `key`, keeping any it already has.
+        pub async fn add_config_value(&self, key: &str, value: &str) -> Result<()> {
+            self.backend
+                .config_add(key, value)
+                .await
+                .with_context(|| format!("Failed to add git config value for key '{}'", key))
+        }
+
+        pub async fn set_config_value(&self, key: &str, value: &str) -> Result<()> {
+            self.backend
+                .config_set(key, value)
+                .await
+                .with_context(|| format!("Failed to set git config value for key '{}'", key))
+        }
+
+        pub async fn get_test_command(&self, test_name: &str) -> Result<GitTestCommand> {
+            self.list_tests()
+                .await?
+                .into_iter()
+                .find(|t| t.test_name == test_name)
+                .with_context(|| format!("Test '{}' is not defined", test_name))
+        }
+
+        /// The test called `name` or, if `name` is a matrix test, all of its variants.
+        pub async fn select_tests(&self, name: &str) -> Result<Vec<GitTestCommand>> {
+            let selected: Vec<GitTestCommand> = self
+                .list_tests()
+                .await?
+                .into_iter()
+                .filter(|t| t.test_name == name || t.variant_of.as_deref() == Some(name))
+                .collect();
+            if selected.is_empty() {
+                anyhow::bail!("Test '{}' is not defined", name);
+            }
+            Ok(selected)
+        }
+
+        pub async fn set_test_command(&self, test: &str, command: &str) -> Result<()> {
+            self.set_config_value(&format!("test.{}.command", test), command)
+                .await
+        }
+
+        pub async fn rename_test(&self, test: &str, new_test: &str) -> Result<()> {
+            self.backend
+                .config_rename_section(&format!("test.{}", test), &format!("test.{}", new_test))
+                .await
+                .with_context(|| format!("Failed to rename the definition of test '{}'", test))
+        }
+
+        pub async fn remove_test(&self, test: &str) -> Result<()> {
+            self.backend
+                .config_remove_section(&format!("test.{}", test))
+                .await
+                .with_context(|| format!("Failed to remove the definition of test '{}'", test))
+        }
+
+        /// The test definitions in the `.gittest.toml` committed in `rev`, if there is one.
+        pub async fn definitions_at(&self, rev: &str) -> Result<Vec<FileDefinition>> {
+            let spec = format!("{}:{}", rev, DEFINITIONS_FILE);
+            let Some(blob) = self.backend.rev_parse(&spec).await? else {
+                return Ok(Vec::new());
+            };
+            let contents = self
+                .backend
+                .read_blobs(&[blob])
+                .await
+                .with_context(|| format!("Failed to read {}", spec))?;
+            parse_definitions(&contents[0]).with_context(|| format!("Invalid {}", spec))
+        }
+
+        /// Every test defined as of `HEAD`; see `list_tests_at`.
+        pub async fn list_tests(&self) -> Result<Vec<GitTestCommand>> {
+            self.list_tests_at("HEAD").await
+        }
+
+        /// Every test defined as of `rev`: those in its committed `.gittest.toml`, in file
+        /// order, then those only in local git config. A command, tree, matrix or tags set in
+        /// git config override the file's. A matrix test (`test.<name>.matrix`) is listed as
+        /// its variants, one per combination of parameter values.
+        pub async fn list_tests_at(&self, rev: &str) -> Result<Vec<GitTestCommand>> {
+            let mut matrices: HashMap<String, Vec<MatrixAxis>> = HashMap::new();
+            let mut tests: Vec<GitTestCommand> = Vec::new();
+            for definition in self.definitions_at(rev).await? {
+                matrices.insert(definition.name.clone(), definition.matrix);
+                tests.push(GitTestCommand {
+                    tree: definition.tree,
+                    timeout: definition.timeout,
+                    env: definition.env,
+                    tags: definition.tags,
+                    source: TestSource::File,
+                    ..self.test_command(definition.name, definition.command)
+                });
+            }
+
+            let entries = self
+                .get_config_regexp(r"^test\..*\.(command|tree|matrix|tags)$")
+                .await?;
+
+            let test_config_re =
+                Regex::new(r"^test\.(?P<name>.*)\.(?P<key>command|tree|matrix|tags)$")
+                    .context("Failed to compile test config regex")?;
+
+            let mut config_matrices: HashMap<String, Vec<MatrixAxis>> = HashMap::new();
+            let mut config_tags: HashMap<String, Vec<String>> = HashMap::new();
+            let mut trees: Vec<(String, String)> = Vec::new();
+            for (key, value) in entries {
+                let captures = test_config_re
+                    .captures(&key)
+                    .context("Failed to parse git config output")?;
+                let name = captures["name"].to_string();
+                match &captures["key"] {
+                    "command" => match tests.iter_mut().find(|t| t.test_name == name) {
+                        Some(git_test_command) => {
+                            git_test_command.test_command = value;
+                            git_test_command.source = TestSource::ConfigOverFile;
+                        }
+                        None => tests.push(self.test_command(name, value)),
+                    },
+                    "matrix" => {
+                        let axis = parse_matrix_axis(&value)
+                            .with_context(|| format!("Invalid test.{}.matrix", name))?;
+                        config_matrices.entry(name).or_default().push(axis);
+                    }
+                    "tags" => config_tags.entry(name).or_default().extend(
+                        value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty())
+                            .map(str::to_string),
+                    ),
+                    _ => trees.push((name, value)),
+                }
+            }
+
+            for (name, tree) in trees {
+                if let Some(git_test_command) = tests.iter_mut().find(|t| t.test_name == name) {
+                    git_test_command.tree = Some(tree);
+                    if git_test_command.source == TestSource::File {
+                        git_test_command.source = TestSource::ConfigOverFile;
+                    }
+                }
+            }
+
+            for (name, tags) in config_tags {
+                if let Some(git_test_command) = tests.iter_mut().find(|t| t.test_name == name) {
+                    git_test_command.tags = tags;
+                    if git_test_command.source == TestSource::File {
+                        git_test_command.source = TestSource::ConfigOverFile;
+                    }
+                }
+            }
+
+            for (name, axes) in config_matrices {
+                if let Some(git_test_command) = tests.iter_mut().find(|t| t.test_name == name) {
+                    if git_test_command.source == TestSource::File {
+                        git_test_command.source = TestSource::ConfigOverFile;
+                    }
+                }
+                matrices.insert(name, axes);
+            }
+
+            Ok(tests
+                .into_iter()
+                .flat_map(
+                    |git_test_command| match matrices.get(&git_test_command.test_name) {
+                        Some(axes) if !axes.is_empty() => matrix_combinations(axes)
+                            .into_iter()
+                            .map(|params| GitTestCommand {
+                                test_name: variant_name(&git_test_command.test_name, &params),
+                                variant_of: Some(git_test_command.test_name.clone()),
+                                params,
+                                ..git_test_command.clone()
+                            })
+                            .collect(),
+                        _ => vec![git_test_command],
+                    },
+                )
+                .collect())
+        }
+
+        /// Resolves a path inside the repository's git directory, such as `hooks`, honoring
+        /// settings like `core.hooksPath`.
+        pub async fn git_path(&self, name: &str) -> Result<PathBuf> {
+            let path = self
+                .run_git(&["rev-parse", "--git-path", name])
+                .await
+                .with_context(|| format!("Failed to locate '{}' in the git directory", name))?;
+            Ok(self.root().join(path))
+        }
+
+        pub async fn get_head_commit(&self) -> Result<String> {
+            self.backend
+                .rev_parse("HEAD")
+                .await?
+                .context("Failed to get HEAD commit")
+        }
+
+        /// Expands commit arguments into commit SHAs, oldest first. Arguments containing `..`
+        /// are treated as ranges; anything else names a single commit.
+        pub async fn resolve_commits(&self, specs: &[String]) -> Result<Vec<GitSha>> {
+            let mut shas = Vec::new();
+            for spec in specs {
+                if spec.contains("..") {
+                    let commits = self
+                        .backend
+                        .rev_list(spec)
+                        .await
+                        .with_context(|| format!("Failed to list commits in '{}'", spec))?;
+                    shas.extend(commits.into_iter().map(GitSha::new));
+                } else {
+                    let sha = self
+                        .backend
+                        .rev_parse(&format!("{}^{{commit}}", spec))
+                        .await?
+                        .with_context(|| format!("Failed to resolve commit '{}'", spec))?;
+                    shas.push(GitSha::new(sha));
+                }
+            }
+            Ok(shas)
+        }
+
+        /// The subject line of each of `shas`, in the same order.
+        pub async fn commit_subjects(&self, shas: &[GitSha]) -> Result<Vec<String>> {
+            if shas.is_empty() {
+                return Ok(Vec::new());
+            }
+            let input: String = shas
+                .iter()
+                .map(|sha| format!("{}\n", sha.as_str()))
+                .collect();
+            let output = self
+                .run_git_with_input(
+                    &["log", "--no-walk=unsorted", "--stdin", "--format=%H%x00%s"],
+                    &input,
+                )
+                .await
+                .context("Failed to read commit subjects")?;
+            let subjects: HashMap<&str, &str> = output
+                .lines()
+                .filter_map(|line| line.split_once('\0'))
+                .collect();
+            shas.iter()
+                .map(|sha| {
+                    subjects
+                        .get(sha.as_str())
+                        .map(|subject| subject.to_string())
+                        .with_context(|| format!("Failed to read the subject of {}", sha.as_str()))
+                })
+                .collect()
+        }
+
+        /// The output of `git log --graph` for `revision`, showing at most `max_count` commits.
+        pub async fn log_graph(
+            &self,
+            revision: &str,
+            max_count: Option<usize>,
+        ) -> Result<Vec<GraphLine>> {
+            let max_count = max_count.map(|count| format!("--max-count={}", count));
+            let mut args = vec!["log", "--graph", "--color=never", "--format=%x1f%H%x1f%s"];
+            args.extend(max_count.as_deref());
+            args.extend([revision, "--"]);
+            let output = self
+                .run_git(&args)
+                .await
+                .with_context(|| format!("Failed to read the history of '{}'", revision))?;
+
+            Ok(output
+                .lines()
+                .map(|line| {
+                    let mut fields = line.splitn(3, '\x1f');
+                    let graph = fields.next().unwrap_or_default().to_string();
+                    match (fields.next(), fields.next()) {
+                        (Some(sha), Some(subject)) => GraphLine {
+                            graph,
+                            commit: Some(GitSha::new(sha.to_string())),
+                            subject: subject.to_string(),
+                        },
+                        _ => GraphLine {
+                            graph,
+                            commit: None,
+                            subject: String::new(),
+                        },
+                    }
+                })
+                .collect())
+        }
+
+        pub async fn add_note(&self, ref_name: &str, object: &str, content: &str) -> Result<()> {
+            self.backend.note_write(ref_name, object, content).await
+        }
+
+        /// Returns the note attached to `object`, or `None` if there isn't one.
+        pub async fn get_note(&self, ref_name: &str, object: &str) -> Result<Option<String>> {
+            self.backend.note_read(ref_name, object).await
+        }
+
+        pub async fn remove_note(&self, ref_name: &str, object: &str) -> Result<()> {
+            self.backend.note_remove(ref_name, object).await
+        }
+
+        /// Removes the notes attached to `objects` in `ref_name` in a single notes commit.
+        pub async fn remove_notes(&self, ref_name: &str, objects: &[String]) -> Result<()> {
+            self.backend
+                .note_remove_many(ref_name, objects)
+                .await
+                .with_context(|| format!("Failed to remove notes from '{}'", ref_name))
+        }
+
+        pub async fn ref_exists(&self, ref_name: &str) -> Result<bool> {
+            self.backend.ref_exists(ref_name).await
+        }
+
+        /// Deletes `ref_name` outright, if it exists.
+        pub async fn delete_ref(&self, ref_name: &str) -> Result<()> {
+            self.backend.delete_ref(ref_name).await
+        }
+
+        pub async fn update_ref(&self, ref_name: &str, target: &str) -> Result<()> {
+            self.run_git(&["update-ref", ref_name, target]).await?;
+            Ok(())
+        }
+
+        /// Moves each `(old, new)` pair of refs in a single transaction: either all of them
+        /// are moved, or none is.
+        pub async fn move_refs(&self, refs: &[(String, String)]) -> Result<()> {
+            let mut input = String::from("start\n");
+            for (old_ref, new_ref) in refs {
+                let target = self
+                    .backend
+                    .rev_parse(old_ref)
+                    .await?
+                    .with_context(|| format!("Failed to resolve '{}'", old_ref))?;
+                input.push_str(&format!("create {} {}\n", new_ref, target));
+                input.push_str(&format!("delete {} {}\n", old_ref, target));
+            }
+            input.push_str("commit\n");
+            self.run_git_with_input(&["update-ref", "--stdin"], &input)
+                .await
+                .context("Failed to move refs")?;
+            Ok(())
+        }
+
+        /// Like `update_ref`, but fails if `ref_name` already exists.
+        pub async fn create_ref(&self, ref_name: &str, target: &str) -> Result<()> {
+            self.run_git(&["update-ref", ref_name, target, ""])
+                .await
+                .with_context(|| format!("Failed to create '{}'", ref_name))?;
+            Ok(())
+        }
+
+        /// Every commit, tree and subtree reachable from a ref other than a notes ref.
+        pub async fn reachable_objects(&self) -> Result<HashSet<String>> {
+            let output = self
+                .run_git(&[
+                    "rev-list",
+                    "--objects",
+                    "--filter=blob:none",
+                    "--exclude=refs/notes/*",
+                    "--all",
+                ])
+                .await
+                .context("Failed to list reachable objects")?;
+            Ok(output
+                .lines()
+                .filter_map(|line| line.split(' ').next())
+                .map(str::to_string)
+                .collect())
+        }
+
+        /// Names of all refs under `prefix`, e.g. `refs/notes/tests/`.
+        pub async fn list_refs(&self, prefix: &str) -> Result<Vec<String>> {
+            let output = self
+                .run_git(&["for-each-ref", "--format=%(refname)", prefix])
+                .await
+                .with_context(|| format!("Failed to list refs under '{}'", prefix))?;
+            Ok(output.lines().map(str::to_string).collect())
+        }
+
+        pub async fn push(&self, remote: &str, refspec: &str) -> Result<()> {
+            self.run_git(&["push", remote, refspec])
+                .await
+                .with_context(|| format!("Failed to push '{}' to '{}'", refspec, remote))?;
+            Ok(())
+        }
+
+        pub async fn fetch(&self, remote: &str, refspec: &str) -> Result<()> {
+            self.run_git(&["fetch", remote, refspec])
+                .await
+                .with_context(|| format!("Failed to fetch '{}' from '{}'", refspec, remote))?;
+            Ok(())
+        }
+
+        /// Every `(note blob, annotated object)` pair in the notes ref `ref_name`.
+        pub async fn list_notes(&self, ref_name: &str) -> Result<Vec<(String, String)>> {
+            self.backend
+                .note_list(ref_name)
+                .await
+                .with_context(|| format!("Failed to list notes in '{}'", ref_name))
+        }
+
+        pub async fn read_blob(&self, blob: &str) -> Result<String> {
+            self.backend
+                .read_blob(blob)
+                .await
+                .with_context(|| format!("Failed to read blob {}", blob))
+        }
+
+        /// The size in bytes of each blob, in the order given.
+        pub async fn blob_sizes(&self, blobs: &[String]) -> Result<Vec<u64>> {
+            self.backend
+                .blob_sizes(blobs)
+                .await
+                .context("Failed to read blob sizes")
+        }
+
+        /// Reads many blobs at once, in the order given.
+        pub async fn read_blobs(&self, blobs: &[String]) -> Result<Vec<String>> {
+            self.backend
+                .read_blobs(blobs)
+                .await
+                .context("Failed to read blobs")
+        }
+
+        /// Merges the test results in the notes ref `incoming_ref` into `ref_name`. Unlike
+        /// `git notes merge`, this never conflicts: results recorded for the same tree on both
+        /// sides are combined with `TestNote::merge`. Notes that can't be parsed as results
+        /// are left alone on the local side and ignored on the incoming side.
+        ///
+        /// The merge is a single notes commit whose second parent is `incoming_ref`, so that
+        /// `ref_name` can then be pushed back to where the incoming notes came from. If
+        /// `ref_name` already contains `incoming_ref`, there is nothing to merge.
+        pub async fn merge_result_notes(
+            &self,
+            ref_name: &str,
+            incoming_ref: &str,
+        ) -> Result<NotesMergeSummary> {
+            let incoming_tip = self
+                .backend
+                .rev_parse(incoming_ref)
+                .await?
+                .with_context(|| format!("Failed to resolve '{}'", incoming_ref))?;
+            let incoming = self.list_notes(incoming_ref).await?;
+            let mut summary = NotesMergeSummary::default();
+            if let Some(local_tip) = self.backend.rev_parse(ref_name).await? {
+                if self.backend.is_ancestor(&incoming_tip, &local_tip).await? {
+                    summary.unchanged = incoming.len();
+                    return Ok(summary);
+                }
+            }
+
+            let local: HashMap<String, String> = self
+                .list_notes(ref_name)
+                .await?
+                .into_iter()
+                .map(|(blob, object)| (object, blob))
+                .collect();
+            // Both sides' notes for the same tree are only needed where they differ.
+            let mut blobs = Vec::new();
+            for (incoming_blob, object) in &incoming {
+                blobs.push(incoming_blob.clone());
+                if let Some(local_blob) = local.get(object) {
+                    if local_blob != incoming_blob {
+                        blobs.push(local_blob.clone());
+                    }
+                }
+            }
+            let contents: HashMap<String, String> = blobs
+                .iter()
+                .cloned()
+                .zip(self.read_blobs(&blobs).await?)
+                .collect();
+
+            let mut merged_notes = Vec::new();
+            for (incoming_blob, object) in incoming {
+                let Some(incoming) = TestNote::parse(&contents[&incoming_blob]) else {
+                    continue;
+                };
+
+                let merged = match local.get(&object) {
+                    None => {
+                        summary.added += 1;
+                        incoming
+                    }
+                    Some(local_blob) if *local_blob == incoming_blob => {
+                        summary.unchanged += 1;
+                        continue;
+                    }
+                    Some(local_blob) => {
+                        let Some(local) = TestNote::parse(&contents[local_blob]) else {
+                            summary.unchanged += 1;
+                            continue;
+                        };
+                        let merged = local.merge(&incoming);
+                        if merged == local {
+                            summary.unchanged += 1;
+                            continue;
+                        }
+                        summary.merged += 1;
+                        merged
+                    }
+                };
+                merged_notes.push((object, merged.to_note_string()?));
+            }
+
+            self.backend
+                .note_merge(ref_name, &incoming_tip, &merged_notes)
+                .await
+                .with_context(|| {
+                    format!("Failed to merge '{}' into '{}'", incoming_ref, ref_name)
+                })?;
+            Ok(summary)
+        }
+    }
+
+    pub async fn get_repo_root(dir: &Path) -> Result<GitRepository> {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C")
+            .arg(dir)
+            .args(["rev-parse", "--show-toplevel"]);
+
+        let output = log_and_run_command(&mut cmd)
+            .await
+            .context("Failed to execute git rev-parse --show-toplevel")?;
+
+        if output.status.success() {
+            let root = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+            Ok(GitRepository::new(root))
+        } else {
+            Err(anyhow::anyhow!("Not in a git repository"))
+        }
+    }
+
+    // Enumeration for worktree configuration
+    #[derive(Debug, Clone)]
+    pub enum WorktreeConfig {
+        Main(GitRepository),
+        Linked {
+            repo: GitRepository,
+            path: PathBuf,
+        },
+        /// One long-lived worktree per test, switched from commit to commit rather than
+        /// recreated, so that build artifacts stay warm between runs.
+        Persistent {
+            repo: GitRepository,
+            path: PathBuf,
+        },
+    }
+
+    // Enumeration for actual worktree
+    #[derive(Debug, Clone)]
+    pub enum Worktree {
+        Main(GitRepository),
+        Linked {
+            repo: GitRepository,
+            path_prefix: PathBuf,
+            sha: GitSha,
+            test_name: String,
+        },
+        Persistent {
+            repo: GitRepository,
+            path_prefix: PathBuf,
+            sha: GitSha,
+            test_name: String,
+        },
+    }
+
+    impl WorktreeConfig {
+        pub fn to_worktree(&self, sha: GitSha, test_name: &str) -> Worktree {
+            match self {
+                WorktreeConfig::Main(repo) => Worktree::Main(repo.clone()),
+                WorktreeConfig::Linked { repo, path } => Worktree::Linked {
+                    repo: repo.clone(),
+                    path_prefix: path.clone(),
+                    sha,
+                    test_name: test_name.to_string(),
+                },
+                WorktreeConfig::Persistent { repo, path } => Worktree::Persistent {
+                    repo: repo.clone(),
+                    path_prefix: path.clone(),
+                    sha,
+                    test_name: test_name.to_string(),
+                },
+            }
+        }
+    }
+
+    impl Worktree {
+        pub async fn create(&self) -> Result<()> {
+            if let Worktree::Linked { repo, sha, .. } = self {
+                repo.backend()
+                    .worktree_add(&self.get_path(), sha.as_str())
+                    .await?;
+            }
+            if let Worktree::Persistent { repo, sha, .. } = self {
+                let worktree_path = self.get_path();
+                if worktree_path.join(".git").exists() {
+                    repo.backend()
+                        .worktree_checkout(&worktree_path, sha.as_str())
+                        .await?;
+                } else {
+                    repo.backend()
+                        .worktree_add(&worktree_path, sha.as_str())
+                        .await?;
+                }
+            }
+            Ok(())
+        }
+
+        pub async fn delete(&self) -> Result<()> {
+            if let Worktree::Linked { repo, .. } = self {
+                repo.backend().worktree_remove(&self.get_path()).await?;
+            }
+            Ok(())
+        }
+
+        pub fn get_path(&self) -> PathBuf {
+            match self {
+                Worktree::Main(repo) => repo.root().to_path_buf(),
+                Worktree::Linked {
+                    path_prefix,
+                    sha,
+                    test_name,
+                    ..
+                } => path_prefix.join(format!("{}/{}", sha.as_str(), test_name)),
+                Worktree::Persistent {
+                    path_prefix,
+                    test_name,
+                    ..
+                } => path_prefix.join(test_name),
+            }
+        }
+    }
+
+    // Extension trait for GitRepository to support worktree operations
+    pub trait GitRepositoryWorktreeExt {
+        fn to_worktree_config(&self) -> WorktreeConfig;
+        fn to_linked_worktree_config(&self, path: &Path) -> WorktreeConfig;
+        fn to_persistent_worktree_config(&self, path: &Path) -> WorktreeConfig;
+    }
+
+    impl GitRepositoryWorktreeExt for GitRepository {
+        fn to_worktree_config(&self) -> WorktreeConfig {
+            WorktreeConfig::Main(self.clone())
+        }
+
+        fn to_linked_worktree_config(&self, path: &Path) -> WorktreeConfig {
+            let absolute_path = if path.is_absolute() {
+                path.to_path_buf()
+            } else {
+                self.root().join(path)
+            };
+            WorktreeConfig::Linked {
+                repo: self.clone(),
+                path: absolute_path,
+            }
+        }
+
+        fn to_persistent_worktree_config(&self, path: &Path) -> WorktreeConfig {
+            let absolute_path = if path.is_absolute() {
+                path.to_path_buf()
+            } else {
+                self.root().join(path)
+            };
+            WorktreeConfig::Persistent {
+                repo: self.clone(),
+                path: absolute_path,
+            }
+        }
+    }
+}
+
+pub mod output {
+    use anyhow::Result;
+    use clap::ValueEnum;
+    use serde::Serialize;
+
+    /// How commands present their results.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+    pub enum OutputFormat {
+        /// Human-readable log lines
+        #[default]
+        Human,
+        /// A single JSON document
+        Json,
+        /// One JSON object per line
+        Ndjson,
+        /// A TAP version 13 stream (run and results only)
+        Tap,
+    }
+
+    impl OutputFormat {
+        pub fn is_human(&self) -> bool {
+            *self == OutputFormat::Human
+        }
+    }
+
+    /// Renders `records` as a pretty-printed JSON array, or for `ndjson` as one compact JSON
+    /// object per line.
+    pub fn render_records<T: Serialize>(format: OutputFormat, records: &[T]) -> Result<String> {
+        match format {
+            OutputFormat::Ndjson => {
+                let mut rendered = String::new();
+                for record in records {
+                    rendered.push_str(&serde_json::to_string(record)?);
+                    rendered.push('\n');
+                }
+                Ok(rendered)
+            }
+            _ => Ok(serde_json::to_string_pretty(records)? + "\n"),
+        }
+    }
+
+    pub fn print_records<T: Serialize>(format: OutputFormat, records: &[T]) -> Result<()> {
+        print!("{}", render_records(format, records)?);
+        Ok(())
+    }
+
+    /// Formats seconds since the Unix epoch as a UTC date and time, e.g. `2024-05-01 13:45:00`.
+    pub fn format_timestamp(timestamp: u64) -> String {
+        // Converts days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`.
+        let days = (timestamp / 86400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        let seconds = timestamp % 86400;
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// The machinery behind `git test run`, exposed as a library API through [`TestRunner`].
+pub mod runner {
+    use crate::capture::{parse_size, Capture, DEFAULT_OUTPUT_LIMIT};
+    use crate::commands::results::status_label;
+    use crate::git::{
+        GitRepository, GitRepositoryWorktreeExt, GitSha, GitTestCommand, TestNote, TestSource,
+        TestStatus, Worktree, WorktreeConfig,
+    };
+    use crate::log_util::{log_and_spawn_command, stream_child};
+    use crate::progress::Progress;
+    use crate::shell::expand;
+    use anyhow::{Context, Result};
+    use colored::Colorize;
+    use futures::future;
+    use log::{info, warn};
+    use serde::Serialize;
+    use std::collections::{HashMap, HashSet};
+    use std::future::Future;
+    use std::path::{Path, PathBuf};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
+    use tokio::process::Command;
+    use tokio::sync::Semaphore;
+
+    /// How a run treats results that are already stored, and failures.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct RunFlags {
+        pub force: bool,
+        pub forget: bool,
+        pub retest: bool,
+        pub keep_going: bool,
+        pub dry_run: bool,
+    }
+
+    type ResultCallback<'a> = Box<dyn FnMut(&RunEntry) + Send + 'a>;
+
+    type StopSignal<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+    /// Which of the defined tests to run or report on.
+    #[derive(Debug, Clone, Default)]
+    pub struct TestSelection {
+        /// Tests by name; the name of a matrix test selects all of its variants.
+        pub tests: Vec<String>,
+        pub all: bool,
+        /// Selects every test with at least one of these tags.
+        pub tags: Vec<String>,
+        /// Leaves out every test with one of these tags, even if it was named. If nothing else
+        /// is selected, this applies to all tests.
+        pub exclude_tags: Vec<String>,
+    }
+
+    impl TestSelection {
+        /// Just the test called `name`.
+        pub fn test(name: impl Into<String>) -> Self {
+            TestSelection {
+                tests: vec![name.into()],
+                ..Default::default()
+            }
+        }
+
+        pub fn all() -> Self {
+            TestSelection {
+                all: true,
+                ..Default::default()
+            }
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.tests.is_empty()
+                && !self.all
+                && self.tags.is_empty()
+                && self.exclude_tags.is_empty()
+        }
+
+        /// The selected tests, in the order they are defined.
+        pub async fn resolve(&self, repo: &GitRepository) -> Result<Vec<GitTestCommand>> {
+            let defined = repo.list_tests().await?;
+            for name in &self.tests {
+                if !defined.iter().any(|t| is_named(t, name)) {
+                    anyhow::bail!("Test '{}' is not defined", name);
+                }
+            }
+
+            let everything = self.all || (self.tests.is_empty() && self.tags.is_empty());
+            let has_tag = |test: &GitTestCommand, tags: &[String]| {
+                test.tags.iter().any(|tag| tags.contains(tag))
+            };
+            Ok(defined
+                .into_iter()
+                .filter(|test| {
+                    everything
+                        || self.tests.iter().any(|name| is_named(test, name))
+                        || has_tag(test, &self.tags)
+                })
+                .filter(|test| !has_tag(test, &self.exclude_tags))
+                .collect())
+        }
+    }
+
+    fn is_named(test: &GitTestCommand, name: &str) -> bool {
+        test.test_name == name || test.variant_of.as_deref() == Some(name)
+    }
+
+    /// Runs tests against commits and records their results in notes, without going through
+    /// the command line.
+    ///
+    /// ```no_run
+    /// # async fn example(repo: &git_test::git::GitRepository) -> anyhow::Result<()> {
+    /// let results = git_test::TestRunner::new(repo)
+    ///     .test("default")
+    ///     .commits(["main..feature"])
+    ///     .keep_going(true)
+    ///     .on_result(|entry| println!("{} {}", entry.sha.as_str(), entry.outcome.label()))
+    ///     .run()
+    ///     .await?;
+    /// for result in results.test_results() {
+    ///     println!("{} took {:?}", result.test_name, result.duration);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub struct TestRunner<'a> {
+        repo: GitRepository,
+        selection: TestSelection,
+        test_commands: Vec<GitTestCommand>,
+        commits: Vec<String>,
+        shas: Vec<GitSha>,
+        worktree_config: Option<WorktreeConfig>,
+        concurrency: Option<usize>,
+        flags: RunFlags,
+        log_results: bool,
+        show_progress: bool,
+        stream_output: bool,
+        output_limit: Option<usize>,
+        spill_output: bool,
+        on_result: Option<ResultCallback<'a>>,
+        stop: Option<StopSignal<'a>>,
+    }
+
+    /// How much of each test's output to keep, whether to also keep all of it in files, and
+    /// how to log it as it arrives.
+    #[derive(Debug, Clone, Copy)]
+    struct OutputSettings {
+        /// Bytes kept at each end of a stream, or `None` to keep everything.
+        limit: Option<usize>,
+        spill: bool,
+        echo: Echo,
+    }
+
+    /// The tests of a run that are under way, so that a stopped run can stop them: on Unix,
+    /// each test runs in its own process group, out of reach of the terminal's Ctrl-C.
+    #[derive(Debug, Clone, Default)]
+    struct LiveTests {
+        state: Arc<Mutex<LiveState>>,
+    }
+
+    #[derive(Debug, Default)]
+    struct LiveState {
+        next_id: u64,
+        tests: HashMap<u64, (Worktree, Option<u32>)>,
+        stopped: bool,
+    }
+
+    impl LiveTests {
+        fn state(&self) -> MutexGuard<'_, LiveState> {
+            self.state.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Records a test about to run in `worktree` until the returned guard is dropped,
+        /// unless the run was stopped.
+        fn start(&self, worktree: &Worktree) -> Option<LiveTest<'_>> {
+            let mut state = self.state();
+            if state.stopped {
+                return None;
+            }
+            state.next_id += 1;
+            let id = state.next_id;
+            state.tests.insert(id, (worktree.clone(), None));
+            Some(LiveTest { live: self, id })
+        }
+
+        /// Kills every live test and removes its worktree, and keeps further tests from
+        /// starting.
+        async fn stop(&self) {
+            let tests: Vec<_> = {
+                let mut state = self.state();
+                state.stopped = true;
+                state.tests.drain().map(|(_, test)| test).collect()
+            };
+            for (worktree, process_group) in tests {
+                if let Some(process_group) = process_group {
+                    kill_process_group(process_group);
+                }
+                if let Err(e) = worktree.delete().await {
+                    warn!("Failed to remove {}: {}", worktree.get_path().display(), e);
+                }
+            }
+        }
+    }
+
+    struct LiveTest<'a> {
+        live: &'a LiveTests,
+        id: u64,
+    }
+
+    impl LiveTest<'_> {
+        /// Forgets the test, returning false if the run was stopped, and its worktree removed,
+        /// in the meantime.
+        fn finish(self) -> bool {
+            self.live.state().tests.remove(&self.id).is_some()
+        }
+
+        /// Records the process group of the test, returning false if the run was stopped in
+        /// the meantime.
+        fn spawned(&self, process_group: Option<u32>) -> bool {
+            match self.live.state().tests.get_mut(&self.id) {
+                Some((_, group)) => {
+                    *group = process_group;
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    impl Drop for LiveTest<'_> {
+        fn drop(&mut self) {
+            self.live.state().tests.remove(&self.id);
+        }
+    }
+
+    /// Aborts the tasks running a commit's tests when dropped.
+    struct AbortOnDrop(Vec<tokio::task::AbortHandle>);
+
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            for task in &self.0 {
+                task.abort();
+            }
+        }
+    }
+
+    /// How a test's output is logged while it runs.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Echo {
+        Off,
+        Plain,
+        /// Each line is prefixed with the commit and test, to tell apart the output of tests
+        /// running side by side.
+        Prefixed,
+    }
+
+    impl<'a> TestRunner<'a> {
+        /// A runner for `repo` that tests `HEAD` in the main worktree, until told otherwise.
+        pub fn new(repo: &GitRepository) -> Self {
+            TestRunner {
+                repo: repo.clone(),
+                selection: TestSelection::default(),
+                test_commands: Vec::new(),
+                commits: Vec::new(),
+                shas: Vec::new(),
+                worktree_config: None,
+                concurrency: None,
+                flags: RunFlags::default(),
+                log_results: false,
+                show_progress: false,
+                stream_output: false,
+                output_limit: None,
+                spill_output: false,
+                on_result: None,
+                stop: None,
+            }
+        }
+
+        /// Adds a test defined in the repository's config, by name. May be repeated.
+        pub fn test(mut self, name: impl Into<String>) -> Self {
+            self.selection.tests.push(name.into());
+            self
+        }
+
+        /// Adds every test tagged `tag`. May be repeated.
+        pub fn tag(mut self, tag: impl Into<String>) -> Self {
+            self.selection.tags.push(tag.into());
+            self
+        }
+
+        /// Leaves out every test tagged `tag`. May be repeated.
+        pub fn exclude_tag(mut self, tag: impl Into<String>) -> Self {
+            self.selection.exclude_tags.push(tag.into());
+            self
+        }
+
+        /// Adds the tests chosen by `selection`, such as those from the command line.
+        pub fn selection(mut self, selection: TestSelection) -> Self {
+            let TestSelection {
+                tests,
+                all,
+                tags,
+                exclude_tags,
+            } = selection;
+            self.selection.tests.extend(tests);
+            self.selection.all |= all;
+            self.selection.tags.extend(tags);
+            self.selection.exclude_tags.extend(exclude_tags);
+            self
+        }
+
+        /// Adds tests that are already loaded, or that aren't defined in config at all.
+        pub fn tests(mut self, tests: impl IntoIterator<Item = GitTestCommand>) -> Self {
+            self.test_commands.extend(tests);
+            self
+        }
+
+        /// Runs every test defined in the repository's config.
+        pub fn all_tests(mut self) -> Self {
+            self.selection.all = true;
+            self
+        }
+
+        /// Adds commits to test, as revisions or ranges such as `main..feature`.
+        pub fn commits<S: Into<String>>(mut self, specs: impl IntoIterator<Item = S>) -> Self {
+            self.commits.extend(specs.into_iter().map(Into::into));
+            self
+        }
+
+        /// Adds commits that are already resolved.
+        pub fn shas(mut self, shas: impl IntoIterator<Item = GitSha>) -> Self {
+            self.shas.extend(shas);
+            self
+        }
+
+        /// Sets where tests are checked out. Defaults to the main worktree.
+        pub fn worktree(mut self, worktree_config: WorktreeConfig) -> Self {
+            self.worktree_config = Some(worktree_config);
+            self
+        }
+
+        /// Checks each (commit, test) out into its own linked worktree under `path`.
+        pub fn linked_worktrees(self, path: &Path) -> Self {
+            let worktree_config = self.repo.to_linked_worktree_config(path);
+            self.worktree(worktree_config)
+        }
+
+        /// Limits how many tests run at once on a commit. By default they all do.
+        pub fn concurrency(mut self, limit: usize) -> Self {
+            self.concurrency = Some(limit.max(1));
+            self
+        }
+
+        pub fn flags(mut self, flags: RunFlags) -> Self {
+            self.flags = flags;
+            self
+        }
+
+        /// Forgets stored results and runs the tests again.
+        pub fn force(mut self, force: bool) -> Self {
+            self.flags.force = force;
+            self
+        }
+
+        /// Forgets stored results without running anything.
+        pub fn forget(mut self, forget: bool) -> Self {
+            self.flags.forget = forget;
+            self
+        }
+
+        /// Runs tests again where the stored result is bad or flaky.
+        pub fn retest(mut self, retest: bool) -> Self {
+            self.flags.retest = retest;
+            self
+        }
+
+        /// Carries on to later commits after a failure.
+        pub fn keep_going(mut self, keep_going: bool) -> Self {
+            self.flags.keep_going = keep_going;
+            self
+        }
+
+        /// Reports stored results without running anything.
+        pub fn dry_run(mut self, dry_run: bool) -> Self {
+            self.flags.dry_run = dry_run;
+            self
+        }
+
+        /// Logs a line per (commit, test) with `info!` as each commit finishes.
+        pub fn log_results(mut self, log_results: bool) -> Self {
+            self.log_results = log_results;
+            self
+        }
+
+        /// Shows the live progress area when stdout is a terminal.
+        pub fn show_progress(mut self, show_progress: bool) -> Self {
+            self.show_progress = show_progress;
+            self
+        }
+
+        /// Echoes each line the tests print as it is printed, rather than only capturing it:
+        /// lines of stdout are logged, lines of stderr written to stderr.
+        pub fn stream_output(mut self, stream_output: bool) -> Self {
+            self.stream_output = stream_output;
+            self
+        }
+
+        /// Keeps only the first and last `limit` bytes of each test's stdout and stderr, or all
+        /// of it if `limit` is zero. Defaults to `test.outputLimit`, a size in bytes such as
+        /// `65536` or `64k`, again with `0` for all of it, or to 256 KiB.
+        pub fn output_limit(mut self, limit: usize) -> Self {
+            self.output_limit = Some(limit);
+            self
+        }
+
+        /// Also writes the complete stdout and stderr of each test to temporary files.
+        pub fn spill_output(mut self, spill_output: bool) -> Self {
+            self.spill_output = spill_output;
+            self
+        }
+
+        /// Calls `callback` with each entry as the commit it belongs to finishes.
+        pub fn on_result(mut self, callback: impl FnMut(&RunEntry) + Send + 'a) -> Self {
+            self.on_result = Some(Box::new(callback));
+            self
+        }
+
+        /// Stops the run once `signal` completes: running tests are killed, their worktrees
+        /// removed, and `run` fails. Runs until done by default.
+        pub fn stop_on(mut self, signal: impl Future<Output = ()> + Send + 'a) -> Self {
+            self.stop = Some(Box::pin(signal));
+            self
+        }
+
+        /// Runs the selected tests against each commit in turn, recording the results in
+        /// notes. Stops after the first commit with a failure unless `keep_going` is set.
+        pub async fn run(self) -> Result<RunResults> {
+            let TestRunner {
+                repo,
+                selection,
+                mut test_commands,
+                commits,
+                mut shas,
+                worktree_config,
+                concurrency,
+                flags,
+                log_results,
+                show_progress,
+                stream_output,
+                output_limit,
+                spill_output,
+                mut on_result,
+                stop,
+            } = self;
+
+            if !selection.is_empty() {
+                test_commands.extend(selection.resolve(&repo).await?);
+            }
+            let mut seen = HashSet::new();
+            test_commands.retain(|test| seen.insert(test.test_name.clone()));
+            let tests = test_commands;
+            if tests.is_empty() {
+                anyhow::bail!("No tests selected");
+            }
+
+            if !commits.is_empty() {
+                shas.extend(repo.resolve_commits(&commits).await?);
+            } else if shas.is_empty() {
+                shas.push(GitSha::new(repo.get_head_commit().await?));
+            }
+
+            let output_limit = match output_limit {
+                Some(limit) => limit,
+                None => match repo.get_optional_config_value("test.outputLimit").await? {
+                    Some(value) => parse_size(&value).context("Invalid test.outputLimit")?,
+                    None => DEFAULT_OUTPUT_LIMIT,
+                },
+            };
+            let output = OutputSettings {
+                limit: (output_limit > 0).then_some(output_limit),
+                spill: spill_output,
+                echo: Echo::Off,
+            };
+
+            let worktree_config = worktree_config.unwrap_or_else(|| repo.to_worktree_config());
+            let limit = concurrency.map(|limit| Arc::new(Semaphore::new(limit)));
+            let progress = Progress::new(
+                shas.len() * tests.len(),
+                show_progress && Progress::can_be_live(),
+            );
+            let live = LiveTests::default();
+            let mut stop = stop.unwrap_or_else(|| Box::pin(future::pending()));
+
+            let mut entries = Vec::new();
+            for sha in &shas {
+                let mut to_run = Vec::new();
+                for git_test_command in &tests_at(&repo, &tests, sha).await? {
+                    if flags.force || flags.forget {
+                        git_test_command.forget_result(sha.as_str()).await?;
+                    }
+                    let outcome = if flags.forget {
+                        RunOutcome::Forgotten
+                    } else {
+                        match git_test_command.get_result(sha.as_str()).await? {
+                            Some(TestStatus::Good) => RunOutcome::Known(TestStatus::Good),
+                            Some(status) if !flags.retest => RunOutcome::Known(status),
+                            _ if flags.dry_run => RunOutcome::Unknown,
+                            _ => {
+                                to_run.push(git_test_command.clone());
+                                continue;
+                            }
+                        }
+                    };
+                    match outcome {
+                        RunOutcome::Known(_) => progress.record_known(),
+                        _ => progress.record_skipped(),
+                    }
+                    entries.push(RunEntry::new(sha, git_test_command, outcome));
+                }
+
+                if !to_run.is_empty() {
+                    let echo = if !stream_output {
+                        Echo::Off
+                    } else if to_run.len() > 1 && concurrency != Some(1) {
+                        Echo::Prefixed
+                    } else {
+                        Echo::Plain
+                    };
+                    let test_results = tokio::select! {
+                        test_results = run_tests_for_commit(
+                            &to_run,
+                            sha,
+                            &worktree_config,
+                            &progress,
+                            &limit,
+                            &live,
+                            OutputSettings { echo, ..output },
+                        ) => test_results?,
+                        _ = &mut stop => {
+                            live.stop().await;
+                            anyhow::bail!("Interrupted");
+                        }
+                    };
+                    update_git_notes(&repo, &to_run, sha.as_str(), &test_results).await?;
+                    for (git_test_command, result) in to_run.iter().zip(test_results) {
+                        entries.push(RunEntry::new(
+                            sha,
+                            git_test_command,
+                            RunOutcome::Tested(result),
+                        ));
+                    }
+                }
+
+                for entry in entries.iter().filter(|entry| entry.sha == *sha) {
+                    if let Some(on_result) = on_result.as_mut() {
+                        on_result(entry);
+                    }
+                    if log_results {
+                        info!(
+                            "{} {} {}",
+                            sha.as_str(),
+                            status_label(&entry.outcome.label()),
+                            entry.test_name
+                        );
+                    }
+                }
+
+                let failed = entries.iter().any(RunEntry::is_failure);
+                if failed && !flags.keep_going && !flags.dry_run {
+                    return Ok(RunResults {
+                        entries,
+                        stopped_at: Some(sha.clone()),
+                    });
+                }
+            }
+
+            Ok(RunResults {
+                entries,
+                stopped_at: None,
+            })
+        }
+    }
+
+    /// Everything a [`TestRunner`] did.
+    #[derive(Debug)]
+    pub struct RunResults {
+        /// One entry per (commit, test) pair visited, in order.
+        pub entries: Vec<RunEntry>,
+        /// The commit at which the run stopped because of a failure, if it did.
+        pub stopped_at: Option<GitSha>,
+    }
+
+    impl RunResults {
+        /// The results of the tests that were actually run.
+        pub fn test_results(&self) -> impl Iterator<Item = &TestResult> {
+            self.entries
+                .iter()
+                .filter_map(|entry| match &entry.outcome {
+                    RunOutcome::Tested(result) => Some(result),
+                    _ => None,
+                })
+        }
+
+        pub fn failures(&self) -> impl Iterator<Item = &RunEntry> {
+            self.entries.iter().filter(|entry| entry.is_failure())
+        }
+
+        pub fn summary(&self) -> RunSummary {
+            RunSummary::from_entries(&self.entries)
+        }
+    }
+
+    /// What happened to one test on one commit during a run.
+    #[derive(Debug)]
+    pub enum RunOutcome {
+        /// The test was run.
+        Tested(TestResult),
+        /// A result was already stored, so the test wasn't run again.
+        Known(TestStatus),
+        /// No result is stored and none was produced (with `--dry-run`).
+        Unknown,
+        /// The stored result was forgotten without running the test (with `--forget`).
+        Forgotten,
+    }
+
+    impl RunOutcome {
+        pub fn label(&self) -> String {
+            match self {
+                RunOutcome::Tested(result) => result.status().as_str().to_string(),
+                RunOutcome::Known(status) => format!("known-{}", status.as_str()),
+                RunOutcome::Unknown => "unknown".to_string(),
+                RunOutcome::Forgotten => "forgotten".to_string(),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct RunEntry {
+        pub sha: GitSha,
+        pub test_name: String,
+        pub outcome: RunOutcome,
+    }
+
+    /// Totals over the entries of a run, as included in the structured run report.
+    #[derive(Debug, Default, Clone, Serialize)]
+    pub struct RunSummary {
+        pub good: usize,
+        pub bad: usize,
+        pub known: usize,
+        pub unknown: usize,
+        pub forgotten: usize,
+    }
+
+    impl RunSummary {
+        pub fn from_entries(entries: &[RunEntry]) -> Self {
+            let mut summary = RunSummary::default();
+            for entry in entries {
+                match &entry.outcome {
+                    RunOutcome::Tested(result) if result.success => summary.good += 1,
+                    RunOutcome::Tested(_) => summary.bad += 1,
+                    RunOutcome::Known(_) => summary.known += 1,
+                    RunOutcome::Unknown => summary.unknown += 1,
+                    RunOutcome::Forgotten => summary.forgotten += 1,
+                }
+            }
+            summary
+        }
+    }
+
+    impl RunEntry {
+        fn new(sha: &GitSha, git_test_command: &GitTestCommand, outcome: RunOutcome) -> Self {
+            RunEntry {
+                sha: sha.clone(),
+                test_name: git_test_command.test_name.clone(),
+                outcome,
+            }
+        }
+
+        pub fn is_failure(&self) -> bool {
+            match &self.outcome {
+                RunOutcome::Tested(result) => !result.success,
+                RunOutcome::Known(status) => *status != TestStatus::Good,
+                RunOutcome::Unknown | RunOutcome::Forgotten => false,
+            }
+        }
+    }
+
+    /// `tests` as defined in `sha`: those from `.gittest.toml` take the definition committed
+    /// there, where it has one.
+    async fn tests_at(
+        repo: &GitRepository,
+        tests: &[GitTestCommand],
+        sha: &GitSha,
+    ) -> Result<Vec<GitTestCommand>> {
+        if tests.iter().all(|test| test.source == TestSource::Config) {
+            return Ok(tests.to_vec());
+        }
+        let defined = repo.list_tests_at(sha.as_str()).await?;
+        Ok(tests
+            .iter()
+            .map(|test| match test.source {
+                TestSource::Config => test.clone(),
+                TestSource::File | TestSource::ConfigOverFile => defined
+                    .iter()
+                    .find(|defined| defined.test_name == test.test_name)
+                    .unwrap_or(test)
+                    .clone(),
+            })
+            .collect())
+    }
+
+    async fn run_tests_for_commit(
+        tests: &[GitTestCommand],
+        sha: &GitSha,
+        worktree_config: &WorktreeConfig,
+        progress: &Progress,
+        limit: &Option<Arc<Semaphore>>,
+        live: &LiveTests,
+        output: OutputSettings,
+    ) -> Result<Vec<TestResult>> {
+        let tasks: Vec<_> = tests
+            .iter()
+            .map(|git_test_command| {
+                let git_test_command = git_test_command.clone();
+                let sha = sha.clone();
+                let worktree_config = worktree_config.clone();
+                let progress = progress.clone();
+                let limit = limit.clone();
+                let live = live.clone();
+
+                tokio::spawn(async move {
+                    let _permit = match &limit {
+                        Some(limit) => Some(limit.clone().acquire_owned().await?),
+                        None => None,
+                    };
+                    let test_name = &git_test_command.test_name;
+                    let prefix = match output.echo {
+                        Echo::Off => None,
+                        Echo::Plain => Some(String::new()),
+                        Echo::Prefixed => Some(
+                            format!("{} {} │ ", sha.short(), test_name)
+                                .dimmed()
+                                .to_string(),
+                        ),
+                    };
+                    progress.start_test(&sha, test_name);
+                    let result = run_single_test(
+                        &git_test_command,
+                        &sha,
+                        &worktree_config,
+                        &live,
+                        prefix.as_deref(),
+                        output,
+                    )
+                    .await;
+                    progress.finish_test(
+                        &sha,
+                        test_name,
+                        result.as_ref().is_ok_and(|result| result.success),
+                    );
+                    result
+                })
+            })
+            .collect();
+
+        // Stopping the run drops this future; the tests' commands are dropped, and so
+        // killed, with their tasks.
+        let _abort = AbortOnDrop(tasks.iter().map(|task| task.abort_handle()).collect());
+        let results = futures::future::join_all(tasks).await;
+        results
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+    }
+
+    async fn run_single_test(
+        git_test_command: &GitTestCommand,
+        sha: &GitSha,
+        worktree_config: &WorktreeConfig,
+        live: &LiveTests,
+        echo: Option<&str>,
+        output: OutputSettings,
+    ) -> Result<TestResult> {
+        let worktree = worktree_config.to_worktree(sha.clone(), &git_test_command.test_name);
+        worktree.create().await?;
+        let Some(live_test) = live.start(&worktree) else {
+            worktree.delete().await?;
+            anyhow::bail!("Interrupted");
+        };
+
+        let result =
+            run_in_worktree(git_test_command, sha, &worktree, &live_test, echo, output).await;
+        // Whether the test passed, failed or couldn't run at all; stopping the run removes
+        // the worktree itself.
+        if live_test.finish() {
+            let deleted = worktree.delete().await;
+            return result.and_then(|result| deleted.map(|()| result));
+        }
+        result
+    }
+
+    async fn run_in_worktree(
+        GitTestCommand {
+            repo,
+            test_name,
+            test_command,
+            timeout,
+            env,
+            params,
+            ..
+        }: &GitTestCommand,
+        sha: &GitSha,
+        worktree: &Worktree,
+        live_test: &LiveTest<'_>,
+        echo: Option<&str>,
+        output: OutputSettings,
+    ) -> Result<TestResult> {
+        let tree = repo
+            .backend()
+            .rev_parse(&format!("{}^{{tree}}", sha.as_str()))
+            .await?
+            .with_context(|| format!("Failed to resolve the tree of {}", sha.as_str()))?;
+        let worktree_path = worktree.get_path().display().to_string();
+        // Each placeholder the command may use, and the environment variable that also holds it.
+        let variables = [
+            ("sha", "GIT_TEST_SHA", sha.as_str()),
+            ("short", "GIT_TEST_SHORT", sha.short()),
+            ("tree", "GIT_TEST_TREE", tree.as_str()),
+            ("worktree", "GIT_TEST_WORKTREE", worktree_path.as_str()),
+            ("test", "GIT_TEST_NAME", test_name.as_str()),
+        ];
+        let placeholders: Vec<(&str, &str)> = variables
+            .iter()
+            .map(|(name, _, value)| (*name, *value))
+            .chain(
+                params
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+            .collect();
+        let param_variables = params
+            .iter()
+            .map(|(name, value)| (format!("GIT_TEST_PARAM_{}", name.to_uppercase()), value));
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(expand(test_command, &placeholders))
+            .envs(variables.iter().map(|(_, var, value)| (var, value)))
+            .envs(param_variables)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .current_dir(worktree.get_path())
+            .kill_on_drop(true);
+        // Its own process group, so that a timeout also stops whatever the test started.
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut stdout = Capture::new(output.limit);
+        let mut stderr = Capture::new(output.limit);
+        if output.spill {
+            let stem = format!(
+                "git-test-{}-{}-{}",
+                sha.as_str(),
+                test_name.replace('/', "_"),
+                std::process::id()
+            );
+            let dir = std::env::temp_dir();
+            stdout = stdout.spill_to(dir.join(format!("{}.stdout", stem)))?;
+            stderr = stderr.spill_to(dir.join(format!("{}.stderr", stem)))?;
+        }
+
+        let (stdout, stderr) = (stdout.shared(), stderr.shared());
+        let start = Instant::now();
+        let child = log_and_spawn_command(&mut cmd)?;
+        let process_group = child.id();
+        if !live_test.spawned(process_group) {
+            if let Some(process_group) = process_group {
+                kill_process_group(process_group);
+            }
+            anyhow::bail!("Interrupted");
+        }
+        let streamed = stream_child(child, echo, stdout.clone(), stderr.clone());
+        let status = match timeout {
+            None => Some(streamed.await?),
+            Some(timeout) => match tokio::time::timeout(*timeout, streamed).await {
+                Ok(status) => Some(status?),
+                Err(_) => {
+                    // Dropping the command killed it; the rest of its group goes too.
+                    if let Some(process_group) = process_group {
+                        kill_process_group(process_group);
+                    }
+                    warn!(
+                        "'{}' timed out after {}s on {}",
+                        test_name,
+                        timeout.as_secs(),
+                        sha.as_str()
+                    );
+                    None
+                }
+            },
+        };
+        let duration = start.elapsed();
+        let stdout = Capture::finish_shared(&stdout);
+        let mut stderr = Capture::finish_shared(&stderr);
+        if let (None, Some(timeout)) = (status, timeout) {
+            if !stderr.text.is_empty() && !stderr.text.ends_with('\n') {
+                stderr.text.push('\n');
+            }
+            stderr
+                .text
+                .push_str(&format!("Timed out after {}s\n", timeout.as_secs()));
+        }
+
+        let success = status.is_some_and(|status| status.success());
+        for (name, captured) in [("stdout", &stdout), ("stderr", &stderr)] {
+            if captured.elided > 0 {
+                match &captured.file {
+                    Some(file) => info!(
+                        "Kept part of the {} of '{}'; all of it is in {}",
+                        name,
+                        test_name,
+                        file.display()
+                    ),
+                    None => info!(
+                        "Elided {} bytes of the {} of '{}'",
+                        captured.elided, name, test_name
+                    ),
+                }
+            }
+        }
+
+        Ok(TestResult {
+            test_name: test_name.to_string(),
+            success,
+            exit_code: status.and_then(|status| status.code()),
+            stdout: stdout.text,
+            stderr: stderr.text,
+            stdout_elided: stdout.elided,
+            stderr_elided: stderr.elided,
+            stdout_file: stdout.file,
+            stderr_file: stderr.file,
+            duration,
+            timed_out: if status.is_none() { *timeout } else { None },
+        })
+    }
+
+    /// Sends SIGKILL to every process in `process_group`.
+    #[cfg(unix)]
+    fn kill_process_group(process_group: u32) {
+        // SAFETY: killpg only sends a signal; the group was created for the test.
+        if unsafe { libc::killpg(process_group as libc::pid_t, libc::SIGKILL) } != 0 {
+            let err = std::io::Error::last_os_error();
+            // The group is already gone once all of its processes exited.
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                warn!("Failed to kill process group {}: {}", process_group, err);
+            }
+        }
+    }
+
+    /// Tests don't get their own process group outside Unix; only the test's own process is
+    /// killed, when its command is dropped.
+    #[cfg(not(unix))]
+    fn kill_process_group(_process_group: u32) {}
+
+    /// Bytes at the end of a failing test's stdout and stderr that are kept in its note. The
+    /// rest stays out of the notes, which are shared, and is only in the spilled files.
+    pub const NOTE_OUTPUT_LIMIT: usize = 8 * 1024;
+
+    #[derive(Debug, Clone, Default)]
+    pub struct TestResult {
+        pub test_name: String,
+        pub success: bool,
+        /// `None` if the test was killed by a signal.
+        pub exit_code: Option<i32>,
+        /// The test's stdout, with the middle elided if it exceeded the output limit.
+        pub stdout: String,
+        pub stderr: String,
+        /// How many bytes were elided from `stdout` and `stderr`.
+        pub stdout_elided: u64,
+        pub stderr_elided: u64,
+        /// Files holding the complete output, when it was spilled.
+        pub stdout_file: Option<PathBuf>,
+        pub stderr_file: Option<PathBuf>,
+        pub duration: Duration,
+        /// The timeout the test was killed after, if it ran into one.
+        pub timed_out: Option<Duration>,
+    }
+
+    impl TestResult {
+        pub fn status(&self) -> TestStatus {
+            TestStatus::from_success(self.success)
+        }
+    }
+
+    /// The end of `output` that is stored in a note: at most `NOTE_OUTPUT_LIMIT` bytes, starting
+    /// on a line boundary where possible.
+    fn note_tail(output: &str) -> String {
+        if output.len() <= NOTE_OUTPUT_LIMIT {
+            return output.to_string();
+        }
+        let mut start = output.len() - NOTE_OUTPUT_LIMIT;
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        if let Some(newline) = output[start..].find('\n') {
+            if start + newline + 1 < output.len() {
+                start += newline + 1;
+            }
+        }
+        format!("[... earlier output elided ...]\n{}", &output[start..])
+    }
+
+    async fn update_git_notes(
+        repo: &GitRepository,
+        tests: &[GitTestCommand],
+        commit: &str,
+        results: &[TestResult],
+    ) -> Result<()> {
+        for (git_test_command, result) in tests.iter().zip(results) {
+            let mut note = TestNote::new(result.status());
+            if !result.success {
+                note.stdout = Some(note_tail(&result.stdout));
+                note.stderr = Some(note_tail(&result.stderr));
+                let display = |file: &PathBuf| file.display().to_string();
+                note.stdout_file = result.stdout_file.as_ref().map(display);
+                note.stderr_file = result.stderr_file.as_ref().map(display);
+            }
+            note.commit = Some(commit.to_string());
+            note.exit_code = result.exit_code;
+            note.duration_ms = Some(result.duration.as_millis() as u64);
+            note.host = Some(gethostname::gethostname().to_string_lossy().into_owned());
+            note.fingerprint = Some(git_test_command.fingerprint());
+            git_test_command.set_result_note(commit, &note).await?;
+        }
+
+        let summary = results
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}: {}",
+                    r.test_name,
+                    TestStatus::from_success(r.success).as_symbol()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        repo.add_note("refs/notes/commits", commit, &summary)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub mod cli {
+    use crate::capture::parse_size;
+    use crate::commands::RunOptions;
+    use crate::output::OutputFormat;
+    use crate::runner::{RunFlags, TestSelection};
+    use clap::{Args, ColorChoice, Parser, Subcommand, ValueEnum};
+    use std::path::PathBuf;
+
+    #[derive(Parser)]
+    #[command(
+        name = "git-test",
+        author,
+        version,
+        about,
+        long_about = "Run tests within a Git project and remember the test results.\n\
+    `git test` consists of a few things:\n\
+    * A way of defining tests for a Git project. The commands to be run\n\
+      for a particular test are stored in the repository's Git\n\
+      configuration.\n\
+    * Tools for running such tests against single Git commits or against\n\
+      ranges of commits.\n\
+    * A scheme for storing the results of such tests as git notes. The\n\
+      results are connected to the tree of the commit that was tested, so\n\
+      the test results remain valid across some types of merges, rebases,\n\
+      etc.\n\
+    * The intelligence not to re-run a test whose results are already\n\
+      known.\n\
+    \n\
+    Example: make sure that all commits on a feature branch pass the tests\n\
+    implied by `make -j16 test` (run the tests in a worktree to avoid\n\
+    tying up your main repository):\n\
+        $ git config test.full.command 'make -j16 test'\n\
+        $ git worktree add --detach ../tests feature\n\
+        $ cd ../tests\n\
+        $ git test run --test=full master..feature\n\
+    Any time you make changes to the feature branch in your main\n\
+    repository, you can re-run the last command in the `tests` worktree.\n\
+    It will only test commits with trees that it hasn't been seen before."
+    )]
+    pub struct Cli {
+        #[command(subcommand)]
+        pub command: Commands,
+
+        #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "generate more verbose output (may be specified multiple times)")]
+        pub verbose: u8,
+
+        #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "generate less verbose output (may be specified multiple times)")]
+        pub quiet: u8,
+
+        #[arg(
+        long,
+        global = true,
+        help = "Control when to use colors: auto, always, never",
+        default_value_t = ColorChoice::Auto
+        )]
+        pub color: ColorChoice,
+
+        #[arg(
+            long,
+            global = true,
+            value_enum,
+            help = "output format for list, results, run, show, log and stats (tap: results and run only); with json and ndjson, log messages go to stderr",
+            default_value_t = OutputFormat::Human
+        )]
+        pub format: OutputFormat,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        #[command(about = "define a new test")]
+        Add(AddArgs),
+
+        #[command(about = "run a test against one or more commits")]
+        Run(RunArgs),
+
+        #[command(about = "obsolete command; please use \"git test run\" instead")]
+        Range(RunArgs),
+
+        #[command(about = "show any stored test results for the specified commits")]
+        Results(ResultsArgs),
+
+        #[command(about = "show everything stored about one commit's test results")]
+        Show(ShowArgs),
+
+        #[command(
+            about = "show the commit graph with the stored result of each test (all tests unless some are selected)"
+        )]
+        Log(LogArgs),
+
+        #[command(
+            about = "summarize stored results: counts, durations and bad streaks (all tests unless some are selected)"
+        )]
+        Stats(StatsArgs),
+
+        #[command(about = "permanently forget stored results for a test")]
+        ForgetResults(ForgetResultsArgs),
+
+        #[command(
+            about = "remove stored results for trees no longer reachable from any ref",
+            long_about = "Remove stored results for trees no longer reachable from any ref, in one \
+                          commit per notes ref. The notes history still holds the removed \
+                          results, so their space is only freed once that history is rewritten."
+        )]
+        Gc(GcArgs),
+
+        #[command(about = "list the tests that are currently defined")]
+        List,
+
+        #[command(about = "remove a test definition and all of its stored results")]
+        Remove(RemoveArgs),
+
+        #[command(about = "rename a test, keeping its stored results")]
+        Rename(RenameArgs),
+
+        #[command(about = "copy a test definition, optionally with its stored results")]
+        Copy(CopyArgs),
+
+        #[command(about = "push stored test results to a remote")]
+        Push(RemoteArgs),
+
+        #[command(about = "fetch test results from a remote and merge them into local results")]
+        Fetch(RemoteArgs),
+
+        #[command(
+            about = "merge test results from another notes ref into the local results",
+            long_about = "Merge test results from another notes ref into the local results. \
+                          Where both sides have a result for the same tree: if either is flaky, \
+                          or one is good and the other bad, the merged result is flaky, which \
+                          counts as a failure like bad does; otherwise they agree and the newer \
+                          one is kept. Which side is local makes no difference."
+        )]
+        MergeNotes(MergeNotesArgs),
+
+        #[command(about = "write an HTML report of stored results for a range of commits")]
+        Report(ReportArgs),
+
+        #[command(about = "re-test a range of commits whenever it changes")]
+        Watch(WatchArgs),
+
+        #[command(about = "serve test definitions, results and runs over a local HTTP API")]
+        Serve(ServeArgs),
+
+        #[command(
+            about = "install a git hook that tests commits before they leave the repository"
+        )]
+        InstallHook(InstallHookArgs),
+
+        #[command(about = "remove a hook installed by install-hook, restoring any previous hook")]
+        UninstallHook(UninstallHookArgs),
+    }
+
+    impl Commands {
+        /// Whether the command honours `--format`.
+        pub fn supports_format(&self) -> bool {
+            matches!(
+                self,
+                Commands::List
+                    | Commands::Run(_)
+                    | Commands::Range(_)
+                    | Commands::Results(_)
+                    | Commands::Show(_)
+                    | Commands::Log(_)
+                    | Commands::Stats(_)
+            )
+        }
+    }
+
+    /// The git hooks that `install-hook` knows how to write.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+    pub enum HookKind {
+        /// Test the commits being pushed, refusing the push if any is bad
+        PrePush,
+    }
+
+    impl HookKind {
+        pub fn name(&self) -> &'static str {
+            match self {
+                HookKind::PrePush => "pre-push",
+            }
+        }
+    }
+
+    #[derive(Args)]
+    pub struct AddArgs {
+        #[arg(
+            short,
+            long,
+            default_value = "default",
+            help = "name of test to add (default is 'default')"
+        )]
+        pub test: String,
+
+        #[arg(long, help = "forget any existing results", conflicts_with = "keep")]
+        pub forget: bool,
+
+        #[arg(
+            long,
+            help = "keep any existing results (default)",
+            conflicts_with = "forget",
+            default_value_t = true
+        )]
+        pub keep: bool,
+
+        #[arg(
+            help = "command to run; {sha}, {short}, {tree}, {worktree} and {test} are replaced by \
+                    the commit, its tree, the worktree path and the test name, which are also \
+                    exported as GIT_TEST_SHA, GIT_TEST_SHORT, GIT_TEST_TREE, GIT_TEST_WORKTREE \
+                    and GIT_TEST_NAME; each placeholder becomes one already-quoted shell word, \
+                    so it may adjoin other text, as in out-{test}.log, but must not be put \
+                    inside quotes: use the variables there"
+        )]
+        pub command: String,
+    }
+
+    /// Options choosing which tests `run` and `results` act on.
+    #[derive(Args)]
+    pub struct TestSelectionArgs {
+        #[arg(
+            short,
+            long,
+            value_name = "TEST",
+            help = "name of test, or of a matrix test for all its variants; may be repeated (default is 'default')",
+            conflicts_with = "all"
+        )]
+        pub test: Vec<String>,
+
+        #[arg(long, help = "select all defined tests", conflicts_with = "test")]
+        pub all: bool,
+
+        #[arg(
+            long,
+            value_name = "TAG",
+            help = "select the tests tagged TAG; may be repeated"
+        )]
+        pub tag: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "TAG",
+            help = "leave out the tests tagged TAG, from all tests if no others are selected; may be repeated"
+        )]
+        pub exclude_tag: Vec<String>,
+    }
+
+    impl TestSelectionArgs {
+        /// The chosen tests, which may be none at all.
+        pub fn given_selection(&self) -> TestSelection {
+            TestSelection {
+                tests: self.test.clone(),
+                all: self.all,
+                tags: self.tag.clone(),
+                exclude_tags: self.exclude_tag.clone(),
+            }
+        }
+
+        /// The chosen tests, or the one named `default` if none were chosen.
+        pub fn to_selection(&self) -> TestSelection {
+            let selection = self.given_selection();
+            if selection.is_empty() {
+                TestSelection {
+                    tests: vec!["default".to_string()],
+                    ..selection
+                }
+            } else {
+                selection
+            }
+        }
+    }
+
+    #[derive(Args)]
+    pub struct RunArgs {
+        #[command(flatten)]
+        pub selection: TestSelectionArgs,
+
+        #[arg(
+            short,
+            long,
+            help = "forget any existing test results for the specified commits and test them again"
+        )]
+        pub force: bool,
+
+        #[arg(
+            long,
+            help = "forget any existing test results for the specified commits"
+        )]
+        pub forget: bool,
+
+        #[arg(
+            long,
+            help = "if a commit is already marked as \"bad\", try testing it again"
+        )]
+        pub retest: bool,
+
+        #[arg(
+            short,
+            long,
+            help = "if a commit fails the test, continue testing other commits rather than aborting"
+        )]
+        pub keep_going: bool,
+
+        #[arg(
+            short = 'n',
+            long,
+            help = "show known results, without running any new tests"
+        )]
+        pub dry_run: bool,
+
+        #[arg(
+            long,
+            help = "read the list of commits to test from standard input, one per line"
+        )]
+        pub stdin: bool,
+
+        #[arg(
+            long,
+            help = "run tests in git worktrees",
+            default_value = ".worktrees"
+        )]
+        pub worktree: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "write a JUnit XML report of the run to FILE"
+        )]
+        pub junit: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "also write the complete output of each test to git-test-<commit>-<test>-<pid>.stdout and .stderr in the temporary directory; these files are left in place"
+        )]
+        pub spill_output: bool,
+
+        #[arg(
+            long,
+            value_name = "SIZE",
+            value_parser = parse_size,
+            help = "keep only the first and last SIZE bytes of each test's stdout and stderr; SIZE is in bytes, or in KiB, MiB or GiB with a k, m or g suffix, and 0 keeps all of it (default is the test.outputLimit config, which takes the same sizes, or 256k)"
+        )]
+        pub output_limit: Option<usize>,
+
+        #[arg(help = "commits or ranges of commits to test")]
+        pub commits: Vec<String>,
+    }
+
+    impl RunArgs {
+        pub fn run_options(&self, format: OutputFormat) -> RunOptions<'_> {
+            RunOptions {
+                flags: RunFlags {
+                    force: self.force,
+                    forget: self.forget,
+                    retest: self.retest,
+                    keep_going: self.keep_going,
+                    dry_run: self.dry_run,
+                },
+                stdin: self.stdin,
+                worktree: self.worktree.as_deref(),
+                junit: self.junit.as_deref(),
+                spill_output: self.spill_output,
+                output_limit: self.output_limit,
+                format,
+            }
+        }
+    }
+
+    #[derive(Args)]
+    pub struct ResultsArgs {
+        #[command(flatten)]
+        pub selection: TestSelectionArgs,
+
+        #[arg(
+            long,
+            help = "read the list of commits from standard input, one per line"
+        )]
+        pub stdin: bool,
+
+        #[arg(help = "commits or ranges of commits")]
+        pub commits: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct GcArgs {
+        #[arg(
+            long,
+            value_name = "AGE",
+            help = "only remove results recorded longer ago than AGE, e.g. 90d, 12h or 30m"
+        )]
+        pub unreachable_older_than: Option<String>,
+
+        #[arg(
+            short = 'n',
+            long,
+            help = "report what would be removed, without removing anything"
+        )]
+        pub dry_run: bool,
+    }
+
+    #[derive(Args)]
+    pub struct ForgetResultsArgs {
+        #[arg(
+            short,
+            long,
+            default_value = "default",
+            help = "name of test whose results should be forgotten (default is 'default')"
+        )]
+        pub test: String,
+    }
+
+    #[derive(Args)]
+    pub struct RemoteArgs {
+        #[arg(
+            default_value = "origin",
+            help = "remote to push results to or fetch them from (default is 'origin')"
+        )]
+        pub remote: String,
+    }
+
+    #[derive(Args)]
+    pub struct MergeNotesArgs {
+        #[arg(
+            short,
+            long,
+            default_value = "default",
+            help = "name of test whose results should be merged (default is 'default')"
+        )]
+        pub test: String,
+
+        #[arg(help = "notes ref holding the results to merge in")]
+        pub notes_ref: String,
+    }
+
+    #[derive(Args)]
+    pub struct ReportArgs {
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "directory to write the HTML report (index.html) into"
+        )]
+        pub html: PathBuf,
+
+        #[arg(
+            long,
+            help = "read the list of commits from standard input, one per line"
+        )]
+        pub stdin: bool,
+
+        #[arg(help = "commits or ranges of commits to include")]
+        pub commits: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct WatchArgs {
+        #[command(flatten)]
+        pub selection: TestSelectionArgs,
+
+        #[arg(
+            long,
+            help = "directory for the worktrees kept between iterations",
+            default_value = ".worktrees"
+        )]
+        pub worktree: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "how often to check whether the range has changed",
+            default_value_t = 2
+        )]
+        pub interval: u64,
+
+        #[arg(required = true, help = "commits or ranges of commits to watch")]
+        pub commits: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct ServeArgs {
+        #[arg(
+            long,
+            value_name = "ADDR",
+            help = "address to listen on",
+            default_value = "127.0.0.1:8080"
+        )]
+        pub listen: String,
+
+        #[arg(
+            long,
+            help = "directory for the worktrees used by queued runs",
+            default_value = ".worktrees"
+        )]
+        pub worktree: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct InstallHookArgs {
+        #[arg(value_enum, help = "which hook to install")]
+        pub hook: HookKind,
+
+        #[arg(
+            short,
+            long,
+            help = "name of test for the hook to run (default is all defined tests)"
+        )]
+        pub test: Option<String>,
+    }
+
+    #[derive(Args)]
+    pub struct UninstallHookArgs {
+        #[arg(value_enum, help = "which hook to uninstall")]
+        pub hook: HookKind,
+    }
+
+    #[derive(Args)]
+    pub struct RemoveArgs {
+        #[arg(
+            short,
+            long,
+            default_value = "default",
+            help = "name of test to remove (default is 'default')"
+        )]
+        pub test: String,
+    }
+
+    #[derive(Args)]
+    pub struct StatsArgs {
+        #[command(flatten)]
+        pub selection: TestSelectionArgs,
+
+        #[arg(help = "commits or ranges of commits to summarize (default is every stored result)")]
+        pub commits: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct LogArgs {
+        #[command(flatten)]
+        pub selection: TestSelectionArgs,
+
+        #[arg(
+            help = "commits or ranges of commits to show (default is the last 20 commits of HEAD)"
+        )]
+        pub commits: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct ShowArgs {
+        #[arg(default_value = "HEAD", help = "commit to show (default is HEAD)")]
+        pub commit: String,
+    }
+
+    #[derive(Args)]
+    pub struct RenameArgs {
+        #[arg(help = "current name of the test")]
+        pub old: String,
+
+        #[arg(help = "new name for the test")]
+        pub new: String,
+    }
+
+    #[derive(Args)]
+    pub struct CopyArgs {
+        #[arg(help = "name of the test to copy")]
+        pub old: String,
+
+        #[arg(help = "name for the copy")]
+        pub new: String,
+
+        #[arg(long, help = "also copy the stored results")]
+        pub with_results: bool,
+    }
+}
+
+pub mod commands {
+    use crate::git::{GitRepository, GitSha};
+    use anyhow::{Context, Result};
+    use futures::future::{self, BoxFuture, FutureExt, Shared};
+    use log::{info, warn};
+    use std::io::BufRead;
+
+    /// Completes on the first Ctrl-C; every clone completes with it.
+    pub type Interrupt = Shared<BoxFuture<'static, ()>>;
+
+    /// Listens for Ctrl-C from the first time it's polled, never completing if it can't.
+    pub fn interrupt() -> Interrupt {
+        async {
+            if tokio::signal::ctrl_c().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+        .boxed()
+        .shared()
+    }
+
+    /// Resolves the commits named on the command line (or on stdin, one per line) into SHAs,
+    /// defaulting to `HEAD` when none are given.
+    pub(crate) async fn resolve_commit_args(
+        repo: &GitRepository,
+        stdin: bool,
+        commits: &[String],
+    ) -> Result<Vec<GitSha>> {
+        let mut specs = commits.to_vec();
+        if stdin {
+            for line in std::io::stdin().lock().lines() {
+                let line = line.context("Failed to read commits from standard input")?;
+                let line = line.trim();
+                if !line.is_empty() {
+                    specs.push(line.to_string());
+                }
+            }
+        }
+
+        if specs.is_empty() {
+            specs.push("HEAD".to_string());
+        }
+
+        repo.resolve_commits(&specs).await
+    }
+
+    pub mod add {
+        use super::*;
+        use crate::commands::forget_results::forget_results;
+
+        use anyhow::{Context, Result};
+        use log::{info, warn};
+
+        pub async fn cmd_add(
+            repo: &GitRepository,
+            test: &str,
+            forget: bool,
+            keep: bool,
+            command: &str,
+        ) -> Result<()> {
+            let existing_command = repo.get_test_command(test).await;
+            let had_existing_command = existing_command.is_ok();
+
+            let old_command = existing_command
+                .map(|cmd| cmd.test_command.to_string())
+                .unwrap_or_else(|_| "<empty>".to_string());
+
+            if !forget && !keep && had_existing_command {
+                warn!(
+                    "Overwriting existing test '{}'. Use --forget to delete stored results or --keep to preserve them.",
+                    test
+                );
+            }
+
+            if forget {
+                forget_results(repo, test)
+                    .await
+                    .with_context(|| format!("Failed to delete stored results for '{}'", test))?;
+            }
+
+            repo.set_test_command(test, command)
+                .await
+                .with_context(|| format!("Failed to set test command for '{}'", test))?;
+
+            info!(
+                "Changing test '{}' from '{}' to '{}'",
+                test, old_command, command
+            );
+
+            Ok(())
+        }
+    }
+
+    pub mod forget_results {
+        use super::*;
+        use crate::git::test_notes_ref;
+
+        pub async fn cmd_forget_results(repo: &GitRepository, test: &str) -> Result<()> {
+            info!("Forgetting results for test '{}'", test);
+            forget_results(repo, test).await?;
+            // The results of a matrix test are stored per variant. The test itself need not
+            // be defined anymore.
+            let tests = repo.list_tests().await?;
+            for variant in tests
+                .iter()
+                .filter(|t| t.variant_of.as_deref() == Some(test))
+            {
+                forget_results(repo, &variant.test_name).await?;
+            }
+            Ok(())
+        }
+
+        pub(crate) async fn forget_results(repo: &GitRepository, test: &str) -> Result<()> {
+            repo.delete_ref(&test_notes_ref(test))
+                .await
+                .with_context(|| format!("Failed to delete stored results for '{}'", test))
+        }
+    }
+
+    pub mod gc {
+        use super::*;
+        use crate::git::{TestNote, TESTS_NOTES_PREFIX};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        /// Removes stored results for trees that are no longer reachable from any ref, such as
+        /// those of abandoned branches or rebased-away commits. With `older_than`, only results
+        /// recorded longer ago than that are removed. Each notes ref gets one commit removing
+        /// them; its history still holds them, so this frees no space by itself.
+        pub async fn cmd_gc(
+            repo: &GitRepository,
+            older_than: Option<Duration>,
+            dry_run: bool,
+        ) -> Result<()> {
+            let reachable = repo.reachable_objects().await?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            let cutoff = older_than.map(|age| now.saturating_sub(age.as_secs()));
+
+            let (mut total_entries, mut total_bytes) = (0, 0);
+            for notes_ref in repo.list_refs(TESTS_NOTES_PREFIX).await? {
+                let test_name = &notes_ref[TESTS_NOTES_PREFIX.len()..];
+                let mut unreachable: Vec<(String, String)> = repo
+                    .list_notes(&notes_ref)
+                    .await?
+                    .into_iter()
+                    .filter(|(_, object)| !reachable.contains(object))
+                    .collect();
+                if let Some(cutoff) = cutoff {
+                    let blobs: Vec<String> =
+                        unreachable.iter().map(|(blob, _)| blob.clone()).collect();
+                    let contents = repo.read_blobs(&blobs).await?;
+                    let mut contents = contents.iter();
+                    unreachable.retain(|_| {
+                        // Notes that can't be parsed have no timestamp; treat them as old.
+                        let content = contents.next().map(String::as_str).unwrap_or_default();
+                        TestNote::parse(content).map_or(0, |note| note.timestamp) < cutoff
+                    });
+                }
+                let (blobs, objects): (Vec<String>, Vec<String>) = unreachable.into_iter().unzip();
+
+                let entries = objects.len();
+                let bytes: u64 = repo.blob_sizes(&blobs).await?.iter().sum();
+                if !dry_run && entries > 0 {
+                    repo.remove_notes(&notes_ref, &objects).await?;
+                }
+
+                if entries > 0 {
+                    info!(
+                        "{} {} results ({} bytes) for test '{}'",
+                        if dry_run { "Would prune" } else { "Pruned" },
+                        entries,
+                        bytes,
+                        test_name
+                    );
+                }
+                total_entries += entries;
+                total_bytes += bytes;
+            }
+
+            info!(
+                "{} {} results ({} bytes) in total",
+                if dry_run { "Would prune" } else { "Pruned" },
+                total_entries,
+                total_bytes
+            );
+            if !dry_run && total_entries > 0 {
+                info!(
+                    "The history of the notes refs still holds them; their space is only freed \
+                     once that history is rewritten"
+                );
+            }
+            Ok(())
+        }
+    }
+
+    pub mod history {
+        use super::*;
+        use crate::git::{GitTestCommand, TestStatus};
+        use crate::output::{print_records, OutputFormat};
+        use crate::runner::TestSelection;
+        use colored::*;
+        use serde::Serialize;
+        use std::collections::BTreeMap;
+
+        /// How many commits of `HEAD`'s history are shown when no commits are given.
+        const DEFAULT_LOG_LENGTH: usize = 20;
+
+        /// One commit as reported by `git test log --format json`.
+        #[derive(Debug, Serialize)]
+        pub struct LogRecord {
+            pub commit: String,
+            pub subject: String,
+            /// `good`, `bad`, `flaky` or `unknown` for each test.
+            pub results: BTreeMap<String, String>,
+        }
+
+        fn status_symbol(status: Option<TestStatus>) -> ColoredString {
+            match status {
+                Some(TestStatus::Good) => "✓".green(),
+                Some(TestStatus::Bad) => "✗".red(),
+                Some(TestStatus::Flaky) => "~".yellow(),
+                None => "?".dimmed(),
+            }
+        }
+
+        /// Prints the history of each of `commits`, as `git log --graph` would, with a column per
+        /// test showing its stored result. As with `run`, a range such as `main..feature` shows
+        /// every commit in it and any other revision shows just that commit.
+        pub async fn cmd_log(
+            repo: &GitRepository,
+            selection: &TestSelection,
+            commits: &[String],
+            format: OutputFormat,
+        ) -> Result<()> {
+            if format == OutputFormat::Tap {
+                anyhow::bail!("TAP output is only available for run and results");
+            }
+
+            let selection = if selection.is_empty() {
+                TestSelection::all()
+            } else {
+                selection.clone()
+            };
+            let tests = selection.resolve(repo).await?;
+
+            let mut lines = Vec::new();
+            if commits.is_empty() {
+                lines.extend(repo.log_graph("HEAD", Some(DEFAULT_LOG_LENGTH)).await?);
+            }
+            for spec in commits {
+                let max_count = if spec.contains("..") { None } else { Some(1) };
+                lines.extend(repo.log_graph(spec, max_count).await?);
+            }
+
+            let mut records = Vec::new();
+            for line in &lines {
+                let Some(sha) = &line.commit else {
+                    records.push((line, Vec::new()));
+                    continue;
+                };
+                let mut statuses = Vec::new();
+                for git_test_command in &tests {
+                    statuses.push(git_test_command.get_result(sha.as_str()).await?);
+                }
+                records.push((line, statuses));
+            }
+
+            if !format.is_human() {
+                let records: Vec<LogRecord> = records
+                    .into_iter()
+                    .filter_map(|(line, statuses)| {
+                        Some(log_record(
+                            &tests,
+                            line.commit.as_ref()?,
+                            &line.subject,
+                            &statuses,
+                        ))
+                    })
+                    .collect();
+                return print_records(format, &records);
+            }
+
+            if tests.is_empty() {
+                warn!("No tests defined.");
+            } else {
+                let names: Vec<&str> = tests.iter().map(|t| t.test_name.as_str()).collect();
+                info!("{}", format!("Columns: {}", names.join(", ")).dimmed());
+            }
+            let blank = " ".repeat(tests.len() * 2);
+            for (line, statuses) in records {
+                match &line.commit {
+                    Some(sha) => {
+                        let columns: Vec<String> = statuses
+                            .into_iter()
+                            .map(|status| status_symbol(status).to_string())
+                            .collect();
+                        info!(
+                            "{} {}{} {}",
+                            columns.join(" "),
+                            line.graph,
+                            sha.short().yellow(),
+                            line.subject
+                        );
+                    }
+                    None => info!("{}{}", blank, line.graph.trim_end()),
+                }
+            }
+            Ok(())
+        }
+
+        fn log_record(
+            tests: &[GitTestCommand],
+            sha: &GitSha,
+            subject: &str,
+            statuses: &[Option<TestStatus>],
+        ) -> LogRecord {
+            LogRecord {
+                commit: sha.as_str().to_string(),
+                subject: subject.to_string(),
+                results: tests
+                    .iter()
+                    .zip(statuses)
+                    .map(|(test, status)| {
+                        let status = status.as_ref().map_or("unknown", TestStatus::as_str);
+                        (test.test_name.clone(), status.to_string())
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    pub mod install_hook {
+        use super::*;
+        use crate::cli::HookKind;
+        use std::path::{Path, PathBuf};
+
+        /// Identifies hook scripts written by `git test install-hook`.
+        const HOOK_MARKER: &str = "# Installed by git-test install-hook.";
+
+        /// The suffix under which a pre-existing hook is kept, so that ours can chain to it.
+        const CHAINED_SUFFIX: &str = ".git-test-chained";
+
+        pub async fn cmd_install_hook(
+            repo: &GitRepository,
+            hook: HookKind,
+            test: Option<&str>,
+        ) -> Result<()> {
+            if let Some(test) = test {
+                repo.select_tests(test).await?;
+            }
+
+            let (hook_path, chained_path) = hook_paths(repo, hook).await?;
+            if let Some(parent) = hook_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+
+            if hook_path.exists() && !is_ours(&hook_path)? {
+                if chained_path.exists() {
+                    anyhow::bail!(
+                        "Cannot install the {} hook: both {} and {} already exist",
+                        hook.name(),
+                        hook_path.display(),
+                        chained_path.display()
+                    );
+                }
+                std::fs::rename(&hook_path, &chained_path).with_context(|| {
+                    format!("Failed to move the existing {} hook aside", hook.name())
+                })?;
+                info!(
+                    "Existing {} hook moved to {}; it will run first",
+                    hook.name(),
+                    chained_path.display()
+                );
+            }
+
+            write_executable(&hook_path, &pre_push_script(test))?;
+            info!("Installed {} hook at {}", hook.name(), hook_path.display());
+            Ok(())
+        }
+
+        pub async fn cmd_uninstall_hook(repo: &GitRepository, hook: HookKind) -> Result<()> {
+            let (hook_path, chained_path) = hook_paths(repo, hook).await?;
+            if !hook_path.exists() || !is_ours(&hook_path)? {
+                anyhow::bail!("No {} hook installed by git-test", hook.name());
+            }
+
+            std::fs::remove_file(&hook_path)
+                .with_context(|| format!("Failed to remove {}", hook_path.display()))?;
+            if chained_path.exists() {
+                std::fs::rename(&chained_path, &hook_path).with_context(|| {
+                    format!("Failed to restore the original {} hook", hook.name())
+                })?;
+                info!("Restored the original {} hook", hook.name());
+            }
+            info!("Uninstalled {} hook", hook.name());
+            Ok(())
+        }
+
+        async fn hook_paths(repo: &GitRepository, hook: HookKind) -> Result<(PathBuf, PathBuf)> {
+            let hooks_dir = repo.git_path("hooks").await?;
+            Ok((
+                hooks_dir.join(hook.name()),
+                hooks_dir.join(format!("{}{}", hook.name(), CHAINED_SUFFIX)),
+            ))
+        }
+
+        fn is_ours(hook_path: &Path) -> Result<bool> {
+            let contents = std::fs::read(hook_path)
+                .with_context(|| format!("Failed to read {}", hook_path.display()))?;
+            Ok(String::from_utf8_lossy(&contents).contains(HOOK_MARKER))
+        }
+
+        fn write_executable(path: &Path, contents: &str) -> Result<()> {
+            std::fs::write(path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+                    .with_context(|| format!("Failed to make {} executable", path.display()))?;
+            }
+            Ok(())
+        }
+
+        /// A pre-push hook that tests, for each ref being pushed, the commits that the remote
+        /// doesn't have yet, and refuses the push if any of them is bad. Without a `test`, the
+        /// hook runs every test, and lets pushes through while there are none defined.
+        fn pre_push_script(test: Option<&str>) -> String {
+            let (test_args, no_tests_check) = match test {
+                Some(test) => (format!("--test={}", crate::shell::quote(test)), ""),
+                None => (
+                    "--all".to_string(),
+                    r#"
+# Nothing to test until a test is defined.
+tests=$(git test list --format ndjson) || exit $?
+test -n "$tests" || exit 0
+"#,
+                ),
+            };
+            format!(
+                r#"#!/bin/sh
+{marker}
+# Remove it with `git test uninstall-hook pre-push`.
+
+remote="$1"
+input=$(cat)
+
+chained="$(dirname "$0")/pre-push{chained_suffix}"
+if test -x "$chained"; then
+    printf '%s\n' "$input" | "$chained" "$@" || exit $?
+fi
+{no_tests_check}
+is_null() {{
+    case "$1" in
+        *[!0]*) return 1 ;;
+        *) return 0 ;;
+    esac
+}}
+
+printf '%s\n' "$input" | while read -r local_ref local_sha remote_ref remote_sha; do
+    test -n "$local_sha" || continue
+    # Nothing to test when a ref is being deleted.
+    is_null "$local_sha" && continue
+
+    if is_null "$remote_sha"; then
+        commits=$(git rev-list --reverse "$local_sha" --not --remotes="$remote")
+    else
+        commits=$(git rev-list --reverse "$remote_sha..$local_sha")
+    fi
+    test -n "$commits" || continue
+
+    echo "git test: testing commits to be pushed to $remote_ref"
+    printf '%s\n' "$commits" | git test run --keep-going {test_args} --stdin || {{
+        echo "git test: refusing to push $local_ref; a commit failed its tests" >&2
+        exit 1
+    }}
+done
+"#,
+                marker = HOOK_MARKER,
+                chained_suffix = CHAINED_SUFFIX,
+                no_tests_check = no_tests_check,
+                test_args = test_args,
+            )
+        }
+    }
+
+    pub mod list {
+        use super::*;
+        use crate::git::{GitTestCommand, TestSource};
+        use crate::output::{print_records, OutputFormat};
+        use colored::*;
+        use serde::Serialize;
+        use std::collections::BTreeMap;
+
+        /// A test definition as reported by `git test list --format json`.
+        #[derive(Debug, Serialize)]
+        pub struct TestDefinition {
+            pub name: String,
+            pub command: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub tree: Option<String>,
+            /// The timeout in seconds.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub timeout: Option<u64>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub env: BTreeMap<String, String>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub tags: Vec<String>,
+            pub source: TestSource,
+            /// The matrix test this is a variant of.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub variant_of: Option<String>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub params: BTreeMap<String, String>,
+        }
+
+        impl From<&GitTestCommand> for TestDefinition {
+            fn from(git_test_command: &GitTestCommand) -> Self {
+                TestDefinition {
+                    name: git_test_command.test_name.clone(),
+                    command: git_test_command.test_command.clone(),
+                    tree: git_test_command.tree.clone(),
+                    timeout: git_test_command.timeout.map(|timeout| timeout.as_secs()),
+                    env: git_test_command.env.iter().cloned().collect(),
+                    tags: git_test_command.tags.clone(),
+                    source: git_test_command.source,
+                    variant_of: git_test_command.variant_of.clone(),
+                    params: git_test_command.params.iter().cloned().collect(),
+                }
+            }
+        }
+
+        pub async fn cmd_list(repo: &GitRepository, format: OutputFormat) -> Result<()> {
+            let tests = repo.list_tests().await?;
+
+            if format == OutputFormat::Tap {
+                anyhow::bail!("TAP output is only available for run and results");
+            }
+
+            if !format.is_human() {
+                let definitions: Vec<TestDefinition> =
+                    tests.iter().map(TestDefinition::from).collect();
+                return print_records(format, &definitions);
+            }
+
+            if tests.is_empty() {
+                warn!("No tests defined.");
+            } else {
+                for git_test_command in tests {
+                    info!("{}:", git_test_command.test_name.bold());
+                    info!("    command = {}", git_test_command.test_command.green());
+                    if let Some(tree) = &git_test_command.tree {
+                        info!("    tree = {}", tree.green());
+                    }
+                    if let Some(timeout) = git_test_command.timeout {
+                        info!(
+                            "    timeout = {}",
+                            format!("{}s", timeout.as_secs()).green()
+                        );
+                    }
+                    for (key, value) in &git_test_command.env {
+                        info!("    env {} = {}", key, value.green());
+                    }
+                    if !git_test_command.tags.is_empty() {
+                        info!("    tags = {}", git_test_command.tags.join(", ").green());
+                    }
+                    if let Some(matrix) = &git_test_command.variant_of {
+                        info!("    variant of = {}", matrix.green());
+                    }
+                    for (name, value) in &git_test_command.params {
+                        info!("    param {} = {}", name, value.green());
+                    }
+                    info!(
+                        "    source = {}",
+                        git_test_command.source.describe().dimmed()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub mod push {
+        use super::*;
+        use crate::git::TESTS_NOTES_PREFIX;
+
+        /// Pushes without forcing: `git test fetch` merges the remote's results into commits
+        /// that descend from them, so after fetching, the local notes fast-forward.
+        pub async fn cmd_push(repo: &GitRepository, remote: &str) -> Result<()> {
+            let refspec = format!("{0}*:{0}*", TESTS_NOTES_PREFIX);
+            repo.push(remote, &refspec).await.with_context(|| {
+                format!(
+                    "'{0}' may have results that aren't merged here yet; run 'git test fetch {0}' first",
+                    remote
+                )
+            })?;
+            info!("Pushed test results to '{}'", remote);
+            Ok(())
+        }
+    }
+
+    pub mod fetch {
+        use super::*;
+        use crate::commands::merge_notes::log_merge_summary;
+        use crate::git::TESTS_NOTES_PREFIX;
+
+        /// Where fetched notes are staged before being merged into the local notes refs.
+        const INCOMING_NOTES_PREFIX: &str = "refs/notes/tests-incoming/";
+
+        pub async fn cmd_fetch(repo: &GitRepository, remote: &str) -> Result<()> {
+            // Force-update the staging refs so that a remote that has diverged from our local
+            // results is never refused as a non-fast-forward; we merge below instead.
+            let refspec = format!("+{}*:{}*", TESTS_NOTES_PREFIX, INCOMING_NOTES_PREFIX);
+            repo.fetch(remote, &refspec).await?;
+
+            for incoming_ref in repo.list_refs(INCOMING_NOTES_PREFIX).await? {
+                let test_name = &incoming_ref[INCOMING_NOTES_PREFIX.len()..];
+                let local_ref = format!("{}{}", TESTS_NOTES_PREFIX, test_name);
+
+                let summary = repo.merge_result_notes(&local_ref, &incoming_ref).await?;
+                repo.delete_ref(&incoming_ref).await?;
+
+                log_merge_summary(test_name, remote, &summary);
+            }
+
+            Ok(())
+        }
+    }
+
+    pub mod merge_notes {
+        use super::*;
+        use crate::git::{test_notes_ref, NotesMergeSummary};
+
+        pub async fn cmd_merge_notes(
+            repo: &GitRepository,
+            test: &str,
+            incoming_ref: &str,
+        ) -> Result<()> {
+            let summary = repo
+                .merge_result_notes(&test_notes_ref(test), incoming_ref)
+                .await?;
+            log_merge_summary(test, incoming_ref, &summary);
+            Ok(())
+        }
+
+        pub(crate) fn log_merge_summary(test: &str, source: &str, summary: &NotesMergeSummary) {
+            info!(
+                "Merged results for test '{}' from '{}': {} added, {} merged, {} unchanged",
+                test, source, summary.added, summary.merged, summary.unchanged
+            );
+        }
+    }
+
+    pub mod remove {
+        use super::*;
+        use crate::commands::forget_results::forget_results;
+        use crate::definitions::DEFINITIONS_FILE;
+        use crate::git::TestSource;
+
+        pub async fn cmd_remove(repo: &GitRepository, test: &str) -> Result<()> {
+            let tests = repo.select_tests(test).await?;
+            if let Some(matrix) = tests[0].variant_of.as_deref().filter(|name| *name != test) {
+                anyhow::bail!(
+                    "Test '{}' is a variant of matrix test '{}'; remove '{}' instead",
+                    test,
+                    matrix,
+                    matrix
+                );
+            }
+
+            match tests[0].source {
+                TestSource::File => anyhow::bail!(
+                    "Test '{}' is defined in {}; remove it from there instead",
+                    test,
+                    DEFINITIONS_FILE
+                ),
+                TestSource::ConfigOverFile => {
+                    info!(
+                        "Removing the local override of test '{}'; it is still defined in {}",
+                        test, DEFINITIONS_FILE
+                    );
+                    repo.remove_test(test).await
+                }
+                TestSource::Config => {
+                    info!("Removing test '{}'", test);
+                    forget_results(repo, test).await?;
+                    for variant in tests.iter().filter(|t| t.variant_of.is_some()) {
+                        forget_results(repo, &variant.test_name).await?;
+                    }
+                    repo.remove_test(test).await
+                }
+            }
+        }
+    }
+
+    pub mod copy {
+        use super::*;
+        use crate::definitions::{variant_name, DEFINITIONS_FILE};
+        use crate::git::{test_notes_ref, TestSource};
+
+        /// Checks that `old` can be copied or renamed to `new`, returning each of `old`'s notes
+        /// refs that exists, paired with the ref its results would move to.
+        pub(crate) async fn plan_copy(
+            repo: &GitRepository,
+            old: &str,
+            new: &str,
+        ) -> Result<Vec<(String, String)>> {
+            let tests = repo.select_tests(old).await?;
+            if let Some(matrix) = tests[0].variant_of.as_deref().filter(|name| *name != old) {
+                anyhow::bail!(
+                    "Test '{}' is a variant of matrix test '{}'; use '{}' instead",
+                    old,
+                    matrix,
+                    matrix
+                );
+            }
+            if tests[0].source != TestSource::Config {
+                anyhow::bail!(
+                    "Test '{}' is defined in {}; change it there instead",
+                    old,
+                    DEFINITIONS_FILE
+                );
+            }
+            if repo.select_tests(new).await.is_ok() {
+                anyhow::bail!("Test '{}' already exists", new);
+            }
+
+            let mut refs = Vec::new();
+            for test in &tests {
+                let new_ref = match test.variant_of {
+                    Some(_) => test_notes_ref(&variant_name(new, &test.params)),
+                    None => test_notes_ref(new),
+                };
+                if repo.ref_exists(&new_ref).await? {
+                    anyhow::bail!(
+                        "There are already results stored in '{}'; use `git test forget-results` \
+                         to delete them first",
+                        new_ref
+                    );
+                }
+                if repo.ref_exists(&test.notes_ref()).await? {
+                    refs.push((test.notes_ref(), new_ref));
+                }
+            }
+            Ok(refs)
+        }
+
+        /// Copies every `test.<old>.<key>` setting to `test.<new>`. If that fails, whatever was
+        /// copied is removed again.
+        pub(crate) async fn copy_definition(
+            repo: &GitRepository,
+            old: &str,
+            new: &str,
         ) -> Result<()> {
-            let existing_command = repo.get_test_command(test).await;
-            let had_existing_command = existing_command.is_ok();
+            let prefix = format!("test.{}.", old);
+            // Matched here rather than in the pattern, which git reads as a POSIX regex. Keys
+            // have no dots, so this leaves out the settings of tests like `<old>.b`.
+            let entries = repo.get_config_regexp("^test\\.").await?;
+            let names = entries.iter().filter_map(|(key, value)| {
+                let name = key.strip_prefix(&prefix)?;
+                (!name.contains('.')).then_some((name, value))
+            });
+            for (name, value) in names {
+                let copied = repo
+                    .add_config_value(&format!("test.{}.{}", new, name), value)
+                    .await;
+                if let Err(err) = copied {
+                    let _ = repo.remove_test(new).await;
+                    return Err(err);
+                }
+            }
+            Ok(())
+        }
+
+        /// Points each new ref in `refs` at the results in the old one. If that fails, the new
+        /// refs that were created are deleted again.
+        pub(crate) async fn copy_results(
+            repo: &GitRepository,
+            refs: &[(String, String)],
+        ) -> Result<()> {
+            for (i, (old_ref, new_ref)) in refs.iter().enumerate() {
+                let copied = async {
+                    let target = repo
+                        .backend()
+                        .rev_parse(old_ref)
+                        .await?
+                        .with_context(|| format!("Failed to resolve '{}'", old_ref))?;
+                    repo.create_ref(new_ref, &target).await
+                };
+                if let Err(err) = copied.await {
+                    for (_, new_ref) in &refs[..i] {
+                        let _ = repo.delete_ref(new_ref).await;
+                    }
+                    return Err(err);
+                }
+            }
+            Ok(())
+        }
+
+        pub async fn cmd_copy(
+            repo: &GitRepository,
+            old: &str,
+            new: &str,
+            with_results: bool,
+        ) -> Result<()> {
+            let refs = plan_copy(repo, old, new).await?;
+
+            copy_definition(repo, old, new)
+                .await
+                .with_context(|| format!("Failed to copy the definition of '{}'", old))?;
+            if with_results {
+                if let Err(err) = copy_results(repo, &refs).await {
+                    let _ = repo.remove_test(new).await;
+                    return Err(err)
+                        .with_context(|| format!("Failed to copy results of '{}'", old));
+                }
+                info!("Copied test '{}' and its results to '{}'", old, new);
+            } else {
+                info!("Copied test '{}' to '{}'", old, new);
+            }
+            Ok(())
+        }
+    }
+
+    pub mod rename {
+        use super::*;
+        use crate::commands::copy::plan_copy;
+
+        /// Renames test `old` to `new`, moving its results along with it. The results move in
+        /// one ref transaction and the definition in one config edit; if the latter fails, the
+        /// results are moved back.
+        pub async fn cmd_rename(repo: &GitRepository, old: &str, new: &str) -> Result<()> {
+            let refs = plan_copy(repo, old, new).await?;
+
+            repo.move_refs(&refs)
+                .await
+                .with_context(|| format!("Failed to move results of '{}'", old))?;
+            if let Err(err) = repo.rename_test(old, new).await {
+                let moved_back: Vec<(String, String)> = refs
+                    .into_iter()
+                    .map(|(old_ref, new_ref)| (new_ref, old_ref))
+                    .collect();
+                let _ = repo.move_refs(&moved_back).await;
+                return Err(err);
+            }
+            info!("Renamed test '{}' to '{}'", old, new);
+            Ok(())
+        }
+    }
+
+    pub mod show {
+        use super::*;
+        use crate::commands::results::status_label;
+        use crate::git::{GitTestCommand, TestNote};
+        use crate::output::{format_timestamp, print_records, OutputFormat};
+        use colored::*;
+        use serde::Serialize;
+
+        /// How many lines of stored output `show` prints.
+        const OUTPUT_TAIL_LINES: usize = 10;
+
+        /// Everything stored about one test's result for a commit, as reported by
+        /// `git test show --format json`.
+        #[derive(Debug, Default, Serialize)]
+        pub struct ResultDetail {
+            pub commit: String,
+            pub test: String,
+            /// `good`, `bad`, `flaky` or `unknown`.
+            pub status: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub exit_code: Option<i32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub duration_ms: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub timestamp: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub host: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub fingerprint: Option<String>,
+            /// Whether the test's definition has changed since the result was recorded, if known.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub definition_changed: Option<bool>,
+            /// The commit the test actually ran on, when the result was recorded for another
+            /// commit with the same tree.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub inherited_from: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stdout_tail: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stderr_tail: Option<String>,
+            /// Where the complete output was spilled, on the machine that ran the test.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stdout_file: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stderr_file: Option<String>,
+        }
+
+        impl ResultDetail {
+            pub fn new(
+                git_test_command: &GitTestCommand,
+                sha: &GitSha,
+                note: Option<TestNote>,
+            ) -> Self {
+                let Some(note) = note else {
+                    return ResultDetail {
+                        commit: sha.as_str().to_string(),
+                        test: git_test_command.test_name.clone(),
+                        status: "unknown".to_string(),
+                        ..Default::default()
+                    };
+                };
+                ResultDetail {
+                    commit: sha.as_str().to_string(),
+                    test: git_test_command.test_name.clone(),
+                    status: note.status.as_str().to_string(),
+                    exit_code: note.exit_code,
+                    duration_ms: note.duration_ms,
+                    // Notes written before results were structured have no timestamp.
+                    timestamp: Some(note.timestamp).filter(|timestamp| *timestamp > 0),
+                    host: note.host,
+                    definition_changed: note
+                        .fingerprint
+                        .as_ref()
+                        .map(|fingerprint| *fingerprint != git_test_command.fingerprint()),
+                    fingerprint: note.fingerprint,
+                    inherited_from: note.commit.filter(|commit| commit != sha.as_str()),
+                    stdout_tail: note.stdout.as_deref().and_then(tail),
+                    stderr_tail: note.stderr.as_deref().and_then(tail),
+                    stdout_file: note.stdout_file,
+                    stderr_file: note.stderr_file,
+                }
+            }
+        }
+
+        /// The last few lines of `output`, or `None` if it is blank.
+        fn tail(output: &str) -> Option<String> {
+            let lines: Vec<&str> = output.trim_end().lines().collect();
+            if lines.is_empty() {
+                return None;
+            }
+            Some(lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n"))
+        }
+
+        pub async fn cmd_show(
+            repo: &GitRepository,
+            commit: &str,
+            format: OutputFormat,
+        ) -> Result<()> {
+            if format == OutputFormat::Tap {
+                anyhow::bail!("TAP output is only available for run and results");
+            }
+            if commit.contains("..") {
+                anyhow::bail!("show takes a single commit, not a range");
+            }
+
+            let sha = repo.resolve_commits(&[commit.to_string()]).await?.remove(0);
+            let mut details = Vec::new();
+            for git_test_command in repo.list_tests().await? {
+                let note = git_test_command.get_result_note(sha.as_str()).await?;
+                details.push((
+                    git_test_command.tree.clone(),
+                    ResultDetail::new(&git_test_command, &sha, note),
+                ));
+            }
+
+            if !format.is_human() {
+                let details: Vec<ResultDetail> =
+                    details.into_iter().map(|(_, detail)| detail).collect();
+                return print_records(format, &details);
+            }
+
+            let subject = repo.commit_subjects(std::slice::from_ref(&sha)).await?;
+            info!(
+                "{} {}",
+                sha.as_str().yellow(),
+                subject.first().map(String::as_str).unwrap_or_default()
+            );
+            if details.is_empty() {
+                warn!("No tests defined.");
+            }
+            for (tree, detail) in details {
+                info!("{}: {}", detail.test.bold(), status_label(&detail.status));
+                if let Some(exit_code) = detail.exit_code {
+                    info!("    exit code = {}", exit_code);
+                }
+                if let Some(duration_ms) = detail.duration_ms {
+                    info!("    duration = {:.1}s", duration_ms as f64 / 1000.0);
+                }
+                if let Some(timestamp) = detail.timestamp {
+                    match &detail.host {
+                        Some(host) => {
+                            info!("    ran = {} UTC on {}", format_timestamp(timestamp), host)
+                        }
+                        None => info!("    ran = {} UTC", format_timestamp(timestamp)),
+                    }
+                }
+                if let Some(fingerprint) = &detail.fingerprint {
+                    let note = match detail.definition_changed {
+                        Some(true) => " (the test has changed since)".red(),
+                        _ => "".normal(),
+                    };
+                    info!("    command = {}{}", fingerprint, note);
+                }
+                if let Some(inherited_from) = &detail.inherited_from {
+                    let tree = match &tree {
+                        Some(subdir) => format!("same '{}' tree", subdir),
+                        None => "same tree".to_string(),
+                    };
+                    info!("    inherited from {} ({})", inherited_from, tree);
+                }
+                for (name, output, file) in [
+                    ("stdout", &detail.stdout_tail, &detail.stdout_file),
+                    ("stderr", &detail.stderr_tail, &detail.stderr_file),
+                ] {
+                    if let Some(output) = output {
+                        let lines = output.lines().count();
+                        let plural = if lines == 1 { "line" } else { "lines" };
+                        info!("    {} (last {} {}):", name, lines, plural);
+                        for line in output.lines() {
+                            info!("        {}", line.dimmed());
+                        }
+                    }
+                    if let Some(file) = file {
+                        info!("    all of the {} is in {}", name, file);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub mod stats {
+        use super::*;
+        use crate::git::{GitTestCommand, TestNote, TestStatus};
+        use crate::output::{print_records, OutputFormat};
+        use crate::runner::TestSelection;
+        use colored::*;
+        use serde::Serialize;
+        use std::collections::HashSet;
+
+        /// How many of the slowest commits are listed for each test.
+        const SLOWEST_COMMITS: usize = 5;
+
+        /// Aggregated results of one test, as reported by `git test stats --format json`.
+        #[derive(Debug, Serialize)]
+        pub struct TestStats {
+            pub test: String,
+            pub good: usize,
+            pub bad: usize,
+            pub flaky: usize,
+            /// Commits in the range without a stored result. Always 0 for the whole repository.
+            pub unknown: usize,
+            /// How many results recorded a duration.
+            pub timed: usize,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub mean_ms: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub p95_ms: Option<u64>,
+            /// How much slower, in percent, the newer half of the timed results is than the
+            /// older half. Negative when the test got faster.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub trend_percent: Option<f64>,
+            pub slowest: Vec<SlowCommit>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub longest_bad_streak: Option<BadStreak>,
+        }
+
+        #[derive(Debug, Clone, Serialize)]
+        pub struct SlowCommit {
+            pub commit: GitSha,
+            pub duration_ms: u64,
+        }
+
+        /// A run of consecutive bad results.
+        #[derive(Debug, Clone, Serialize)]
+        pub struct BadStreak {
+            pub length: usize,
+            pub first: GitSha,
+            pub last: GitSha,
+        }
+
+        /// A result to aggregate: the commit it belongs to, and the stored note, if any.
+        struct Entry {
+            commit: GitSha,
+            note: Option<TestNote>,
+        }
+
+        /// Prints statistics about the stored results of the selected tests (all tests unless
+        /// some are selected). With `commits`, the results of those commits are aggregated in
+        /// history order; otherwise every stored result is, in the order they were recorded.
+        pub async fn cmd_stats(
+            repo: &GitRepository,
+            selection: &TestSelection,
+            commits: &[String],
+            format: OutputFormat,
+        ) -> Result<()> {
+            if format == OutputFormat::Tap {
+                anyhow::bail!("TAP output is only available for run and results");
+            }
+
+            let selection = if selection.is_empty() {
+                TestSelection::all()
+            } else {
+                selection.clone()
+            };
+            let tests = selection.resolve(repo).await?;
+            let shas = if commits.is_empty() {
+                None
+            } else {
+                Some(repo.resolve_commits(commits).await?)
+            };
+
+            let mut stats = Vec::new();
+            for git_test_command in &tests {
+                let entries = match &shas {
+                    Some(shas) => range_entries(repo, git_test_command, shas).await?,
+                    None => stored_entries(repo, git_test_command).await?,
+                };
+                stats.push(test_stats(&git_test_command.test_name, &entries));
+            }
+
+            if !format.is_human() {
+                return print_records(format, &stats);
+            }
+
+            if stats.is_empty() {
+                warn!("No tests defined.");
+            }
+            for test_stats in &stats {
+                print_test_stats(test_stats);
+            }
+            Ok(())
+        }
+
+        /// The result of each commit in `shas`, in order. Commits whose result is keyed on the
+        /// same object as an earlier one's share its result, so only the first is counted.
+        async fn range_entries(
+            repo: &GitRepository,
+            git_test_command: &GitTestCommand,
+            shas: &[GitSha],
+        ) -> Result<Vec<Entry>> {
+            let notes_ref = git_test_command.notes_ref();
+            let mut seen = HashSet::new();
+            let mut entries = Vec::new();
+            for sha in shas {
+                let object = git_test_command.result_object(sha.as_str()).await?;
+                let resolved = repo.backend().rev_parse(&object).await?;
+                if !seen.insert(resolved.unwrap_or_else(|| object.clone())) {
+                    continue;
+                }
+                let note = repo.get_note(&notes_ref, &object).await?;
+                entries.push(Entry {
+                    commit: sha.clone(),
+                    note: note.as_deref().and_then(TestNote::parse),
+                });
+            }
+            Ok(entries)
+        }
+
+        /// Every result stored for `git_test_command`, oldest first. Results are stored per
+        /// tree, so each is attributed to the commit it ran on when the note records it.
+        async fn stored_entries(
+            repo: &GitRepository,
+            git_test_command: &GitTestCommand,
+        ) -> Result<Vec<Entry>> {
+            let notes_ref = git_test_command.notes_ref();
+            if !repo.ref_exists(&notes_ref).await? {
+                return Ok(Vec::new());
+            }
+
+            let (blobs, objects): (Vec<String>, Vec<String>) =
+                repo.list_notes(&notes_ref).await?.into_iter().unzip();
+            let mut entries = Vec::new();
+            for (content, object) in repo.read_blobs(&blobs).await?.into_iter().zip(objects) {
+                let Some(note) = TestNote::parse(&content) else {
+                    continue;
+                };
+                entries.push(Entry {
+                    commit: GitSha::new(note.commit.clone().unwrap_or(object)),
+                    note: Some(note),
+                });
+            }
+            entries.sort_by_key(|entry| entry.note.as_ref().map(|note| note.timestamp));
+            Ok(entries)
+        }
+
+        fn test_stats(test: &str, entries: &[Entry]) -> TestStats {
+            let mut stats = TestStats {
+                test: test.to_string(),
+                good: 0,
+                bad: 0,
+                flaky: 0,
+                unknown: 0,
+                timed: 0,
+                mean_ms: None,
+                p95_ms: None,
+                trend_percent: None,
+                slowest: Vec::new(),
+                longest_bad_streak: None,
+            };
+
+            let mut timed = Vec::new();
+            let mut streak: Option<BadStreak> = None;
+            for entry in entries {
+                let status = entry.note.as_ref().map(|note| note.status);
+                match status {
+                    Some(TestStatus::Good) => stats.good += 1,
+                    Some(TestStatus::Bad) => stats.bad += 1,
+                    Some(TestStatus::Flaky) => stats.flaky += 1,
+                    None => stats.unknown += 1,
+                }
+
+                if status == Some(TestStatus::Bad) {
+                    let current = match streak.take() {
+                        Some(mut current) => {
+                            current.length += 1;
+                            current.last = entry.commit.clone();
+                            current
+                        }
+                        None => BadStreak {
+                            length: 1,
+                            first: entry.commit.clone(),
+                            last: entry.commit.clone(),
+                        },
+                    };
+                    if stats
+                        .longest_bad_streak
+                        .as_ref()
+                        .is_none_or(|longest| current.length > longest.length)
+                    {
+                        stats.longest_bad_streak = Some(current.clone());
+                    }
+                    streak = Some(current);
+                } else {
+                    streak = None;
+                }
+
+                if let Some(duration_ms) = entry.note.as_ref().and_then(|note| note.duration_ms) {
+                    timed.push(SlowCommit {
+                        commit: entry.commit.clone(),
+                        duration_ms,
+                    });
+                }
+            }
+
+            stats.timed = timed.len();
+            let durations: Vec<u64> = timed.iter().map(|slow| slow.duration_ms).collect();
+            stats.mean_ms = mean(&durations);
+            stats.p95_ms = percentile(&durations, 95);
+            if durations.len() >= 4 {
+                let (older, newer) = durations.split_at(durations.len() / 2);
+                if let (Some(older), Some(newer)) = (mean(older), mean(newer)) {
+                    if older > 0 {
+                        let change = (newer as f64 - older as f64) / older as f64 * 100.0;
+                        stats.trend_percent = Some((change * 10.0).round() / 10.0);
+                    }
+                }
+            }
+
+            timed.sort_by_key(|slow| std::cmp::Reverse(slow.duration_ms));
+            timed.truncate(SLOWEST_COMMITS);
+            stats.slowest = timed;
+            stats
+        }
+
+        fn mean(durations: &[u64]) -> Option<u64> {
+            if durations.is_empty() {
+                return None;
+            }
+            Some(durations.iter().sum::<u64>() / durations.len() as u64)
+        }
+
+        /// The nearest-rank `percent`th percentile of `durations`.
+        fn percentile(durations: &[u64], percent: usize) -> Option<u64> {
+            if durations.is_empty() {
+                return None;
+            }
+            let mut sorted = durations.to_vec();
+            sorted.sort_unstable();
+            let rank = (sorted.len() * percent).div_ceil(100);
+            Some(sorted[rank.max(1) - 1])
+        }
+
+        fn seconds(duration_ms: u64) -> String {
+            format!("{:.1}s", duration_ms as f64 / 1000.0)
+        }
+
+        fn print_test_stats(stats: &TestStats) {
+            info!("{}:", stats.test.bold());
+
+            let mut counts = format!(
+                "{} good, {} bad, {} flaky",
+                stats.good.to_string().green(),
+                stats.bad.to_string().red(),
+                stats.flaky.to_string().yellow()
+            );
+            if stats.unknown > 0 {
+                counts.push_str(&format!(", {} unknown", stats.unknown));
+            }
+            info!("    results = {}", counts);
+
+            if let (Some(mean_ms), Some(p95_ms)) = (stats.mean_ms, stats.p95_ms) {
+                info!(
+                    "    duration = mean {}, p95 {} (over {} timed results)",
+                    seconds(mean_ms),
+                    seconds(p95_ms),
+                    stats.timed
+                );
+            }
+            if let Some(trend_percent) = stats.trend_percent {
+                let trend = if trend_percent > 0.0 {
+                    format!("{:.1}% slower", trend_percent).red()
+                } else {
+                    format!("{:.1}% faster", -trend_percent).green()
+                };
+                info!("    trend = {} (newer half against older half)", trend);
+            }
+            if !stats.slowest.is_empty() {
+                let slowest: Vec<String> = stats
+                    .slowest
+                    .iter()
+                    .map(|slow| format!("{} {}", slow.commit.short(), seconds(slow.duration_ms)))
+                    .collect();
+                info!("    slowest = {}", slowest.join(", "));
+            }
+            if let Some(streak) = &stats.longest_bad_streak {
+                let commits = if streak.length == 1 {
+                    format!("1 commit, {}", streak.first.short())
+                } else {
+                    format!(
+                        "{} commits, {} to {}",
+                        streak.length,
+                        streak.first.short(),
+                        streak.last.short()
+                    )
+                };
+                info!("    longest bad streak = {}", commits);
+            }
+        }
+    }
+
+    pub mod report {
+        use super::*;
+        use crate::report::html::{render_html_report, ReportRow};
+        use std::path::Path;
+
+        pub async fn cmd_report(
+            repo: &GitRepository,
+            html_dir: &Path,
+            stdin: bool,
+            commits: &[String],
+        ) -> Result<()> {
+            let tests = repo.list_tests().await?;
+            let shas = resolve_commit_args(repo, stdin, commits).await?;
+            let subjects = repo.commit_subjects(&shas).await?;
+
+            let mut rows = Vec::new();
+            for (sha, subject) in shas.into_iter().zip(subjects) {
+                let mut results = Vec::new();
+                for git_test_command in &tests {
+                    results.push(git_test_command.get_result_note(sha.as_str()).await?);
+                }
+                rows.push(ReportRow {
+                    sha,
+                    subject,
+                    results,
+                });
+            }
+
+            let test_names: Vec<&str> = tests.iter().map(|t| t.test_name.as_str()).collect();
+            let html = render_html_report(&test_names, &rows);
+
+            std::fs::create_dir_all(html_dir)
+                .with_context(|| format!("Failed to create {}", html_dir.display()))?;
+            let index = html_dir.join("index.html");
+            std::fs::write(&index, html)
+                .with_context(|| format!("Failed to write {}", index.display()))?;
+            info!(
+                "Wrote report for {} commit(s) to {}",
+                rows.len(),
+                index.display()
+            );
+
+            Ok(())
+        }
+    }
+
+    pub mod watch {
+        use super::*;
+        use crate::git::{GitRepositoryWorktreeExt, GitTestCommand, WorktreeConfig};
+        use crate::runner::{RunEntry, TestRunner, TestSelection};
+        use std::collections::HashSet;
+        use std::path::Path;
+        use std::time::Duration;
+
+        /// Re-resolves a range of commits on demand and tests the commits that weren't part of
+        /// it last time. Worktrees are persistent, so they stay warm from one poll to the next.
+        pub struct Watcher {
+            repo: GitRepository,
+            tests: Vec<GitTestCommand>,
+            range: Vec<String>,
+            worktree_config: WorktreeConfig,
+            seen: HashSet<GitSha>,
+            stop: Option<Interrupt>,
+        }
+
+        impl Watcher {
+            pub fn new(
+                repo: &GitRepository,
+                tests: Vec<GitTestCommand>,
+                range: &[String],
+                worktree: &Path,
+            ) -> Self {
+                Watcher {
+                    repo: repo.clone(),
+                    tests,
+                    range: range.to_vec(),
+                    worktree_config: repo.to_persistent_worktree_config(worktree),
+                    seen: HashSet::new(),
+                    stop: None,
+                }
+            }
+
+            /// Stops each poll's run, as `TestRunner::stop_on` does, once `stop` completes.
+            pub fn stop_on(mut self, stop: Interrupt) -> Self {
+                self.stop = Some(stop);
+                self
+            }
+
+            /// Resolves the range and tests any commits in it that haven't been seen yet,
+            /// returning their results. Commits whose trees were already tested are reported
+            /// as known rather than run again.
+            pub async fn poll(&mut self) -> Result<Vec<RunEntry>> {
+                let new_shas: Vec<GitSha> = self
+                    .repo
+                    .resolve_commits(&self.range)
+                    .await?
+                    .into_iter()
+                    .filter(|sha| !self.seen.contains(sha))
+                    .collect();
+                if new_shas.is_empty() {
+                    return Ok(Vec::new());
+                }
+
+                let mut runner = TestRunner::new(&self.repo)
+                    .tests(self.tests.clone())
+                    .shas(new_shas.clone())
+                    .worktree(self.worktree_config.clone())
+                    .keep_going(true)
+                    .log_results(true)
+                    .show_progress(true)
+                    .stream_output(true);
+                if let Some(stop) = &self.stop {
+                    runner = runner.stop_on(stop.clone());
+                }
+                let results = runner.run().await?;
+
+                self.seen.extend(new_shas);
+                Ok(results.entries)
+            }
+        }
+
+        pub async fn cmd_watch(
+            repo: &GitRepository,
+            selection: &TestSelection,
+            range: &[String],
+            worktree: &Path,
+            interval: Duration,
+        ) -> Result<()> {
+            let tests = selection.resolve(repo).await?;
+            let interrupted = interrupt();
+            let mut watcher =
+                Watcher::new(repo, tests, range, worktree).stop_on(interrupted.clone());
+            info!("Watching {} (press Ctrl-C to stop)", range.join(" "));
+
+            loop {
+                let polled = watcher.poll().await;
+                // A Ctrl-C during the poll stopped its run; that ends the watch too.
+                if interrupted.peek().is_some() {
+                    return Ok(());
+                }
+                if let Err(err) = polled {
+                    warn!("{:#}", err);
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = interrupted.clone() => return Ok(()),
+                }
+            }
+        }
+    }
+
+    pub mod serve {
+        use super::*;
+        use crate::commands::list::TestDefinition;
+        use crate::commands::results::{collect_statuses, CommitStatus};
+        use crate::commands::run::RunEntryRecord;
+        use crate::runner::{RunSummary, TestRunner};
+        use axum::extract::{Path as UrlPath, Query, State};
+        use axum::http::StatusCode;
+        use axum::response::sse::{Event, Sse};
+        use axum::response::{IntoResponse, Response};
+        use axum::routing::{get, post};
+        use axum::{Json, Router};
+        use futures::Stream;
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+        use std::convert::Infallible;
+        use std::path::{Path, PathBuf};
+        use std::sync::{Arc, Mutex, MutexGuard};
+        use tokio::sync::{broadcast, mpsc};
+
+        /// The body of `POST /runs`. Without `test` or `all`, the `default` test is run.
+        #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+        pub struct RunRequest {
+            #[serde(default)]
+            pub test: Option<String>,
+            #[serde(default)]
+            pub all: bool,
+            #[serde(default)]
+            pub commits: Vec<String>,
+            #[serde(default)]
+            pub force: bool,
+            #[serde(default)]
+            pub retest: bool,
+            #[serde(default)]
+            pub keep_going: bool,
+        }
+
+        /// Something that happened to a queued run, as streamed by `GET /runs/{id}/events`.
+        #[derive(Debug, Clone, Serialize)]
+        #[serde(tag = "event", rename_all = "lowercase")]
+        pub enum RunEvent {
+            Queued,
+            Started,
+            /// One (commit, test) entry, in the same shape as the `run --format json` report.
+            Result {
+                result: serde_json::Value,
+            },
+            Finished {
+                summary: RunSummary,
+            },
+            Failed {
+                error: String,
+            },
+        }
+
+        impl RunEvent {
+            fn is_terminal(&self) -> bool {
+                matches!(self, RunEvent::Finished { .. } | RunEvent::Failed { .. })
+            }
+
+            fn status(&self) -> &'static str {
+                match self {
+                    RunEvent::Queued => "queued",
+                    RunEvent::Started | RunEvent::Result { .. } => "running",
+                    RunEvent::Finished { .. } => "finished",
+                    RunEvent::Failed { .. } => "failed",
+                }
+            }
+        }
+
+        struct RunState {
+            request: RunRequest,
+            events: Vec<RunEvent>,
+            sender: broadcast::Sender<RunEvent>,
+        }
+
+        #[derive(Serialize)]
+        struct RunView<'a> {
+            id: u64,
+            status: &'static str,
+            request: &'a RunRequest,
+            events: &'a [RunEvent],
+        }
+
+        struct ServerState {
+            repo: GitRepository,
+            worktree: PathBuf,
+            runs: Mutex<HashMap<u64, RunState>>,
+            next_id: Mutex<u64>,
+            queue: mpsc::UnboundedSender<u64>,
+        }
+
+        impl ServerState {
+            fn runs(&self) -> MutexGuard<'_, HashMap<u64, RunState>> {
+                self.runs.lock().unwrap_or_else(|e| e.into_inner())
+            }
+
+            /// Records `event` for run `id` and sends it to anyone streaming the run's events.
+            /// Both happen under the lock, so a new subscriber sees each event exactly once.
+            fn emit(&self, id: u64, event: RunEvent) {
+                if let Some(run) = self.runs().get_mut(&id) {
+                    let _ = run.sender.send(event.clone());
+                    run.events.push(event);
+                }
+            }
+        }
+
+        struct ApiError(StatusCode, String);
+
+        impl From<anyhow::Error> for ApiError {
+            fn from(err: anyhow::Error) -> Self {
+                ApiError(StatusCode::BAD_REQUEST, format!("{:#}", err))
+            }
+        }
+
+        impl IntoResponse for ApiError {
+            fn into_response(self) -> Response {
+                (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+            }
+        }
+
+        /// Builds the API, spawning the worker that executes queued runs one at a time, so
+        /// that two runs never use the same worktree path concurrently.
+        pub fn router(repo: &GitRepository, worktree: &Path) -> Router {
+            let (queue, mut queued) = mpsc::unbounded_channel();
+            let state = Arc::new(ServerState {
+                repo: repo.clone(),
+                worktree: worktree.to_path_buf(),
+                runs: Mutex::new(HashMap::new()),
+                next_id: Mutex::new(1),
+                queue,
+            });
+
+            let worker_state = Arc::downgrade(&state);
+            tokio::spawn(async move {
+                while let Some(id) = queued.recv().await {
+                    let Some(state) = worker_state.upgrade() else {
+                        break;
+                    };
+                    execute_run(&state, id).await;
+                }
+            });
+
+            Router::new()
+                .route("/tests", get(list_tests))
+                .route("/results", get(get_results))
+                .route("/runs", post(enqueue_run))
+                .route("/runs/{id}", get(get_run))
+                .route("/runs/{id}/events", get(stream_run_events))
+                .with_state(state)
+        }
+
+        pub async fn cmd_serve(repo: &GitRepository, listen: &str, worktree: &Path) -> Result<()> {
+            let listener = tokio::net::TcpListener::bind(listen)
+                .await
+                .with_context(|| format!("Failed to listen on {}", listen))?;
+            info!("Serving test results on http://{}", listener.local_addr()?);
+            axum::serve(listener, router(repo, worktree))
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                })
+                .await
+                .context("HTTP server failed")
+        }
+
+        async fn list_tests(
+            State(state): State<Arc<ServerState>>,
+        ) -> Result<Json<Vec<TestDefinition>>, ApiError> {
+            let tests = state.repo.list_tests().await?;
+            Ok(Json(tests.iter().map(TestDefinition::from).collect()))
+        }
+
+        #[derive(Deserialize)]
+        struct ResultsQuery {
+            test: Option<String>,
+            /// Comma-separated commits or ranges; `HEAD` if omitted.
+            commits: Option<String>,
+        }
+
+        async fn get_results(
+            State(state): State<Arc<ServerState>>,
+            Query(query): Query<ResultsQuery>,
+        ) -> Result<Json<Vec<CommitStatus>>, ApiError> {
+            let git_test_command = state
+                .repo
+                .get_test_command(query.test.as_deref().unwrap_or("default"))
+                .await?;
+            let commits: Vec<String> = query
+                .commits
+                .iter()
+                .flat_map(|commits| commits.split(','))
+                .filter(|commit| !commit.is_empty())
+                .map(str::to_string)
+                .collect();
+            let shas = resolve_commit_args(&state.repo, false, &commits).await?;
+            Ok(Json(collect_statuses(&git_test_command, &shas).await?))
+        }
+
+        async fn enqueue_run(
+            State(state): State<Arc<ServerState>>,
+            Json(request): Json<RunRequest>,
+        ) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+            let id = {
+                let mut next_id = state.next_id.lock().unwrap_or_else(|e| e.into_inner());
+                let id = *next_id;
+                *next_id += 1;
+                id
+            };
+
+            let (sender, _) = broadcast::channel(256);
+            state.runs().insert(
+                id,
+                RunState {
+                    request,
+                    events: Vec::new(),
+                    sender,
+                },
+            );
+            state.emit(id, RunEvent::Queued);
+            state.queue.send(id).map_err(|_| {
+                ApiError(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Run queue is closed".into(),
+                )
+            })?;
 
-            let old_command = existing_command
-                .map(|cmd| cmd.test_command.to_string())
-                .unwrap_or_else(|_| "<empty>".to_string());
+            Ok((
+                StatusCode::ACCEPTED,
+                Json(serde_json::json!({ "id": id, "status": "queued" })),
+            ))
+        }
 
-            if !forget && !keep && had_existing_command {
-                warn!(
-                    "Overwriting existing test '{}'. Use --forget to delete stored results or --keep to preserve them.",
-                    test
-                );
-            }
+        async fn get_run(
+            State(state): State<Arc<ServerState>>,
+            UrlPath(id): UrlPath<u64>,
+        ) -> Result<Json<serde_json::Value>, ApiError> {
+            let runs = state.runs();
+            let run = runs.get(&id).ok_or_else(|| unknown_run(id))?;
+            let view = RunView {
+                id,
+                status: run.events.last().map_or("queued", RunEvent::status),
+                request: &run.request,
+                events: &run.events,
+            };
+            Ok(Json(
+                serde_json::to_value(view).map_err(anyhow::Error::from)?,
+            ))
+        }
 
-            if forget {
-                forget_results(repo, test)
-                    .await
-                    .with_context(|| format!("Failed to delete stored results for '{}'", test))?;
-            }
+        async fn stream_run_events(
+            State(state): State<Arc<ServerState>>,
+            UrlPath(id): UrlPath<u64>,
+        ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+            let (backlog, receiver, done) = {
+                let runs = state.runs();
+                let run = runs.get(&id).ok_or_else(|| unknown_run(id))?;
+                let done = run.events.last().is_some_and(RunEvent::is_terminal);
+                (run.events.clone(), run.sender.subscribe(), done)
+            };
 
-            repo.set_test_command(test, command)
-                .await
-                .with_context(|| format!("Failed to set test command for '{}'", test))?;
+            let live =
+                futures::stream::unfold((receiver, done), |(mut receiver, done)| async move {
+                    if done {
+                        return None;
+                    }
+                    loop {
+                        match receiver.recv().await {
+                            Ok(event) => {
+                                let done = event.is_terminal();
+                                return Some((event, (receiver, done)));
+                            }
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                    }
+                });
 
-            info!(
-                "Changing test '{}' from '{}' to '{}'",
-                test, old_command, command
+            let events = futures::StreamExt::map(
+                futures::StreamExt::chain(futures::stream::iter(backlog), live),
+                |event| Ok(Event::default().json_data(&event).unwrap_or_default()),
             );
+            Ok(Sse::new(events))
+        }
 
-            Ok(())
+        fn unknown_run(id: u64) -> ApiError {
+            ApiError(StatusCode::NOT_FOUND, format!("No run with id {}", id))
         }
-    }
 
-    pub mod forget_results {
-        use super::*;
+        async fn execute_run(state: &ServerState, id: u64) {
+            let Some(request) = state.runs().get(&id).map(|run| run.request.clone()) else {
+                return;
+            };
+            state.emit(id, RunEvent::Started);
 
-        pub async fn cmd_forget_results(repo: &GitRepository, test: &str) -> Result<()> {
-            // Implement forget-results command
-            println!("Forgetting results for test '{}'", test);
-            Ok(())
+            let event = match run_request(state, id, &request).await {
+                Ok(summary) => RunEvent::Finished { summary },
+                Err(err) => RunEvent::Failed {
+                    error: format!("{:#}", err),
+                },
+            };
+            state.emit(id, event);
         }
 
-        pub(crate) async fn forget_results(repo: &GitRepository, test: &str) -> Result<()> {
-            // This is a placeholder for the forget-results logic
-            // Implement the actual forget-results functionality here
-            println!("Forgetting results for test '{}'", test);
-            Ok(())
+        async fn run_request(
+            state: &ServerState,
+            id: u64,
+            request: &RunRequest,
+        ) -> Result<RunSummary> {
+            let mut runner = TestRunner::new(&state.repo);
+            runner = if request.all {
+                runner.all_tests()
+            } else {
+                runner.test(request.test.as_deref().unwrap_or("default"))
+            };
+            let shas = resolve_commit_args(&state.repo, false, &request.commits).await?;
+
+            let results = runner
+                .shas(shas)
+                .linked_worktrees(&state.worktree)
+                .force(request.force)
+                .retest(request.retest)
+                .keep_going(request.keep_going)
+                .log_results(true)
+                .on_result(|entry| {
+                    let result =
+                        serde_json::to_value(RunEntryRecord::from(entry)).unwrap_or_default();
+                    state.emit(id, RunEvent::Result { result });
+                })
+                .stop_on(interrupt())
+                .run()
+                .await?;
+
+            Ok(results.summary())
         }
     }
 
-    pub mod list {
+    pub mod results {
         use super::*;
+        use crate::git::GitTestCommand;
+        use crate::output::{print_records, OutputFormat};
+        use crate::runner::TestSelection;
         use colored::*;
+        use serde::Serialize;
+
+        /// The stored result of one test for one commit, as reported by
+        /// `git test results --format json`.
+        #[derive(Debug, Serialize)]
+        pub struct CommitStatus {
+            pub commit: String,
+            pub test: String,
+            /// `good`, `bad`, `flaky` or `unknown`.
+            pub status: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub timestamp: Option<u64>,
+        }
 
-        pub async fn cmd_list(repo: &GitRepository) -> Result<()> {
-            let tests = repo.list_tests().await?;
-
+        pub async fn cmd_results(
+            repo: &GitRepository,
+            selection: &TestSelection,
+            stdin: bool,
+            commits: &[String],
+            format: OutputFormat,
+        ) -> Result<()> {
+            let tests = selection.resolve(repo).await?;
             if tests.is_empty() {
-                warn!("No tests defined.");
-            } else {
-                for git_test_command in tests {
-                    info!("{}:", git_test_command.test_name.bold());
-                    info!("    command = {}", git_test_command.test_command.green());
+                anyhow::bail!("No tests selected");
+            }
+            let shas = resolve_commit_args(repo, stdin, commits).await?;
+            let mut statuses = Vec::new();
+            for sha in &shas {
+                for git_test_command in &tests {
+                    statuses.extend(
+                        collect_statuses(git_test_command, std::slice::from_ref(sha)).await?,
+                    );
                 }
             }
 
-            Ok(())
-        }
-    }
+            if format == OutputFormat::Tap {
+                print!("{}", crate::report::tap::results_tap_report(&statuses));
+                return Ok(());
+            }
 
-    pub mod remove {
-        use super::*;
+            if !format.is_human() {
+                return print_records(format, &statuses);
+            }
+
+            for commit_status in statuses {
+                if tests.len() > 1 {
+                    info!(
+                        "{} {} {}",
+                        commit_status.commit,
+                        status_label(&commit_status.status),
+                        commit_status.test
+                    );
+                } else {
+                    info!(
+                        "{} {}",
+                        commit_status.commit,
+                        status_label(&commit_status.status)
+                    );
+                }
+            }
 
-        pub fn cmd_remove(repo: &GitRepository, test: &str) -> Result<()> {
-            // Implement remove command
-            println!("Removing test '{}'", test);
             Ok(())
         }
-    }
 
-    pub mod results {
-        use super::*;
+        /// Looks up the stored result of `git_test_command` for each of `shas`.
+        pub async fn collect_statuses(
+            git_test_command: &GitTestCommand,
+            shas: &[GitSha],
+        ) -> Result<Vec<CommitStatus>> {
+            let mut statuses = Vec::new();
+            for sha in shas {
+                let note = git_test_command.get_result_note(sha.as_str()).await?;
+                statuses.push(CommitStatus {
+                    commit: sha.as_str().to_string(),
+                    test: git_test_command.test_name.clone(),
+                    status: note
+                        .as_ref()
+                        .map_or("unknown", |note| note.status.as_str())
+                        .to_string(),
+                    timestamp: note.map(|note| note.timestamp),
+                });
+            }
+            Ok(statuses)
+        }
 
-        pub fn cmd_results(
-            repo: &GitRepository,
-            test: &str,
-            stdin: bool,
-            commits: &[String],
-        ) -> Result<()> {
-            // Implement results command
-            println!(
-                "Showing results for test '{}' on commits: {:?}",
-                test, commits
-            );
-            Ok(())
+        pub(crate) fn status_label(label: &str) -> ColoredString {
+            if label.ends_with("good") {
+                label.green()
+            } else if label.ends_with("bad") {
+                label.red()
+            } else if label.ends_with("flaky") {
+                label.yellow()
+            } else {
+                label.dimmed()
+            }
         }
     }
 
     pub mod run {
         use super::*;
-        use crate::git::GitTestCommand;
-        use crate::git::{
-            GitRepository, GitRepositoryWorktreeExt, GitSha, WorktreeConfig,
-        };
-        use crate::log_util::log_and_run_command;
+        use crate::git::TestStatus;
+        use crate::output::{render_records, OutputFormat};
+        use crate::runner::{TestRunner, TestSelection};
+        use serde::Serialize;
         use std::path::Path;
-        use tokio::process::Command;
+
+        pub use crate::runner::{RunEntry, RunFlags, RunOutcome, RunSummary, TestResult};
+
+        /// Everything about a run besides which tests and commits it covers.
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct RunOptions<'a> {
+            pub flags: RunFlags,
+            /// Read the commits from standard input, one per line.
+            pub stdin: bool,
+            /// Run in worktrees linked under this directory instead of temporary ones.
+            pub worktree: Option<&'a Path>,
+            /// Write a JUnit XML report of the run here.
+            pub junit: Option<&'a Path>,
+            /// Also write the complete output of each test to files in `temp_dir()`.
+            pub spill_output: bool,
+            /// Bytes kept at each end of each test's output, or all of it if zero, instead of
+            /// `test.outputLimit`.
+            pub output_limit: Option<usize>,
+            pub format: OutputFormat,
+        }
 
         pub async fn cmd_run(
             repo: &GitRepository,
-            test: Option<&str>,
-            all: bool,
-            force: bool,
-            forget: bool,
-            retest: bool,
-            keep_going: bool,
-            dry_run: bool,
-            stdin: bool,
+            selection: &TestSelection,
             commits: &[String],
-            worktree: Option<&Path>,
+            options: &RunOptions<'_>,
         ) -> Result<()> {
-            if test.is_some() && all {
+            let RunOptions {
+                flags,
+                stdin,
+                worktree,
+                junit,
+                spill_output,
+                output_limit,
+                format,
+            } = *options;
+            if !selection.tests.is_empty() && selection.all {
                 anyhow::bail!("Cannot specify both --test and --all");
             }
 
-            let tests: Vec<GitTestCommand> = if all {
-                repo.list_tests().await?
-            } else if let Some(test_name) = test {
-                vec![repo.get_test_command(test_name).await?]
-            } else {
-                anyhow::bail!("Must specify either --test or --all");
-            };
+            let mut runner = TestRunner::new(repo).selection(selection.clone());
+            if let Some(worktree_path) = worktree {
+                runner = runner.linked_worktrees(worktree_path);
+            }
+            if let Some(limit) = output_limit {
+                runner = runner.output_limit(limit);
+            }
 
-            let worktree_config = if let Some(worktree_path) = worktree {
-                repo.to_linked_worktree_config(worktree_path)
-            } else {
-                repo.to_worktree_config()
+            let shas = resolve_commit_args(repo, stdin, commits).await?;
+            let results = runner
+                .shas(shas)
+                .flags(flags)
+                .log_results(format.is_human())
+                .show_progress(format.is_human())
+                .stream_output(format.is_human())
+                .spill_output(spill_output)
+                .stop_on(interrupt())
+                .run()
+                .await?;
+            let (entries, stopped_at) = (results.entries, results.stopped_at);
+
+            if let Some(junit_path) = junit {
+                crate::report::junit::write_junit_report(junit_path, &entries)?;
+            }
+
+            if !format.is_human() {
+                print_run_report(format, &entries)?;
+            }
+
+            if let Some(sha) = stopped_at {
+                anyhow::bail!("Commit {} failed; stopping", sha.as_str());
+            }
+
+            let failures = entries.iter().filter(|entry| entry.is_failure()).count();
+            if failures > 0 && !flags.dry_run {
+                anyhow::bail!("{} test(s) failed", failures);
+            }
+
+            Ok(())
+        }
+
+        /// One `RunEntry` as it appears in the structured run report.
+        #[derive(Debug, Serialize)]
+        pub struct RunEntryRecord<'a> {
+            pub commit: &'a str,
+            pub test: &'a str,
+            /// What happened, e.g. `good`, `bad`, `known-good` or `unknown`.
+            pub outcome: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub status: Option<TestStatus>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub exit_code: Option<i32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub duration_ms: Option<u128>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stdout: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub stderr: Option<&'a str>,
+        }
+
+        impl<'a> From<&'a RunEntry> for RunEntryRecord<'a> {
+            fn from(entry: &'a RunEntry) -> Self {
+                let (status, result) = match &entry.outcome {
+                    RunOutcome::Tested(result) => (Some(result.status()), Some(result)),
+                    RunOutcome::Known(status) => (Some(*status), None),
+                    RunOutcome::Unknown | RunOutcome::Forgotten => (None, None),
+                };
+                RunEntryRecord {
+                    commit: entry.sha.as_str(),
+                    test: &entry.test_name,
+                    outcome: entry.outcome.label(),
+                    status,
+                    exit_code: result.and_then(|result| result.exit_code),
+                    duration_ms: result.map(|result| result.duration.as_millis()),
+                    stdout: result.map(|result| result.stdout.as_str()),
+                    stderr: result.map(|result| result.stderr.as_str()),
+                }
+            }
+        }
+
+        #[derive(Debug, Serialize)]
+        pub struct RunReport<'a> {
+            pub results: Vec<RunEntryRecord<'a>>,
+            pub summary: RunSummary,
+        }
+
+        /// Renders the structured run report: a single JSON document for `json`, or one line
+        /// per entry followed by a `{"summary": ...}` line for `ndjson`.
+        pub fn render_run_report(format: OutputFormat, entries: &[RunEntry]) -> Result<String> {
+            let report = RunReport {
+                results: entries.iter().map(RunEntryRecord::from).collect(),
+                summary: RunSummary::from_entries(entries),
             };
+            match format {
+                OutputFormat::Ndjson => {
+                    let mut rendered = render_records(format, &report.results)?;
+                    rendered.push_str(&serde_json::to_string(
+                        &serde_json::json!({ "summary": report.summary }),
+                    )?);
+                    Ok(rendered)
+                }
+                _ => Ok(serde_json::to_string_pretty(&report)?),
+            }
+        }
 
-            let commits = if commits.is_empty() {
-                vec![repo.get_head_commit().await?]
+        fn print_run_report(format: OutputFormat, entries: &[RunEntry]) -> Result<()> {
+            if format == OutputFormat::Tap {
+                print!("{}", crate::report::tap::run_tap_report(entries));
             } else {
-                commits.to_vec()
-            };
+                println!("{}", render_run_report(format, entries)?);
+            }
+            Ok(())
+        }
+    }
+
+    pub use add::cmd_add;
+    pub use copy::cmd_copy;
+    pub use fetch::cmd_fetch;
+    pub use forget_results::cmd_forget_results;
+    pub use gc::cmd_gc;
+    pub use history::cmd_log;
+    pub use install_hook::{cmd_install_hook, cmd_uninstall_hook};
+    pub use list::cmd_list;
+    pub use merge_notes::cmd_merge_notes;
+    pub use push::cmd_push;
+    pub use remove::cmd_remove;
+    pub use rename::cmd_rename;
+    pub use report::cmd_report;
+    pub use results::cmd_results;
+    pub use run::{cmd_run, RunOptions};
+    pub use serve::cmd_serve;
+    pub use show::cmd_show;
+    pub use stats::cmd_stats;
+    pub use watch::cmd_watch;
+}
+
+pub mod report {
+    pub mod html {
+        use super::escape;
+        use crate::git::{GitSha, TestNote, TestStatus};
+        use std::fmt::Write;
+
+        /// One commit's row in the HTML report: its stored result for each test, in the same
+        /// order as the report's columns.
+        pub struct ReportRow {
+            pub sha: GitSha,
+            pub subject: String,
+            pub results: Vec<Option<TestNote>>,
+        }
 
-            for commit in commits {
-                let sha = GitSha::new(commit.clone());
-                let test_results = run_tests_for_commit(&tests, &sha, &worktree_config).await?;
-                update_git_notes(repo, &commit, &test_results).await?;
+        const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+td.status { text-align: center; min-width: 4em; }
+td.good { background: #c8f0c8; }
+td.bad { background: #f5b8b8; }
+td.flaky { background: #f8e0a0; }
+td.unknown { background: #eee; color: #888; }
+code { font-family: monospace; }
+pre { background: #f6f6f6; padding: 0.6em; overflow-x: auto; }
+";
+
+        /// Renders a self-contained HTML page with one row per commit and one column per test.
+        /// Failed cells link to the output stored with the result further down the page.
+        pub fn render_html_report(test_names: &[&str], rows: &[ReportRow]) -> String {
+            let mut out = String::new();
+            out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+            out.push_str("<title>git test report</title>\n");
+            let _ = writeln!(out, "<style>\n{}</style>", STYLE);
+            out.push_str("</head>\n<body>\n<h1>git test report</h1>\n<table>\n<tr><th>commit</th><th>subject</th>");
+            for test_name in test_names {
+                let _ = write!(out, "<th>{}</th>", escape(test_name));
+            }
+            out.push_str("</tr>\n");
+
+            let mut outputs = String::new();
+            for row in rows {
+                let short = row.sha.short();
+                let _ = write!(
+                    out,
+                    "<tr><td><code>{}</code></td><td>{}</td>",
+                    short,
+                    escape(&row.subject)
+                );
+                for (test_name, note) in test_names.iter().zip(&row.results) {
+                    let Some(note) = note else {
+                        out.push_str("<td class=\"status unknown\">?</td>");
+                        continue;
+                    };
+
+                    let class = note.status.as_str();
+                    let has_output = note.stdout.is_some() || note.stderr.is_some();
+                    if note.status != TestStatus::Good && has_output {
+                        let anchor = output_anchor(&row.sha, test_name);
+                        let _ = write!(
+                            out,
+                            "<td class=\"status {}\"><a href=\"#{}\">{}</a></td>",
+                            class,
+                            anchor,
+                            note.status.as_symbol()
+                        );
+                        write_output_section(&mut outputs, &anchor, short, test_name, note);
+                    } else {
+                        let _ = write!(
+                            out,
+                            "<td class=\"status {}\">{}</td>",
+                            class,
+                            note.status.as_symbol()
+                        );
+                    }
+                }
+                out.push_str("</tr>\n");
             }
+            out.push_str("</table>\n");
 
-            Ok(())
+            if !outputs.is_empty() {
+                out.push_str("<h2>Output of failed tests</h2>\n");
+                out.push_str(&outputs);
+            }
+            out.push_str("</body>\n</html>\n");
+            out
         }
 
-        async fn run_tests_for_commit(
-            tests: &[GitTestCommand],
-            sha: &GitSha,
-            worktree_config: &WorktreeConfig,
-        ) -> Result<Vec<TestResult>> {
-            let tasks: Vec<_> = tests
+        fn output_anchor(sha: &GitSha, test_name: &str) -> String {
+            let test_name: String = test_name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect();
+            format!("output-{}-{}", sha.as_str(), test_name)
+        }
+
+        fn write_output_section(
+            out: &mut String,
+            anchor: &str,
+            short: &str,
+            test_name: &str,
+            note: &TestNote,
+        ) {
+            let _ = writeln!(
+                out,
+                "<section id=\"{}\">\n<h3><code>{}</code> {}</h3>",
+                anchor,
+                short,
+                escape(test_name)
+            );
+            for (label, text, file) in [
+                ("stdout", &note.stdout, &note.stdout_file),
+                ("stderr", &note.stderr, &note.stderr_file),
+            ] {
+                if let Some(text) = text.as_deref().filter(|text| !text.is_empty()) {
+                    let _ = writeln!(out, "<h4>{}</h4>\n<pre>{}</pre>", label, escape(text));
+                }
+                if let Some(file) = file {
+                    let _ = writeln!(
+                        out,
+                        "<p>All of the {} is in <code>{}</code> on the machine that ran it.</p>",
+                        label,
+                        escape(file)
+                    );
+                }
+            }
+            out.push_str("</section>\n");
+        }
+    }
+
+    /// Escapes text for use in XML or HTML content and attributes, dropping characters that XML 1.0
+    /// doesn't allow at all (such as most control characters in test output).
+    pub(crate) fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                '\t' | '\n' | '\r' => escaped.push(c),
+                c if c < ' ' => {}
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    pub mod tap {
+        use crate::commands::results::CommitStatus;
+        use crate::runner::{RunEntry, RunOutcome};
+        use std::fmt::Write;
+
+        /// How many trailing lines of stderr to include in a test point's diagnostics.
+        const STDERR_TAIL_LINES: usize = 20;
+
+        /// A single TAP test point.
+        struct TestPoint {
+            ok: bool,
+            description: String,
+            status: String,
+            skip: Option<String>,
+            exit_code: Option<i32>,
+            stderr_tail: Option<String>,
+        }
+
+        /// Renders a run as a TAP 13 stream with one test point per (commit, test). Results
+        /// that were already known, or that weren't produced, are marked `# SKIP`.
+        pub fn run_tap_report(entries: &[RunEntry]) -> String {
+            let points = entries
                 .iter()
-                .map(|git_test_command| {
-                    let git_test_command = git_test_command.clone();
-                    let sha = sha.clone();
-                    let worktree_config = worktree_config.clone();
+                .map(|entry| {
+                    let description = format!("{} {}", entry.sha.as_str(), entry.test_name);
+                    match &entry.outcome {
+                        RunOutcome::Tested(result) => TestPoint {
+                            ok: result.success,
+                            description,
+                            status: result.status().as_str().to_string(),
+                            skip: None,
+                            exit_code: result.exit_code,
+                            stderr_tail: Some(tail(&result.stderr, STDERR_TAIL_LINES)),
+                        },
+                        outcome => TestPoint {
+                            ok: true,
+                            description,
+                            status: outcome.label(),
+                            skip: Some(outcome.label()),
+                            exit_code: None,
+                            stderr_tail: None,
+                        },
+                    }
+                })
+                .collect::<Vec<_>>();
+            render(&points)
+        }
 
-                    tokio::spawn(async move {
-                        run_single_test(&git_test_command, &sha, &worktree_config).await
-                    })
+        /// Renders stored results as a TAP 13 stream. Commits without a result are skipped.
+        pub fn results_tap_report(statuses: &[CommitStatus]) -> String {
+            let points = statuses
+                .iter()
+                .map(|commit_status| TestPoint {
+                    ok: commit_status.status == "good" || commit_status.status == "unknown",
+                    description: format!("{} {}", commit_status.commit, commit_status.test),
+                    status: commit_status.status.clone(),
+                    skip: (commit_status.status == "unknown").then(|| "unknown".to_string()),
+                    exit_code: None,
+                    stderr_tail: None,
                 })
-                .collect();
+                .collect::<Vec<_>>();
+            render(&points)
+        }
 
-            let results = futures::future::join_all(tasks).await;
-            results
-                .into_iter()
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
-                .into_iter()
-                .collect::<Result<Vec<_>, _>>()
+        fn render(points: &[TestPoint]) -> String {
+            let mut out = String::from("TAP version 13\n");
+            let _ = writeln!(out, "1..{}", points.len());
+            for (i, point) in points.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{} {} - {}",
+                    if point.ok { "ok" } else { "not ok" },
+                    i + 1,
+                    point.description
+                );
+                if let Some(reason) = &point.skip {
+                    let _ = write!(out, " # SKIP {}", reason);
+                }
+                out.push('\n');
+
+                if point.ok {
+                    continue;
+                }
+                out.push_str("  ---\n");
+                let _ = writeln!(out, "  status: {}", point.status);
+                if let Some(code) = point.exit_code {
+                    let _ = writeln!(out, "  exit_code: {}", code);
+                }
+                match point.stderr_tail.as_deref() {
+                    Some(stderr) if !stderr.is_empty() => {
+                        out.push_str("  stderr: |-\n");
+                        for line in stderr.lines() {
+                            let _ = writeln!(out, "    {}", line);
+                        }
+                    }
+                    _ => {}
+                }
+                out.push_str("  ...\n");
+            }
+            out
         }
 
-        async fn run_single_test(
-            GitTestCommand {
-                repo,
-                test_name,
-                test_command,
-            }: &GitTestCommand,
-            sha: &GitSha,
-            worktree_config: &WorktreeConfig,
-        ) -> Result<TestResult> {
-            let worktree = worktree_config.to_worktree(sha.clone(), test_name);
-            worktree.create().await?;
+        fn tail(text: &str, lines: usize) -> String {
+            let all: Vec<&str> = text.lines().collect();
+            all[all.len().saturating_sub(lines)..].join("\n")
+        }
+    }
 
-            let mut cmd = Command::new("sh");
-            cmd.arg("-c")
-                .arg(test_command)
-                .current_dir(worktree.get_path());
+    pub mod junit {
+        use super::escape;
+        use crate::runner::{RunEntry, RunOutcome};
+        use anyhow::{Context, Result};
+        use std::fmt::Write;
+        use std::path::Path;
+        use std::time::Duration;
 
-            let output = log_and_run_command(&mut cmd).await?;
+        /// Writes a JUnit XML report with one `<testsuite>` per test and one `<testcase>` per
+        /// commit. Results that were already known are reported as skipped.
+        pub fn write_junit_report(path: &Path, entries: &[RunEntry]) -> Result<()> {
+            std::fs::write(path, junit_report(entries))
+                .with_context(|| format!("Failed to write JUnit report to {}", path.display()))
+        }
 
-            let success = output.status.success();
-            let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
-            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        pub fn junit_report(entries: &[RunEntry]) -> String {
+            let mut test_names: Vec<&str> = Vec::new();
+            for entry in entries {
+                if !test_names.contains(&entry.test_name.as_str()) {
+                    test_names.push(&entry.test_name);
+                }
+            }
 
-            // Clean up the worktree after the test
-            worktree.delete().await?;
+            let mut suites = String::new();
+            for test_name in &test_names {
+                let cases: Vec<&RunEntry> = entries
+                    .iter()
+                    .filter(|entry| entry.test_name == *test_name)
+                    .collect();
+                let _ = writeln!(
+                    suites,
+                    r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{}">"#,
+                    escape(test_name),
+                    cases.len(),
+                    count_failures(&cases),
+                    count_skipped(&cases),
+                    seconds(total_duration(&cases)),
+                );
+                for entry in cases {
+                    write_testcase(&mut suites, entry);
+                }
+                suites.push_str("  </testsuite>\n");
+            }
 
-            Ok(TestResult {
-                test_name: test_name.to_string(),
-                success,
-                stdout,
-                stderr,
-            })
+            let all: Vec<&RunEntry> = entries.iter().collect();
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <testsuites name=\"git-test\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">\n\
+                 {}</testsuites>\n",
+                all.len(),
+                count_failures(&all),
+                count_skipped(&all),
+                seconds(total_duration(&all)),
+                suites,
+            )
         }
 
-        struct TestResult {
-            test_name: String,
-            success: bool,
-            stdout: String,
-            stderr: String,
+        fn write_testcase(out: &mut String, entry: &RunEntry) {
+            let duration = match &entry.outcome {
+                RunOutcome::Tested(result) => result.duration,
+                _ => Duration::ZERO,
+            };
+            let _ = writeln!(
+                out,
+                r#"    <testcase name="{}" classname="{}" time="{}">"#,
+                entry.sha.as_str(),
+                escape(&entry.test_name),
+                seconds(duration),
+            );
+
+            match &entry.outcome {
+                RunOutcome::Tested(result) => {
+                    if !result.success {
+                        // The output is only in <system-out> and <system-err>.
+                        let message = match (result.timed_out, result.exit_code) {
+                            (Some(timeout), _) => {
+                                format!("timed out after {}s", timeout.as_secs())
+                            }
+                            (None, Some(code)) => format!("exit status {}", code),
+                            (None, None) => "terminated by signal".to_string(),
+                        };
+                        let _ = writeln!(out, r#"      <failure message="{}"/>"#, message);
+                    }
+                    if !result.stdout.is_empty() {
+                        let _ = writeln!(
+                            out,
+                            "      <system-out>{}</system-out>",
+                            escape(&result.stdout)
+                        );
+                    }
+                    if !result.stderr.is_empty() {
+                        let _ = writeln!(
+                            out,
+                            "      <system-err>{}</system-err>",
+                            escape(&result.stderr)
+                        );
+                    }
+                }
+                outcome => {
+                    let _ = writeln!(
+                        out,
+                        r#"      <skipped message="{}"/>"#,
+                        escape(&outcome.label())
+                    );
+                }
+            }
+
+            out.push_str("    </testcase>\n");
         }
 
-        async fn update_git_notes(
-            repo: &GitRepository,
-            commit: &str,
-            results: &[TestResult],
-        ) -> Result<()> {
-            for result in results {
-                let status = if result.success { "✓" } else { "✗" };
-                repo.add_note(
-                    &format!("refs/notes/tests/{}", result.test_name),
-                    &format!("{}^{{tree}}", commit),
-                    status,
+        fn count_failures(entries: &[&RunEntry]) -> usize {
+            entries
+                .iter()
+                .filter(
+                    |entry| matches!(&entry.outcome, RunOutcome::Tested(result) if !result.success),
                 )
-                .await?;
-            }
+                .count()
+        }
 
-            let summary = results
+        fn count_skipped(entries: &[&RunEntry]) -> usize {
+            entries
                 .iter()
-                .map(|r| format!("{}: {}", r.test_name, if r.success { "✓" } else { "✗" }))
-                .collect::<Vec<_>>()
-                .join("\n");
+                .filter(|entry| !matches!(entry.outcome, RunOutcome::Tested(_)))
+                .count()
+        }
 
-            repo.add_note("refs/notes/commits", commit, &summary)
-                .await?;
+        fn total_duration(entries: &[&RunEntry]) -> Duration {
+            entries
+                .iter()
+                .filter_map(|entry| match &entry.outcome {
+                    RunOutcome::Tested(result) => Some(result.duration),
+                    _ => None,
+                })
+                .sum()
+        }
 
-            Ok(())
+        fn seconds(duration: Duration) -> String {
+            format!("{:.3}", duration.as_secs_f64())
         }
     }
-
-    pub use add::cmd_add;
-    pub use forget_results::cmd_forget_results;
-    pub use list::cmd_list;
-    pub use remove::cmd_remove;
-    pub use results::cmd_results;
-    pub use run::cmd_run;
 }
 
+pub use runner::TestRunner;
+
 #[tokio::main]
 pub async fn main() -> Result<()> {
     use crate::git::get_repo_root;
     use crate::log_util::init_logging;
     use cli::{Cli, Commands};
+    use log::warn;
 
     let cli = Cli::parse();
 
     // Calculate verbosity and set up logger
     let verbosity = cli.verbose as i8 - cli.quiet as i8;
-    init_logging(verbosity, cli.color)?;
+    init_logging(verbosity, cli.color, !cli.format.is_human())?;
+
+    if !cli.format.is_human() && !cli.command.supports_format() {
+        anyhow::bail!(
+            "--format is only supported by list, run, range, results, show, log and stats"
+        );
+    }
 
     // Get the repository root
     let current_dir = std::env::current_dir()?;
@@ -876,23 +7073,81 @@ pub async fn main() -> Result<()> {
         Commands::Add(args) => {
             commands::cmd_add(&repo, &args.test, args.forget, args.keep, &args.command).await
         }
-        Commands::List => commands::cmd_list(&repo).await,
+        Commands::List => commands::cmd_list(&repo, cli.format).await,
         Commands::Run(args) => {
             commands::cmd_run(
                 &repo,
-                args.test.as_deref(),
-                args.all,
-                args.force,
-                args.forget,
-                args.retest,
-                args.keep_going,
-                args.dry_run,
+                &args.selection.to_selection(),
+                &args.commits,
+                &args.run_options(cli.format),
+            )
+            .await
+        }
+        Commands::Range(args) => {
+            warn!("\"git test range\" is obsolete; please use \"git test run\" instead");
+            commands::cmd_run(
+                &repo,
+                &args.selection.to_selection(),
+                &args.commits,
+                &args.run_options(cli.format),
+            )
+            .await
+        }
+        Commands::Results(args) => {
+            commands::cmd_results(
+                &repo,
+                &args.selection.to_selection(),
                 args.stdin,
                 &args.commits,
-                args.worktree.as_deref(),
+                cli.format,
+            )
+            .await
+        }
+        Commands::ForgetResults(args) => commands::cmd_forget_results(&repo, &args.test).await,
+        Commands::Gc(args) => {
+            let older_than = args
+                .unreachable_older_than
+                .as_deref()
+                .map(crate::definitions::parse_duration)
+                .transpose()?;
+            commands::cmd_gc(&repo, older_than, args.dry_run).await
+        }
+        Commands::Show(args) => commands::cmd_show(&repo, &args.commit, cli.format).await,
+        Commands::Log(args) => {
+            let selection = args.selection.given_selection();
+            commands::cmd_log(&repo, &selection, &args.commits, cli.format).await
+        }
+        Commands::Stats(args) => {
+            let selection = args.selection.given_selection();
+            commands::cmd_stats(&repo, &selection, &args.commits, cli.format).await
+        }
+        Commands::Remove(args) => commands::cmd_remove(&repo, &args.test).await,
+        Commands::Rename(args) => commands::cmd_rename(&repo, &args.old, &args.new).await,
+        Commands::Copy(args) => {
+            commands::cmd_copy(&repo, &args.old, &args.new, args.with_results).await
+        }
+        Commands::Push(args) => commands::cmd_push(&repo, &args.remote).await,
+        Commands::Fetch(args) => commands::cmd_fetch(&repo, &args.remote).await,
+        Commands::Watch(args) => {
+            commands::cmd_watch(
+                &repo,
+                &args.selection.to_selection(),
+                &args.commits,
+                &args.worktree,
+                std::time::Duration::from_secs(args.interval),
             )
             .await
         }
-        _ => unimplemented!("Other commands need to be updated"),
+        Commands::InstallHook(args) => {
+            commands::cmd_install_hook(&repo, args.hook, args.test.as_deref()).await
+        }
+        Commands::UninstallHook(args) => commands::cmd_uninstall_hook(&repo, args.hook).await,
+        Commands::Serve(args) => commands::cmd_serve(&repo, &args.listen, &args.worktree).await,
+        Commands::Report(args) => {
+            commands::cmd_report(&repo, &args.html, args.stdin, &args.commits).await
+        }
+        Commands::MergeNotes(args) => {
+            commands::cmd_merge_notes(&repo, &args.test, &args.notes_ref).await
+        }
     }
 }
@@ -6,7 +6,7 @@ pub mod test_logging {
     static INIT: Once = Once::new();
 
     thread_local! {
-        static LOG_CONTENTS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+        static LOG_CONTENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
     }
 
     struct TestLogger;
@@ -58,10 +58,33 @@ pub mod test_git {
 
     pub fn init_git_repo(temp_dir: &Path) {
         std::process::Command::new("git")
-            .args(&["init"])
+            .args(["init"])
             .current_dir(temp_dir)
             .status()
             .unwrap();
+        git(temp_dir, &["config", "user.name", "Test User"]);
+        git(temp_dir, &["config", "user.email", "test@example.com"]);
+        git(temp_dir, &["config", "commit.gpgsign", "false"]);
+    }
+
+    pub fn git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Writes `contents` to `path` in the repository and commits it, returning the new commit.
+    pub fn commit_file(dir: &Path, path: &str, contents: &str) -> String {
+        let file_path = dir.join(path);
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        std::fs::write(&file_path, contents).unwrap();
+        git(dir, &["add", path]);
+        git(dir, &["commit", "-q", "-m", &format!("Update {}", path)]);
+        git(dir, &["rev-parse", "HEAD"])
     }
 
     pub async fn setup_test() -> (TempDir, GitRepository) {
@@ -73,46 +96,395 @@ pub mod test_git {
     }
 }
 
+/// An in-memory stand-in for a repository, so that command logic can be tested without the
+/// `git` binary. History is linear: each commit's parent is the previous commit. Trees are
+/// named by a hash of their contents, so commits with the same files share results.
+pub mod test_mock_git {
+    use anyhow::{Context, Result};
+    use async_trait::async_trait;
+    use git_test::git::{GitBackend, GitRepository};
+    use regex::Regex;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeMap, HashMap};
+    use std::hash::{Hash, Hasher};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    type Files = BTreeMap<String, String>;
+
+    #[derive(Debug, Default)]
+    struct State {
+        commits: Vec<(String, Files)>,
+        config: Vec<(String, String)>,
+        /// Notes ref -> annotated object -> note blob.
+        notes: HashMap<String, BTreeMap<String, String>>,
+        blobs: HashMap<String, String>,
+        checkouts: Vec<(PathBuf, String)>,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct MockBackend {
+        state: Mutex<State>,
+    }
+
+    fn hash_id(value: impl Hash) -> String {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        format!("{:040x}", hasher.finish())
+    }
+
+    impl MockBackend {
+        /// Adds a commit on top of the current one, replacing the tree with `files`, and
+        /// returns its SHA.
+        pub fn commit(&self, files: &[(&str, &str)]) -> String {
+            let mut state = self.state.lock().unwrap();
+            let sha = format!("c{:039x}", state.commits.len() + 1);
+            let files: Files = files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect();
+            for contents in files.values() {
+                state.blobs.insert(hash_id(contents), contents.clone());
+            }
+            state.commits.push((sha.clone(), files));
+            sha
+        }
+
+        /// Every `(worktree, commit)` checked out so far, in order.
+        pub fn checkouts(&self) -> Vec<(PathBuf, String)> {
+            self.state.lock().unwrap().checkouts.clone()
+        }
+
+        fn state(&self) -> std::sync::MutexGuard<'_, State> {
+            self.state.lock().unwrap()
+        }
+
+        fn checkout(&self, path: &Path, commit: &str) -> Result<()> {
+            let mut state = self.state();
+            let (_, files) = state
+                .commits
+                .iter()
+                .find(|(sha, _)| sha == commit)
+                .with_context(|| format!("No such commit {}", commit))?;
+            if path.exists() {
+                std::fs::remove_dir_all(path)?;
+            }
+            std::fs::create_dir_all(path)?;
+            for (file, contents) in files {
+                std::fs::write(path.join(file), contents)?;
+            }
+            state
+                .checkouts
+                .push((path.to_path_buf(), commit.to_string()));
+            Ok(())
+        }
+    }
+
+    impl State {
+        fn commit_index(&self, name: &str) -> Option<usize> {
+            let (name, back) = match name.split_once('~') {
+                Some((name, back)) => (name, back.parse::<usize>().ok()?),
+                None => (name, 0),
+            };
+            let index = match name {
+                "HEAD" => self.commits.len().checked_sub(1)?,
+                sha => self.commits.iter().position(|(commit, _)| commit == sha)?,
+            };
+            index.checked_sub(back)
+        }
+
+        fn rev_parse(&self, spec: &str) -> Option<String> {
+            if let Some(commit) = spec.strip_suffix("^{tree}") {
+                let (_, files) = &self.commits[self.commit_index(commit)?];
+                return Some(hash_id(files));
+            }
+            if let Some((commit, subdir)) = spec.split_once(':') {
+                let (_, files) = &self.commits[self.commit_index(commit)?];
+                if let Some(contents) = files.get(subdir) {
+                    return Some(hash_id(contents));
+                }
+                let prefix = format!("{}/", subdir);
+                let subtree: Files = files
+                    .iter()
+                    .filter(|(path, _)| path.starts_with(&prefix))
+                    .map(|(path, contents)| (path.clone(), contents.clone()))
+                    .collect();
+                return (!subtree.is_empty()).then(|| hash_id(&subtree));
+            }
+            let commit = spec.strip_suffix("^{commit}").unwrap_or(spec);
+            Some(self.commits[self.commit_index(commit)?].0.clone())
+        }
+    }
+
+    #[async_trait]
+    impl GitBackend for MockBackend {
+        async fn rev_parse(&self, spec: &str) -> Result<Option<String>> {
+            Ok(self.state().rev_parse(spec))
+        }
+
+        async fn rev_list(&self, range: &str) -> Result<Vec<String>> {
+            let state = self.state();
+            let (from, to) = range.split_once("..").context("Not a range")?;
+            let from = state.commit_index(from).context("Unknown commit")?;
+            let to = state.commit_index(to).context("Unknown commit")?;
+            Ok(state.commits[(from + 1).min(to + 1)..=to]
+                .iter()
+                .map(|(sha, _)| sha.clone())
+                .collect())
+        }
+
+        async fn config_get(&self, key: &str) -> Result<Option<String>> {
+            let state = self.state();
+            Ok(state
+                .config
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.clone()))
+        }
+
+        async fn config_get_regexp(&self, pattern: &str) -> Result<Vec<(String, String)>> {
+            let pattern = Regex::new(pattern)?;
+            Ok(self
+                .state()
+                .config
+                .iter()
+                .filter(|(key, _)| pattern.is_match(key))
+                .cloned()
+                .collect())
+        }
+
+        async fn config_set(&self, key: &str, value: &str) -> Result<()> {
+            let mut state = self.state();
+            match state.config.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value.to_string(),
+                None => state.config.push((key.to_string(), value.to_string())),
+            }
+            Ok(())
+        }
+
+        async fn config_add(&self, key: &str, value: &str) -> Result<()> {
+            let mut state = self.state();
+            state.config.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+
+        async fn config_remove_section(&self, section: &str) -> Result<()> {
+            let mut state = self.state();
+            let before = state.config.len();
+            state
+                .config
+                .retain(|(key, _)| key.rsplit_once('.').map(|(s, _)| s) != Some(section));
+            anyhow::ensure!(state.config.len() < before, "No such section '{}'", section);
+            Ok(())
+        }
+
+        async fn config_rename_section(&self, section: &str, new_section: &str) -> Result<()> {
+            let mut state = self.state();
+            let mut renamed = false;
+            for (key, _) in &mut state.config {
+                if let Some((key_section, name)) = key.rsplit_once('.') {
+                    if key_section == section {
+                        *key = format!("{}.{}", new_section, name);
+                        renamed = true;
+                    }
+                }
+            }
+            anyhow::ensure!(renamed, "No such section '{}'", section);
+            Ok(())
+        }
+
+        async fn note_read(&self, ref_name: &str, object: &str) -> Result<Option<String>> {
+            let state = self.state();
+            let Some(object) = state.rev_parse(object) else {
+                return Ok(None);
+            };
+            Ok(state
+                .notes
+                .get(ref_name)
+                .and_then(|notes| notes.get(&object))
+                .map(|blob| state.blobs[blob].clone()))
+        }
+
+        async fn note_write(&self, ref_name: &str, object: &str, content: &str) -> Result<()> {
+            let mut state = self.state();
+            let object = state.rev_parse(object).context("Unknown object")?;
+            let blob = hash_id(content);
+            state.blobs.insert(blob.clone(), content.to_string());
+            state
+                .notes
+                .entry(ref_name.to_string())
+                .or_default()
+                .insert(object, blob);
+            Ok(())
+        }
+
+        async fn note_remove(&self, ref_name: &str, object: &str) -> Result<()> {
+            let mut state = self.state();
+            let object = state.rev_parse(object).context("Unknown object")?;
+            if let Some(notes) = state.notes.get_mut(ref_name) {
+                notes.remove(&object);
+            }
+            Ok(())
+        }
+
+        async fn note_remove_many(&self, ref_name: &str, objects: &[String]) -> Result<()> {
+            for object in objects {
+                self.note_remove(ref_name, object).await?;
+            }
+            Ok(())
+        }
+
+        async fn note_merge(
+            &self,
+            ref_name: &str,
+            _merged_commit: &str,
+            notes: &[(String, String)],
+        ) -> Result<()> {
+            for (object, content) in notes {
+                self.note_write(ref_name, object, content).await?;
+            }
+            Ok(())
+        }
+
+        async fn note_list(&self, ref_name: &str) -> Result<Vec<(String, String)>> {
+            Ok(self
+                .state()
+                .notes
+                .get(ref_name)
+                .into_iter()
+                .flatten()
+                .map(|(object, blob)| (blob.clone(), object.clone()))
+                .collect())
+        }
+
+        async fn read_blob(&self, blob: &str) -> Result<String> {
+            self.state()
+                .blobs
+                .get(blob)
+                .cloned()
+                .with_context(|| format!("No such blob {}", blob))
+        }
+
+        async fn read_blobs(&self, blobs: &[String]) -> Result<Vec<String>> {
+            let mut contents = Vec::new();
+            for blob in blobs {
+                contents.push(self.read_blob(blob).await?);
+            }
+            Ok(contents)
+        }
+
+        async fn blob_sizes(&self, blobs: &[String]) -> Result<Vec<u64>> {
+            let contents = self.read_blobs(blobs).await?;
+            Ok(contents
+                .iter()
+                .map(|content| content.len() as u64)
+                .collect())
+        }
+
+        async fn ref_exists(&self, ref_name: &str) -> Result<bool> {
+            Ok(self.state().notes.contains_key(ref_name))
+        }
+
+        async fn delete_ref(&self, ref_name: &str) -> Result<()> {
+            self.state().notes.remove(ref_name);
+            Ok(())
+        }
+
+        async fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+            let state = self.state();
+            let ancestor = state.commit_index(ancestor).context("Unknown commit")?;
+            let descendant = state.commit_index(descendant).context("Unknown commit")?;
+            Ok(ancestor <= descendant)
+        }
+
+        async fn worktree_add(&self, path: &Path, commit: &str) -> Result<()> {
+            self.checkout(path, commit)
+        }
+
+        async fn worktree_checkout(&self, path: &Path, commit: &str) -> Result<()> {
+            self.checkout(path, commit)
+        }
+
+        async fn worktree_remove(&self, path: &Path) -> Result<()> {
+            std::fs::remove_dir_all(path)?;
+            Ok(())
+        }
+    }
+
+    /// A repository backed by a fresh `MockBackend`, rooted in a temporary directory that
+    /// holds its worktrees.
+    pub fn setup_mock() -> (TempDir, GitRepository, Arc<MockBackend>) {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = Arc::new(MockBackend::default());
+        let repo = GitRepository::with_backend(temp_dir.path().to_path_buf(), backend.clone());
+        (temp_dir, repo, backend)
+    }
+}
+
 pub mod test_cli {
     use clap::{ColorChoice, Parser};
     use git_test::cli::{Cli, Commands};
+    use git_test::output::OutputFormat;
 
     #[test]
     fn test_color_default_is_auto() {
-        let cli = Cli::try_parse_from(&["git-test", "list"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "list"]).unwrap();
         assert_eq!(cli.color, ColorChoice::Auto);
     }
 
     #[test]
     fn test_color_always() {
-        let cli = Cli::try_parse_from(&["git-test", "--color", "always", "list"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "--color", "always", "list"]).unwrap();
         assert_eq!(cli.color, ColorChoice::Always);
     }
 
     #[test]
     fn test_color_never() {
-        let cli = Cli::try_parse_from(&["git-test", "--color", "never", "list"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "--color", "never", "list"]).unwrap();
         assert_eq!(cli.color, ColorChoice::Never);
     }
 
     #[test]
     fn test_invalid_color_choice() {
-        let result = Cli::try_parse_from(&["git-test", "--color", "invalid", "list"]);
+        let result = Cli::try_parse_from(["git-test", "--color", "invalid", "list"]);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_format_default_is_human() {
+        let cli = Cli::try_parse_from(["git-test", "list"]).unwrap();
+        assert_eq!(cli.format, OutputFormat::Human);
+
+        let cli = Cli::try_parse_from(["git-test", "results", "--format", "ndjson"]).unwrap();
+        assert_eq!(cli.format, OutputFormat::Ndjson);
+    }
+
     #[test]
     fn test_subcommand_parsing() {
-        let cli = Cli::try_parse_from(&["git-test", "list"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "list"]).unwrap();
         assert!(matches!(cli.command, Commands::List));
 
-        let cli =
-            Cli::try_parse_from(&["git-test", "add", "--test", "default", "command"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "add", "--test", "default", "command"]).unwrap();
         assert!(matches!(cli.command, Commands::Add(_)));
 
-        let cli = Cli::try_parse_from(&["git-test", "run", "--test", "default"]).unwrap();
+        let cli = Cli::try_parse_from(["git-test", "run", "--test", "default"]).unwrap();
         assert!(matches!(cli.command, Commands::Run(_)));
     }
+
+    #[test]
+    fn test_run_all_does_not_imply_a_test() {
+        let cli = Cli::try_parse_from(["git-test", "run", "--all"]).unwrap();
+        let Commands::Run(args) = cli.command else {
+            panic!("expected the run command");
+        };
+        assert!(args.selection.all);
+        assert!(args.selection.test.is_empty());
+        assert!(args.selection.to_selection().tests.is_empty());
+
+        assert!(Cli::try_parse_from(["git-test", "run", "--all", "--test", "lint"]).is_err());
+    }
 }
 mod test_command_add {
     use anyhow::Result;
@@ -320,6 +692,7 @@ mod test_command_list {
     use crate::test_logging::{clear_log_contents, get_log_contents, setup_logger};
     use anyhow::Result;
     use git_test::commands::cmd_list;
+    use git_test::output::OutputFormat;
 
     #[tokio::test]
     async fn test_list_tests() -> Result<()> {
@@ -341,21 +714,2822 @@ mod test_command_list {
         .await?;
         repo.set_test_command("empty-command", "").await?;
 
-        cmd_list(&repo).await?;
+        cmd_list(&repo, OutputFormat::Human).await?;
 
         let log_contents = get_log_contents();
         let expected_logs = vec![
             "default:",
             "    command = just default",
+            "    source = git config",
             "spotless-formats:",
             "    command = just spotless formats",
+            "    source = git config",
             "spotless-java-sort-imports:",
             "    command = just spotless java-sort-imports",
+            "    source = git config",
             "empty-command:",
             "    command = ",
+            "    source = git config",
         ];
 
         assert_eq!(log_contents, expected_logs);
         Ok(())
     }
 }
+
+mod test_command_run {
+    use crate::test_git::{commit_file, setup_test};
+    use crate::test_logging::{
+        clear_log_contents, get_log_contents, set_color_enabled, setup_logger,
+    };
+    use anyhow::Result;
+    use git_test::commands::{cmd_run, RunOptions};
+    use git_test::git::TestStatus;
+    use git_test::runner::TestSelection;
+
+    #[tokio::test]
+    async fn test_run_skips_known_results() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "hello");
+        repo.set_test_command("default", "true").await?;
+
+        clear_log_contents();
+        cmd_run(
+            &repo,
+            &TestSelection::test("default"),
+            &[],
+            &RunOptions {
+                ..RunOptions::default()
+            },
+        )
+        .await?;
+        cmd_run(
+            &repo,
+            &TestSelection::test("default"),
+            &[],
+            &RunOptions {
+                ..RunOptions::default()
+            },
+        )
+        .await?;
+
+        assert_eq!(
+            get_log_contents(),
+            vec![
+                format!("{} good default", commit),
+                format!("{} known-good default", commit),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_first_bad_commit() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), "README", "base");
+        let first = commit_file(temp_dir.path(), "README", "hello");
+        commit_file(temp_dir.path(), "README", "goodbye");
+        repo.set_test_command("default", "false").await?;
+
+        let result = cmd_run(
+            &repo,
+            &TestSelection::test("default"),
+            &[format!("{}..HEAD", base)],
+            &RunOptions {
+                ..RunOptions::default()
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        let git_test_command = repo.get_test_command("default").await?;
+        assert_eq!(
+            git_test_command.get_result(&first).await?,
+            Some(TestStatus::Bad)
+        );
+        assert_eq!(git_test_command.get_result("HEAD").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subtree_results_carry_across_unrelated_changes() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        commit_file(temp_dir.path(), "lib/a.txt", "a");
+        let first = commit_file(temp_dir.path(), "docs/b.txt", "b");
+        repo.set_test_command("lib", "true").await?;
+        repo.set_config_value("test.lib.tree", "lib").await?;
+        repo.set_test_command("whole", "true").await?;
+
+        cmd_run(
+            &repo,
+            &TestSelection::all(),
+            std::slice::from_ref(&first),
+            &RunOptions {
+                ..RunOptions::default()
+            },
+        )
+        .await?;
+
+        let second = commit_file(temp_dir.path(), "docs/b.txt", "changed");
+        let lib = repo.get_test_command("lib").await?;
+        let whole = repo.get_test_command("whole").await?;
+        assert_eq!(lib.get_result(&second).await?, Some(TestStatus::Good));
+        assert_eq!(whole.get_result(&second).await?, None);
+
+        let third = commit_file(temp_dir.path(), "lib/a.txt", "changed");
+        assert_eq!(lib.get_result(&third).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subtree_missing_from_commit_uses_whole_tree() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "docs/b.txt", "b");
+        repo.set_test_command("lib", "true").await?;
+        repo.set_config_value("test.lib.tree", "lib").await?;
+        let lib = repo.get_test_command("lib").await?;
+
+        assert_eq!(lib.get_result(&commit).await?, None);
+        lib.set_result(&commit, TestStatus::Bad).await?;
+        assert_eq!(lib.get_result(&commit).await?, Some(TestStatus::Bad));
+        assert_eq!(
+            lib.result_object(&commit).await?,
+            format!("{}^{{tree}}", commit)
+        );
+
+        let with_lib = commit_file(temp_dir.path(), "lib/a.txt", "a");
+        assert_eq!(lib.get_result(&with_lib).await?, None);
+        assert_eq!(
+            lib.result_object(&with_lib).await?,
+            format!("{}:lib", with_lib)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_all_from_command_line() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("first", "true").await?;
+        repo.set_test_command("second", "true").await?;
+
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(["run", "--all"])
+            .current_dir(temp_dir.path())
+            .status()?;
+        assert!(status.success());
+        for test in ["first", "second"] {
+            let git_test_command = repo.get_test_command(test).await?;
+            assert_eq!(
+                git_test_command.get_result(&commit).await?,
+                Some(TestStatus::Good)
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_run_stops_tests_and_removes_worktrees() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), ".gitignore", ".worktrees\n");
+        let pid_dir = tempfile::TempDir::new()?;
+        let pid_file = pid_dir.path().join("pid");
+        repo.set_test_command(
+            "slow",
+            &format!("echo $$ > '{}'; sleep 30", pid_file.display()),
+        )
+        .await?;
+
+        let mut git_test = std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(["run", "--test", "slow"])
+            .current_dir(temp_dir.path())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        let start = std::time::Instant::now();
+        while !pid_file.exists() || std::fs::read_to_string(&pid_file)?.trim().is_empty() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        let process_group = std::fs::read_to_string(&pid_file)?.trim().to_string();
+
+        let interrupted = std::process::Command::new("kill")
+            .args(["-INT", &git_test.id().to_string()])
+            .status()?;
+        assert!(interrupted.success());
+        assert!(!git_test.wait()?.success());
+
+        // The test's shell leads its process group; the `sleep` in it must be gone too. Killed
+        // processes may linger as zombies, which don't count.
+        let processes = std::process::Command::new("ps")
+            .args(["-eo", "pgid=,stat="])
+            .output()?;
+        let live_in_group = String::from_utf8(processes.stdout)?
+            .lines()
+            .filter_map(|line| line.trim().split_once(char::is_whitespace))
+            .filter(|(group, stat)| group.trim() == process_group && !stat.trim().starts_with('Z'))
+            .count();
+        assert_eq!(live_in_group, 0);
+        assert!(!temp_dir
+            .path()
+            .join(".worktrees")
+            .join(&commit)
+            .join("slow")
+            .exists());
+        let slow = repo.get_test_command("slow").await?;
+        assert_eq!(slow.get_result(&commit).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_worktree_is_removed_when_a_test_cannot_run() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), ".gitignore", ".worktrees\n");
+        repo.set_test_command("default", "true").await?;
+
+        // Spilling the output fails, after the worktree was created.
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(["run", "--worktree", ".worktrees", "--spill-output"])
+            .env("TMPDIR", temp_dir.path().join("missing"))
+            .current_dir(temp_dir.path())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()?;
+        assert!(!status.success());
+        assert!(!temp_dir
+            .path()
+            .join(".worktrees")
+            .join(&commit)
+            .join("default")
+            .exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_echoed_stderr_goes_to_stderr() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("default", "echo to-stdout; echo to-stderr >&2")
+            .await?;
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(["run", "--color", "never"])
+            .current_dir(temp_dir.path())
+            .output()?;
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout)?;
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stdout.contains("to-stdout"), "{}", stdout);
+        assert!(!stdout.contains("to-stderr"), "{}", stdout);
+        assert!(stderr.contains("to-stderr"), "{}", stderr);
+        Ok(())
+    }
+}
+
+mod test_command_push_fetch {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::{cmd_fetch, cmd_push};
+    use git_test::git::{get_repo_root, TestStatus};
+    use git_test::TestRunner;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_push_and_fetch_results_through_bare_remote() -> Result<()> {
+        setup_logger();
+        let remote_dir = TempDir::new()?;
+        git(remote_dir.path(), &["init", "-q", "--bare"]);
+        let remote = remote_dir.path().to_str().unwrap();
+
+        let (temp_dir, repo) = setup_test().await;
+        let first = commit_file(temp_dir.path(), "README", "hello");
+        let second = commit_file(temp_dir.path(), "README", "goodbye");
+        git(
+            temp_dir.path(),
+            &["push", "-q", remote, "HEAD:refs/heads/main"],
+        );
+        repo.set_test_command("default", "true").await?;
+        repo.get_test_command("default")
+            .await?
+            .set_result(&first, TestStatus::Good)
+            .await?;
+        cmd_push(&repo, remote).await?;
+
+        let clone_parent = TempDir::new()?;
+        git(
+            clone_parent.path(),
+            &["clone", "-q", "-b", "main", remote, "clone"],
+        );
+        let clone_path = clone_parent.path().join("clone");
+        git(&clone_path, &["config", "user.name", "Test User"]);
+        git(&clone_path, &["config", "user.email", "test@example.com"]);
+        let clone = get_repo_root(&clone_path).await?;
+        clone.set_test_command("default", "true").await?;
+        let clone_test = clone.get_test_command("default").await?;
+
+        // Record a result locally first, so that the fetched notes have diverged.
+        clone_test.set_result(&second, TestStatus::Bad).await?;
+        cmd_fetch(&clone, remote).await?;
+
+        assert_eq!(clone_test.get_result(&first).await?, Some(TestStatus::Good));
+        assert_eq!(clone_test.get_result(&second).await?, Some(TestStatus::Bad));
+        assert!(clone
+            .list_refs("refs/notes/tests-incoming/")
+            .await?
+            .is_empty());
+
+        // The clone's results now include the remote's, so it can push on top of them.
+        let third = commit_file(&clone_path, "README", "again");
+        TestRunner::new(&clone).test("default").run().await?;
+        git(
+            &clone_path,
+            &["push", "-q", "origin", "HEAD:refs/heads/main"],
+        );
+        cmd_push(&clone, remote).await?;
+
+        // And so can the original repository, once it has fetched those in turn.
+        let test = repo.get_test_command("default").await?;
+        test.set_result(&second, TestStatus::Good).await?;
+        git(temp_dir.path(), &["fetch", "-q", remote, "main"]);
+        cmd_fetch(&repo, remote).await?;
+        assert_eq!(test.get_result(&third).await?, Some(TestStatus::Good));
+        assert_eq!(test.get_result(&second).await?, Some(TestStatus::Flaky));
+        cmd_push(&repo, remote).await?;
+        assert_eq!(
+            git(
+                remote_dir.path(),
+                &["rev-parse", "refs/notes/tests/default"]
+            ),
+            git(temp_dir.path(), &["rev-parse", "refs/notes/tests/default"])
+        );
+        Ok(())
+    }
+}
+
+mod test_merge_notes {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::cmd_merge_notes;
+    use git_test::git::{TestNote, TestStatus};
+    use git_test::runner::RunOutcome;
+    use git_test::TestRunner;
+
+    fn note(status: TestStatus, timestamp: u64) -> TestNote {
+        TestNote {
+            timestamp,
+            ..TestNote::new(status)
+        }
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let old_good = note(TestStatus::Good, 100);
+        let new_good = note(TestStatus::Good, 200);
+        let new_bad = note(TestStatus::Bad, 200);
+
+        assert_eq!(old_good.merge(&new_good), new_good);
+        assert_eq!(new_good.merge(&old_good), new_good);
+        assert_eq!(old_good.merge(&new_bad), note(TestStatus::Flaky, 200));
+        assert_eq!(new_bad.merge(&old_good), note(TestStatus::Flaky, 200));
+        assert_eq!(
+            note(TestStatus::Flaky, 50).merge(&new_good),
+            note(TestStatus::Flaky, 200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_bad_and_good_for_same_tree() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("default", "true").await?;
+        repo.set_test_command("ci", "true").await?;
+
+        // Neither the newer good result nor the bad one wins outright.
+        let old_bad = note(TestStatus::Bad, 100);
+        let new_good = note(TestStatus::Good, 200);
+        assert_eq!(old_bad.merge(&new_good), note(TestStatus::Flaky, 200));
+        assert_eq!(new_good.merge(&old_bad), note(TestStatus::Flaky, 200));
+
+        let local = repo.get_test_command("default").await?;
+        local.set_result_note(&commit, &old_bad).await?;
+        let incoming = repo.get_test_command("ci").await?;
+        incoming.set_result_note(&commit, &new_good).await?;
+        cmd_merge_notes(&repo, "default", &incoming.notes_ref()).await?;
+        assert_eq!(local.get_result(&commit).await?, Some(TestStatus::Flaky));
+
+        // Like a bad result, a flaky one is a failure until it is tested again.
+        let results = TestRunner::new(&repo).test("default").run().await?;
+        assert!(results.entries[0].is_failure());
+        let results = TestRunner::new(&repo)
+            .test("default")
+            .retest(true)
+            .run()
+            .await?;
+        assert!(matches!(results.entries[0].outcome, RunOutcome::Tested(_)));
+        assert_eq!(local.get_result(&commit).await?, Some(TestStatus::Good));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_legacy_note() {
+        assert_eq!(TestNote::parse("✓"), Some(note(TestStatus::Good, 0)));
+        assert_eq!(TestNote::parse("✗\n"), Some(note(TestStatus::Bad, 0)));
+        assert_eq!(TestNote::parse("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn test_merge_notes_command() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let first = commit_file(temp_dir.path(), "README", "one");
+        let second = commit_file(temp_dir.path(), "README", "two");
+        let third = commit_file(temp_dir.path(), "README", "three");
+        repo.set_test_command("default", "true").await?;
+        repo.set_test_command("ci", "true").await?;
+
+        let local = repo.get_test_command("default").await?;
+        local
+            .set_result_note(&first, &note(TestStatus::Good, 100))
+            .await?;
+        local
+            .set_result_note(&second, &note(TestStatus::Good, 100))
+            .await?;
+
+        // Use the results of another test as a stand-in for another machine's notes.
+        let incoming = repo.get_test_command("ci").await?;
+        incoming
+            .set_result_note(&first, &note(TestStatus::Good, 300))
+            .await?;
+        incoming
+            .set_result_note(&second, &note(TestStatus::Bad, 200))
+            .await?;
+        incoming
+            .set_result_note(&third, &note(TestStatus::Bad, 200))
+            .await?;
+
+        let local_tip = git(temp_dir.path(), &["rev-parse", &local.notes_ref()]);
+        let incoming_tip = git(temp_dir.path(), &["rev-parse", &incoming.notes_ref()]);
+        cmd_merge_notes(&repo, "default", &incoming.notes_ref()).await?;
+
+        // One merge commit, with the incoming notes as its second parent.
+        let merge = git(temp_dir.path(), &["rev-parse", &local.notes_ref()]);
+        assert_eq!(
+            git(
+                temp_dir.path(),
+                &["rev-list", "--parents", "-n", "1", &local.notes_ref()]
+            ),
+            format!("{} {} {}", merge, local_tip, incoming_tip)
+        );
+        // Merging the same notes again finds nothing new.
+        cmd_merge_notes(&repo, "default", &incoming.notes_ref()).await?;
+        assert_eq!(
+            git(temp_dir.path(), &["rev-parse", &local.notes_ref()]),
+            merge
+        );
+
+        assert_eq!(
+            local.get_result_note(&first).await?,
+            Some(note(TestStatus::Good, 300))
+        );
+        assert_eq!(
+            local.get_result_note(&second).await?,
+            Some(note(TestStatus::Flaky, 200))
+        );
+        assert_eq!(
+            local.get_result_note(&third).await?,
+            Some(note(TestStatus::Bad, 200))
+        );
+        Ok(())
+    }
+}
+
+mod test_junit_report {
+    use crate::test_git::{commit_file, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::run::{RunEntry, RunOutcome, TestResult};
+    use git_test::commands::{cmd_run, RunOptions};
+    use git_test::git::{GitSha, TestStatus};
+    use git_test::report::junit::junit_report;
+    use git_test::runner::RunFlags;
+    use git_test::runner::TestSelection;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_writes_junit_report() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let first = commit_file(temp_dir.path(), "README", "one");
+        let second = commit_file(temp_dir.path(), "README", "two");
+        repo.set_test_command("default", "echo 'boom <&>' >&2; false")
+            .await?;
+        repo.get_test_command("default")
+            .await?
+            .set_result(&first, TestStatus::Good)
+            .await?;
+
+        let report_path = temp_dir.path().join("junit.xml");
+        let result = cmd_run(
+            &repo,
+            &TestSelection::test("default"),
+            &[first.clone(), second.clone()],
+            &RunOptions {
+                flags: RunFlags {
+                    keep_going: true,
+                    ..RunFlags::default()
+                },
+                junit: Some(&report_path),
+                ..RunOptions::default()
+            },
+        )
+        .await;
+        assert!(result.is_err());
+
+        let report = std::fs::read_to_string(&report_path)?;
+        assert!(report.contains(r#"<testsuite name="default" tests="2" failures="1" skipped="1""#));
+        assert!(report.contains(&format!(
+            r#"<testcase name="{}" classname="default""#,
+            first
+        )));
+        assert!(report.contains(r#"<skipped message="known-good"/>"#));
+        assert!(report.contains(&format!(
+            r#"<testcase name="{}" classname="default""#,
+            second
+        )));
+        assert!(report.contains(r#"<failure message="exit status 1"/>"#));
+        assert_eq!(report.matches("boom").count(), 1);
+        assert!(report.contains("<system-err>boom &lt;&amp;&gt;\n</system-err>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_junit_report_of_timed_out_test() {
+        let entries = vec![RunEntry {
+            sha: GitSha::new("aaaa".to_string()),
+            test_name: "slow".to_string(),
+            outcome: RunOutcome::Tested(TestResult {
+                test_name: "slow".to_string(),
+                stderr: "Timed out after 5s\n".to_string(),
+                timed_out: Some(Duration::from_secs(5)),
+                ..Default::default()
+            }),
+        }];
+
+        let report = junit_report(&entries);
+        assert!(report.contains(r#"<failure message="timed out after 5s"/>"#));
+        assert!(report.contains("<system-err>Timed out after 5s\n</system-err>"));
+    }
+}
+
+mod test_json_output {
+    use crate::test_git::{commit_file, setup_test};
+    use anyhow::Result;
+    use git_test::commands::list::TestDefinition;
+    use git_test::commands::run::{render_run_report, RunEntry, RunOutcome, TestResult};
+    use git_test::git::{GitSha, TestStatus};
+    use git_test::output::{render_records, OutputFormat};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_list_definitions_as_json() -> Result<()> {
+        let (_temp_dir, repo) = setup_test().await;
+        repo.set_test_command("default", "just default").await?;
+        repo.set_test_command("lib", "cargo test").await?;
+        repo.set_config_value("test.lib.tree", "lib").await?;
+
+        let definitions: Vec<TestDefinition> = repo
+            .list_tests()
+            .await?
+            .iter()
+            .map(TestDefinition::from)
+            .collect();
+
+        let rendered: Value =
+            serde_json::from_str(&render_records(OutputFormat::Json, &definitions)?)?;
+        assert_eq!(
+            rendered,
+            json!([
+                {"name": "default", "command": "just default", "source": "config"},
+                {"name": "lib", "command": "cargo test", "tree": "lib", "source": "config"},
+            ])
+        );
+
+        let lines: Vec<Value> = render_records(OutputFormat::Ndjson, &definitions)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["tree"], "lib");
+        Ok(())
+    }
+
+    fn git_test(dir: &std::path::Path, args: &[&str]) -> std::process::Output {
+        std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_machine_formats_keep_logs_off_stdout() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let dir = temp_dir.path();
+        commit_file(dir, "README", "one");
+        repo.set_test_command("default", "true").await?;
+
+        let output = git_test(dir, &["--format", "json", "list"]);
+        assert!(output.status.success());
+        let listed: Value = serde_json::from_slice(&output.stdout)?;
+        assert_eq!(
+            listed,
+            json!([{"name": "default", "command": "true", "source": "config"}])
+        );
+
+        let output = git_test(dir, &["--format", "ndjson", "range"]);
+        assert!(output.status.success());
+        for line in String::from_utf8(output.stdout)?.lines() {
+            serde_json::from_str::<Value>(line)?;
+        }
+        assert!(String::from_utf8(output.stderr)?.contains("obsolete"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_format_rejected_by_other_commands() -> Result<()> {
+        let (temp_dir, _repo) = setup_test().await;
+        let output = git_test(temp_dir.path(), &["--format", "json", "gc"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)?.contains("--format is only supported by"));
+
+        let output = git_test(temp_dir.path(), &["--format", "human", "gc", "--dry-run"]);
+        assert!(output.status.success());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_report_as_json() -> Result<()> {
+        let entries = vec![
+            RunEntry {
+                sha: GitSha::new("aaaa".to_string()),
+                test_name: "default".to_string(),
+                outcome: RunOutcome::Known(TestStatus::Good),
+            },
+            RunEntry {
+                sha: GitSha::new("bbbb".to_string()),
+                test_name: "default".to_string(),
+                outcome: RunOutcome::Tested(TestResult {
+                    test_name: "default".to_string(),
+                    success: false,
+                    exit_code: Some(2),
+                    stdout: String::new(),
+                    stderr: "oops".to_string(),
+                    duration: Duration::from_millis(1500),
+                    ..Default::default()
+                }),
+            },
+        ];
+
+        let report: Value =
+            serde_json::from_str(&render_run_report(OutputFormat::Json, &entries)?)?;
+        assert_eq!(
+            report["results"][0],
+            json!({"commit": "aaaa", "test": "default", "outcome": "known-good", "status": "good"})
+        );
+        assert_eq!(report["results"][1]["exit_code"], 2);
+        assert_eq!(report["results"][1]["duration_ms"], 1500);
+        assert_eq!(report["results"][1]["stderr"], "oops");
+        assert_eq!(report["summary"]["bad"], 1);
+        assert_eq!(report["summary"]["known"], 1);
+
+        let ndjson = render_run_report(OutputFormat::Ndjson, &entries)?;
+        let last: Value = serde_json::from_str(ndjson.lines().last().unwrap())?;
+        assert_eq!(ndjson.lines().count(), 3);
+        assert_eq!(last["summary"]["bad"], 1);
+        Ok(())
+    }
+}
+
+mod test_tap_output {
+    use anyhow::Result;
+    use git_test::commands::results::CommitStatus;
+    use git_test::commands::run::{RunEntry, RunOutcome, TestResult};
+    use git_test::git::{GitSha, TestStatus};
+    use git_test::report::tap::{results_tap_report, run_tap_report};
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_tap_report() -> Result<()> {
+        let stderr = (1..=25)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let entries = vec![
+            RunEntry {
+                sha: GitSha::new("aaaa".to_string()),
+                test_name: "default".to_string(),
+                outcome: RunOutcome::Known(TestStatus::Good),
+            },
+            RunEntry {
+                sha: GitSha::new("bbbb".to_string()),
+                test_name: "default".to_string(),
+                outcome: RunOutcome::Tested(TestResult {
+                    test_name: "default".to_string(),
+                    success: false,
+                    exit_code: Some(1),
+                    stdout: String::new(),
+                    stderr,
+                    duration: Duration::from_millis(10),
+                    ..Default::default()
+                }),
+            },
+        ];
+
+        let tap = run_tap_report(&entries);
+        let mut expected = String::from(
+            "TAP version 13\n\
+             1..2\n\
+             ok 1 - aaaa default # SKIP known-good\n\
+             not ok 2 - bbbb default\n  \
+             ---\n  \
+             status: bad\n  \
+             exit_code: 1\n  \
+             stderr: |-\n",
+        );
+        for i in 6..=25 {
+            expected.push_str(&format!("    line {}\n", i));
+        }
+        expected.push_str("  ...\n");
+        assert_eq!(tap, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_results_tap_report() {
+        let status = |commit: &str, status: &str| CommitStatus {
+            commit: commit.to_string(),
+            test: "default".to_string(),
+            status: status.to_string(),
+            timestamp: None,
+        };
+
+        let tap = results_tap_report(&[
+            status("aaaa", "good"),
+            status("bbbb", "unknown"),
+            status("cccc", "flaky"),
+        ]);
+        assert_eq!(
+            tap,
+            "TAP version 13\n\
+             1..3\n\
+             ok 1 - aaaa default\n\
+             ok 2 - bbbb default # SKIP unknown\n\
+             not ok 3 - cccc default\n  \
+             ---\n  \
+             status: flaky\n  \
+             ...\n"
+        );
+    }
+}
+
+mod test_command_report {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::{cmd_report, cmd_run, RunOptions};
+    use git_test::git::GitSha;
+    use git_test::runner::RunFlags;
+    use git_test::runner::TestSelection;
+
+    #[tokio::test]
+    async fn test_html_report_from_notes() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), "README", "base");
+        let good = commit_file(temp_dir.path(), "check.sh", "exit 0");
+        let bad = commit_file(temp_dir.path(), "check.sh", "echo '<broken>' >&2; exit 1");
+        repo.set_test_command("check", "sh check.sh").await?;
+        repo.set_test_command("other", "true").await?;
+
+        let range = format!("{}..{}", base, bad);
+        let _ = cmd_run(
+            &repo,
+            &TestSelection::test("check"),
+            std::slice::from_ref(&range),
+            &RunOptions {
+                flags: RunFlags {
+                    keep_going: true,
+                    ..RunFlags::default()
+                },
+                worktree: Some(std::path::Path::new(".worktrees")),
+                ..RunOptions::default()
+            },
+        )
+        .await;
+
+        let out_dir = temp_dir.path().join("out");
+        cmd_report(&repo, &out_dir, false, &[range]).await?;
+        let html = std::fs::read_to_string(out_dir.join("index.html"))?;
+
+        assert!(html.contains("<th>check</th><th>other</th>"));
+        assert!(html.contains(&format!(
+            "<tr><td><code>{}</code></td><td>Update check.sh</td>\
+             <td class=\"status good\">✓</td><td class=\"status unknown\">?</td></tr>",
+            &good[..10]
+        )));
+        assert!(html.contains(&format!(
+            "<td class=\"status bad\"><a href=\"#output-{}-check\">✗</a></td>",
+            bad
+        )));
+        assert!(html.contains(&format!("<section id=\"output-{}-check\">", bad)));
+        assert!(html.contains("<pre>&lt;broken&gt;\n</pre>"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_commit_subjects_are_keyed_by_commit() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let dir = temp_dir.path();
+        let first = commit_file(dir, "README", "one");
+        git(
+            dir,
+            &[
+                "commit",
+                "-q",
+                "--allow-empty",
+                "--allow-empty-message",
+                "-m",
+                "",
+            ],
+        );
+        let empty = git(dir, &["rev-parse", "HEAD"]);
+        let last = commit_file(dir, "README", "two");
+
+        let shas = [&last, &empty, &first, &last].map(|sha| GitSha::new(sha.to_string()));
+        assert_eq!(
+            repo.commit_subjects(&shas).await?,
+            ["Update README", "", "Update README", "Update README"]
+        );
+        Ok(())
+    }
+}
+
+mod test_progress {
+    use crate::test_logging::set_color_enabled;
+    use git_test::git::GitSha;
+    use git_test::progress::Progress;
+
+    #[tokio::test]
+    async fn test_status_lines_track_counts() {
+        set_color_enabled(false);
+        let progress = Progress::new(4, false);
+        let sha = GitSha::new("0123456789abcdef".to_string());
+
+        progress.record_known();
+        progress.start_test(&sha, "default");
+        progress.start_test(&sha, "lint");
+        assert_eq!(
+            progress.status_lines(),
+            vec![
+                "0 good, 0 bad, 1 known, 3 remaining",
+                "  0123456789 default 0:00",
+                "  0123456789 lint 0:00",
+            ]
+        );
+
+        progress.finish_test(&sha, "default", true);
+        progress.finish_test(&sha, "lint", false);
+        let lines = progress.status_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("1 good, 1 bad, 1 known, 1 remaining, ETA "));
+    }
+}
+
+mod test_command_watch {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::run::RunOutcome;
+    use git_test::commands::watch::Watcher;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_watch_tests_only_new_commits_in_warm_worktree() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), "README", "base");
+        let first = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("default", "ls warm 2>/dev/null; touch warm")
+            .await?;
+
+        let tests = vec![repo.get_test_command("default").await?];
+        let range = vec![format!("{}..HEAD", base)];
+        let mut watcher = Watcher::new(&repo, tests, &range, Path::new(".worktrees"));
+
+        let entries = watcher.poll().await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sha.as_str(), first);
+        assert!(watcher.poll().await?.is_empty());
+
+        let second = commit_file(temp_dir.path(), "README", "two");
+        let entries = watcher.poll().await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sha.as_str(), second);
+        match &entries[0].outcome {
+            RunOutcome::Tested(result) => {
+                assert!(result.success);
+                assert_eq!(result.stdout, "warm\n");
+            }
+            outcome => panic!("expected {} to be tested, got {:?}", second, outcome),
+        }
+
+        let worktree = temp_dir.path().join(".worktrees/default");
+        assert_eq!(git(&worktree, &["rev-parse", "HEAD"]), second);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_watch_stops_while_testing() -> Result<()> {
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), ".gitignore", ".worktrees\n");
+        commit_file(temp_dir.path(), "README", "one");
+        let pid_dir = tempfile::TempDir::new()?;
+        let pid_file = pid_dir.path().join("pid");
+        repo.set_test_command(
+            "slow",
+            &format!("echo $$ > '{}'; sleep 30", pid_file.display()),
+        )
+        .await?;
+
+        let mut git_test = std::process::Command::new(env!("CARGO_BIN_EXE_git_test"))
+            .args(["watch", "--test", "slow", &format!("{}..HEAD", base)])
+            .current_dir(temp_dir.path())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        let start = std::time::Instant::now();
+        while !pid_file.exists() || std::fs::read_to_string(&pid_file)?.trim().is_empty() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        let interrupted = std::process::Command::new("kill")
+            .args(["-INT", &git_test.id().to_string()])
+            .status()?;
+        assert!(interrupted.success());
+        let start = std::time::Instant::now();
+        let status = loop {
+            if let Some(status) = git_test.try_wait()? {
+                break status;
+            }
+            if start.elapsed() > std::time::Duration::from_secs(10) {
+                git_test.kill()?;
+                panic!("watch kept going after Ctrl-C");
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        };
+        assert!(status.success());
+        Ok(())
+    }
+}
+
+mod test_command_serve {
+    use crate::test_git::{commit_file, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use git_test::commands::serve::router;
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use tower::ServiceExt;
+
+    async fn send(app: &Router, request: Request<Body>) -> Result<(StatusCode, String)> {
+        let response = app.clone().oneshot(request).await?;
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    async fn get_json(app: &Router, uri: &str) -> Result<Value> {
+        let (status, body) = send(app, Request::get(uri).body(Body::empty())?).await?;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    #[tokio::test]
+    async fn test_serve_runs_and_results() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), "README", "base");
+        let first = commit_file(temp_dir.path(), "README", "one");
+        let second = commit_file(temp_dir.path(), "README", "two");
+        repo.set_test_command("default", "true").await?;
+
+        let app = router(&repo, Path::new(".worktrees"));
+
+        assert_eq!(
+            get_json(&app, "/tests").await?,
+            json!([{"name": "default", "command": "true", "source": "config"}])
+        );
+
+        let (status, body) = send(
+            &app,
+            Request::post("/runs")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({ "commits": [format!("{}..{}", base, second)] }).to_string(),
+                ))?,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body)?,
+            json!({"id": 1, "status": "queued"})
+        );
+
+        let mut run = get_json(&app, "/runs/1").await?;
+        for _ in 0..100 {
+            if run["status"] == "finished" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            run = get_json(&app, "/runs/1").await?;
+        }
+        assert_eq!(run["status"], "finished", "{}", run);
+        assert_eq!(run["events"][2]["result"]["commit"], first.as_str());
+        assert_eq!(run["events"][3]["result"]["commit"], second.as_str());
+        assert_eq!(run["events"][4]["summary"]["good"], 2);
+
+        let (status, events) =
+            send(&app, Request::get("/runs/1/events").body(Body::empty())?).await?;
+        assert_eq!(status, StatusCode::OK);
+        let data: Vec<Value> = events
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        let kinds: Vec<&str> = data.iter().map(|e| e["event"].as_str().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            vec!["queued", "started", "result", "result", "finished"]
+        );
+
+        let results = get_json(&app, &format!("/results?commits={},{}", first, base)).await?;
+        assert_eq!(results[0]["status"], "good");
+        assert_eq!(results[1]["status"], "unknown");
+
+        let (status, _) = send(&app, Request::get("/runs/7").body(Body::empty())?).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        Ok(())
+    }
+}
+
+mod test_command_install_hook {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::cli::HookKind;
+    use git_test::commands::{cmd_install_hook, cmd_uninstall_hook};
+    use git_test::git::TestStatus;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// Runs `git push` with a `git-test` executable on the PATH, returning whether it succeeded.
+    fn push_with_git_test(dir: &Path, bin_dir: &Path, args: &[&str]) -> bool {
+        let path = format!(
+            "{}:{}",
+            bin_dir.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        std::process::Command::new("git")
+            .arg("push")
+            .args(args)
+            .current_dir(dir)
+            .env("PATH", path)
+            .output()
+            .unwrap()
+            .status
+            .success()
+    }
+
+    #[tokio::test]
+    async fn test_install_chains_and_uninstall_restores_existing_hook() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let hook_path = temp_dir.path().join(".git/hooks/pre-push");
+        std::fs::write(&hook_path, "#!/bin/sh\nexit 0\n")?;
+
+        cmd_install_hook(&repo, HookKind::PrePush, None).await?;
+        let installed = std::fs::read_to_string(&hook_path)?;
+        assert!(installed.contains("git test run --keep-going --all --stdin"));
+        assert!(installed.contains(r#"printf '%s\n' "$input" | while read -r"#));
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join(".git/hooks/pre-push.git-test-chained"))?,
+            "#!/bin/sh\nexit 0\n"
+        );
+
+        // Reinstalling replaces our hook without touching the chained one.
+        repo.set_test_command("unit tests", "true").await?;
+        cmd_install_hook(&repo, HookKind::PrePush, Some("unit tests")).await?;
+        assert!(std::fs::read_to_string(&hook_path)?.contains("--test='unit tests'"));
+
+        cmd_uninstall_hook(&repo, HookKind::PrePush).await?;
+        assert_eq!(std::fs::read_to_string(&hook_path)?, "#!/bin/sh\nexit 0\n");
+        assert!(!temp_dir
+            .path()
+            .join(".git/hooks/pre-push.git-test-chained")
+            .exists());
+        assert!(cmd_uninstall_hook(&repo, HookKind::PrePush).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pre_push_hook_blocks_bad_commits() -> Result<()> {
+        setup_logger();
+        let bin_dir = TempDir::new()?;
+        std::os::unix::fs::symlink(
+            env!("CARGO_BIN_EXE_git_test"),
+            bin_dir.path().join("git-test"),
+        )?;
+        let remote_dir = TempDir::new()?;
+        git(remote_dir.path(), &["init", "-q", "--bare"]);
+        let remote = remote_dir.path().to_str().unwrap();
+
+        let (temp_dir, repo) = setup_test().await;
+        commit_file(temp_dir.path(), "check.sh", "exit 0");
+        commit_file(temp_dir.path(), ".gitignore", ".worktrees\n");
+        repo.set_test_command("check", "sh check.sh").await?;
+        cmd_install_hook(&repo, HookKind::PrePush, Some("check")).await?;
+
+        assert!(push_with_git_test(
+            temp_dir.path(),
+            bin_dir.path(),
+            &[remote, "HEAD:refs/heads/main"]
+        ));
+
+        let good = commit_file(temp_dir.path(), "README", "fine");
+        let bad = commit_file(temp_dir.path(), "check.sh", "exit 1");
+        assert!(!push_with_git_test(
+            temp_dir.path(),
+            bin_dir.path(),
+            &[remote, "HEAD:refs/heads/main"]
+        ));
+
+        let check = repo.get_test_command("check").await?;
+        assert_eq!(check.get_result(&good).await?, Some(TestStatus::Good));
+        assert_eq!(check.get_result(&bad).await?, Some(TestStatus::Bad));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pre_push_hook_for_all_tests_allows_pushes_without_tests() -> Result<()> {
+        setup_logger();
+        let bin_dir = TempDir::new()?;
+        std::os::unix::fs::symlink(
+            env!("CARGO_BIN_EXE_git_test"),
+            bin_dir.path().join("git-test"),
+        )?;
+        let remote_dir = TempDir::new()?;
+        git(remote_dir.path(), &["init", "-q", "--bare"]);
+        let remote = remote_dir.path().to_str().unwrap();
+
+        let (temp_dir, repo) = setup_test().await;
+        commit_file(temp_dir.path(), ".gitignore", ".worktrees\n");
+        cmd_install_hook(&repo, HookKind::PrePush, None).await?;
+
+        assert!(push_with_git_test(
+            temp_dir.path(),
+            bin_dir.path(),
+            &[remote, "HEAD:refs/heads/main"]
+        ));
+
+        commit_file(temp_dir.path(), "README", "more");
+        repo.set_test_command("never", "false").await?;
+        assert!(!push_with_git_test(
+            temp_dir.path(),
+            bin_dir.path(),
+            &[remote, "HEAD:refs/heads/main"]
+        ));
+        Ok(())
+    }
+}
+
+mod test_runner {
+    use crate::test_git::{commit_file, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::git::TestStatus;
+    use git_test::runner::RunOutcome;
+    use git_test::TestRunner;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_runner_builder_runs_and_reports_results() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let base = commit_file(temp_dir.path(), "README", "base");
+        let good = commit_file(temp_dir.path(), "README", "good");
+        let bad = commit_file(temp_dir.path(), "broken", "");
+        repo.set_test_command("always", "true").await?;
+        repo.set_test_command("intact", "test ! -f broken").await?;
+
+        let mut seen = Vec::new();
+        let results = TestRunner::new(&repo)
+            .all_tests()
+            .commits([format!("{}..HEAD", base)])
+            .linked_worktrees(Path::new(".worktrees"))
+            .concurrency(1)
+            .keep_going(true)
+            .on_result(|entry| seen.push((entry.sha.as_str().to_string(), entry.is_failure())))
+            .run()
+            .await?;
+
+        assert_eq!(results.stopped_at, None);
+        assert_eq!(results.test_results().count(), 4);
+        let summary = results.summary();
+        assert_eq!((summary.good, summary.bad), (3, 1));
+        let failures: Vec<_> = results.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].sha.as_str(), bad);
+        assert_eq!(failures[0].test_name, "intact");
+        assert_eq!(
+            seen,
+            vec![
+                (good.clone(), false),
+                (good.clone(), false),
+                (bad.clone(), false),
+                (bad.clone(), true),
+            ]
+        );
+
+        let results = TestRunner::new(&repo).test("intact").run().await?;
+        assert_eq!(
+            results.stopped_at.as_ref().map(|sha| sha.as_str()),
+            Some(&*bad)
+        );
+        assert!(matches!(
+            results.entries[0].outcome,
+            RunOutcome::Known(TestStatus::Bad)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runner_requires_a_test() -> Result<()> {
+        let (_temp_dir, repo) = setup_test().await;
+        let err = TestRunner::new(&repo).run().await.unwrap_err();
+        assert_eq!(err.to_string(), "No tests selected");
+        Ok(())
+    }
+}
+
+mod test_git_backend {
+    use crate::test_git::{commit_file, git, setup_test};
+    use anyhow::Result;
+    use git_test::git::{GitBackend, SubprocessBackend};
+    use std::path::Path;
+
+    fn backends(root: &Path) -> Vec<Box<dyn GitBackend>> {
+        #[allow(unused_mut)]
+        let mut backends: Vec<Box<dyn GitBackend>> =
+            vec![Box::new(SubprocessBackend::new(root.to_path_buf()))];
+        #[cfg(feature = "gitoxide")]
+        backends.push(Box::new(
+            git_test::git::gitoxide::GixBackend::open(root).unwrap(),
+        ));
+        backends
+    }
+
+    #[tokio::test]
+    async fn test_backends_agree_with_git() -> Result<()> {
+        let (temp_dir, _repo) = setup_test().await;
+        let root = temp_dir.path();
+        let base = commit_file(root, "README", "base");
+        let first = commit_file(root, "README", "one");
+        let second = commit_file(root, "README", "two");
+
+        for (i, backend) in backends(root).into_iter().enumerate() {
+            let notes_ref = &format!("refs/notes/tests/backend-{}", i);
+            let section = format!("test.my.name{}", i);
+            let key = format!("{}.command", section);
+
+            assert_eq!(
+                backend.rev_list(&format!("{}..HEAD", base)).await?,
+                vec![first.clone(), second.clone()]
+            );
+            assert_eq!(backend.rev_parse("HEAD").await?, Some(second.clone()));
+            assert_eq!(backend.rev_parse("no-such-ref").await?, None);
+            assert_eq!(backend.rev_parse("HEAD:no-such-path").await?, None);
+
+            backend.config_set(&key, "make check").await?;
+            assert_eq!(git(root, &["config", &key]), "make check");
+            assert_eq!(
+                backend.config_get_regexp(r"^test\..*\.command$").await?,
+                vec![(key.clone(), "make check".to_string())]
+            );
+            let tags = format!("{}.tags", section);
+            backend.config_add(&tags, "fast").await?;
+            backend.config_add(&tags, "style").await?;
+            assert_eq!(git(root, &["config", "--get-all", &tags]), "fast\nstyle");
+            let renamed = format!("{}-renamed", section);
+            backend.config_rename_section(&section, &renamed).await?;
+            assert_eq!(backend.config_get(&key).await?, None);
+            assert_eq!(
+                git(root, &["config", "--get-all", &format!("{}.tags", renamed)]),
+                "fast\nstyle"
+            );
+            assert!(backend
+                .config_rename_section(&section, &renamed)
+                .await
+                .is_err());
+            backend.config_rename_section(&renamed, &section).await?;
+            backend.config_remove_section(&section).await?;
+            assert_eq!(backend.config_get(&key).await?, None);
+            assert!(backend.config_remove_section(&section).await.is_err());
+
+            // Another writer holds the config lock.
+            let lock = root.join(".git/config.lock");
+            std::fs::write(&lock, "")?;
+            assert!(backend.config_set(&key, "make check").await.is_err());
+            std::fs::remove_file(&lock)?;
+            assert_eq!(backend.config_get(&key).await?, None);
+
+            let tree = format!("{}^{{tree}}", first);
+            assert!(!backend.ref_exists(notes_ref).await?);
+            assert!(backend.note_list(notes_ref).await?.is_empty());
+            backend.note_write(notes_ref, &tree, "first").await?;
+            backend.note_write(notes_ref, &tree, "replaced").await?;
+            assert_eq!(
+                git(root, &["notes", "--ref", notes_ref, "show", &tree]),
+                "replaced"
+            );
+
+            let other = format!("{}^{{tree}}", second);
+            git(
+                root,
+                &["notes", "--ref", notes_ref, "add", "-m", "from git", &other],
+            );
+            assert_eq!(
+                backend.note_read(notes_ref, &other).await?.as_deref(),
+                Some("from git")
+            );
+            let listed = backend.note_list(notes_ref).await?;
+            assert_eq!(listed.len(), 2);
+            assert_eq!(
+                git(root, &["notes", "--ref", notes_ref, "list"])
+                    .lines()
+                    .count(),
+                2
+            );
+            for (blob, _) in &listed {
+                assert!(!backend.read_blob(blob).await?.is_empty());
+            }
+            let blobs: Vec<String> = listed.iter().map(|(blob, _)| blob.clone()).collect();
+            let contents = backend.read_blobs(&blobs).await?;
+            let mut sorted = contents.clone();
+            sorted.sort();
+            assert_eq!(sorted, ["from git\n", "replaced\n"]);
+            assert_eq!(
+                backend.blob_sizes(&blobs).await?,
+                contents
+                    .iter()
+                    .map(|content| content.len() as u64)
+                    .collect::<Vec<_>>()
+            );
+
+            backend.note_remove(notes_ref, &tree).await?;
+            backend.note_remove(notes_ref, &tree).await?;
+            assert_eq!(backend.note_read(notes_ref, &tree).await?, None);
+            let objects =
+                [other.clone(), tree.clone()].map(|object| git(root, &["rev-parse", &object]));
+            backend.note_write(notes_ref, &tree, "again").await?;
+            let commits = git(root, &["rev-list", "--count", notes_ref]);
+            backend.note_remove_many(notes_ref, &objects).await?;
+            assert!(backend.note_list(notes_ref).await?.is_empty());
+            assert_eq!(
+                git(root, &["rev-list", "--count", notes_ref]).parse::<u32>()?,
+                commits.parse::<u32>()? + 1
+            );
+            backend.delete_ref(notes_ref).await?;
+            assert!(!backend.ref_exists(notes_ref).await?);
+
+            let incoming_ref = &format!("{}-incoming", notes_ref);
+            git(
+                root,
+                &[
+                    "notes",
+                    "--ref",
+                    incoming_ref,
+                    "add",
+                    "-m",
+                    "incoming",
+                    &other,
+                ],
+            );
+            let incoming_tip = git(root, &["rev-parse", incoming_ref]);
+            backend.note_write(notes_ref, &tree, "local").await?;
+            let local_tip = git(root, &["rev-parse", notes_ref]);
+            backend
+                .note_merge(
+                    notes_ref,
+                    &incoming_tip,
+                    &[(objects[0].clone(), "incoming".to_string())],
+                )
+                .await?;
+            assert_eq!(
+                git(root, &["rev-list", "--parents", "-n", "1", notes_ref]),
+                format!(
+                    "{} {} {}",
+                    git(root, &["rev-parse", notes_ref]),
+                    local_tip,
+                    incoming_tip
+                )
+            );
+            assert_eq!(
+                git(root, &["notes", "--ref", notes_ref, "show", &other]),
+                "incoming"
+            );
+            assert_eq!(
+                backend.note_read(notes_ref, &tree).await?.as_deref(),
+                Some("local")
+            );
+            assert!(backend.is_ancestor(&incoming_tip, notes_ref).await?);
+            assert!(!backend.is_ancestor(notes_ref, &incoming_tip).await?);
+            backend.delete_ref(notes_ref).await?;
+            backend.delete_ref(incoming_ref).await?;
+
+            // Only a missing note reads as `None`; a broken notes ref is an error.
+            let blob = git(root, &["rev-parse", &format!("{}:README", first)]);
+            git(root, &["update-ref", notes_ref, &blob]);
+            assert!(backend.note_read(notes_ref, &tree).await.is_err());
+            backend.delete_ref(notes_ref).await?;
+        }
+        Ok(())
+    }
+}
+
+mod test_run_logic {
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::git::{GitRepository, TestStatus};
+    use git_test::runner::{RunOutcome, RunResults};
+    use git_test::TestRunner;
+    use std::path::Path;
+
+    fn runner(repo: &GitRepository) -> TestRunner<'static> {
+        TestRunner::new(repo)
+            .test("default")
+            .linked_worktrees(Path::new("worktrees"))
+    }
+
+    fn labels(results: &RunResults) -> Vec<String> {
+        results
+            .entries
+            .iter()
+            .map(|entry| entry.outcome.label())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_known_results_are_skipped() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        let first = backend.commit(&[("README", "one")]);
+        backend.commit(&[("README", "two")]);
+        let same_tree = backend.commit(&[("README", "one")]);
+        repo.set_test_command("default", "true").await?;
+
+        let results = runner(&repo).commits([&first]).run().await?;
+        assert_eq!(labels(&results), ["good"]);
+        assert_eq!(backend.checkouts().len(), 1);
+
+        let results = runner(&repo)
+            .commits([first.clone(), format!("{}..HEAD", first)])
+            .run()
+            .await?;
+        assert_eq!(labels(&results), ["known-good", "good", "known-good"]);
+        assert_eq!(results.entries[2].sha.as_str(), same_tree);
+        assert_eq!(backend.checkouts().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_first_failure_unless_keep_going() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "base")]);
+        backend.commit(&[("README", "one")]);
+        let broken = backend.commit(&[("README", "two"), ("broken", "")]);
+        let last = backend.commit(&[("README", "three")]);
+        repo.set_test_command("default", "test ! -f broken").await?;
+
+        let results = runner(&repo).commits(["HEAD~3..HEAD"]).run().await?;
+        assert_eq!(labels(&results), ["good", "bad"]);
+        assert_eq!(
+            results.stopped_at.as_ref().map(|sha| sha.as_str()),
+            Some(&*broken)
+        );
+
+        let results = runner(&repo)
+            .commits(["HEAD~3..HEAD"])
+            .keep_going(true)
+            .run()
+            .await?;
+        assert_eq!(labels(&results), ["known-good", "known-bad", "good"]);
+        assert_eq!(results.stopped_at, None);
+        assert_eq!(results.entries[2].sha.as_str(), last);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retest_reruns_bad_results_only() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "base")]);
+        backend.commit(&[("README", "good")]);
+        backend.commit(&[("README", "bad")]);
+        repo.set_test_command("default", "! grep -q bad README")
+            .await?;
+        runner(&repo)
+            .commits(["HEAD~2..HEAD"])
+            .keep_going(true)
+            .run()
+            .await?;
+
+        repo.set_test_command("default", "true").await?;
+        let results = runner(&repo).commits(["HEAD~2..HEAD"]).run().await?;
+        assert_eq!(labels(&results), ["known-good", "known-bad"]);
+
+        let results = runner(&repo)
+            .commits(["HEAD~2..HEAD"])
+            .retest(true)
+            .run()
+            .await?;
+        assert_eq!(labels(&results), ["known-good", "good"]);
+        assert_eq!(backend.checkouts().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_forget_clears_results_without_running() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        let commit = backend.commit(&[("README", "one")]);
+        repo.set_test_command("default", "true").await?;
+        runner(&repo).run().await?;
+        let test = repo.get_test_command("default").await?;
+        assert_eq!(test.get_result(&commit).await?, Some(TestStatus::Good));
+
+        let results = runner(&repo).forget(true).run().await?;
+        assert!(matches!(results.entries[0].outcome, RunOutcome::Forgotten));
+        assert_eq!(test.get_result(&commit).await?, None);
+        assert_eq!(backend.checkouts().len(), 1);
+
+        let results = runner(&repo).dry_run(true).run().await?;
+        assert_eq!(labels(&results), ["unknown"]);
+        Ok(())
+    }
+}
+
+mod test_stream_output {
+    use crate::test_logging::{clear_log_contents, get_log_contents, setup_logger};
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::runner::RunOutcome;
+    use git_test::TestRunner;
+
+    #[tokio::test]
+    async fn test_output_is_logged_live_and_captured() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        let commit = backend.commit(&[("README", "one")]);
+        repo.set_test_command("a", "echo out-a; echo err-a >&2")
+            .await?;
+        repo.set_test_command("b", "echo out-b").await?;
+
+        clear_log_contents();
+        let results = TestRunner::new(&repo)
+            .all_tests()
+            .stream_output(true)
+            .run()
+            .await?;
+        let logs = get_log_contents();
+        // Lines of stderr are echoed to stderr rather than logged.
+        assert!(!logs.iter().any(|log| log.contains("err-a")), "{:?}", logs);
+        for (test, line) in [("a", "out-a"), ("b", "out-b")] {
+            let prefix = format!("{} {} │ ", &commit[..10], test);
+            assert!(
+                logs.iter()
+                    .any(|log| log.contains(&prefix) && log.ends_with(line)),
+                "{:?} not logged with prefix {:?} in {:?}",
+                line,
+                prefix,
+                logs
+            );
+        }
+        match &results.entries[0].outcome {
+            RunOutcome::Tested(result) => {
+                assert_eq!(result.stdout, "out-a\n");
+                assert_eq!(result.stderr, "err-a\n");
+            }
+            outcome => panic!("expected a to be tested, got {:?}", outcome),
+        }
+
+        clear_log_contents();
+        TestRunner::new(&repo)
+            .test("b")
+            .force(true)
+            .stream_output(true)
+            .run()
+            .await?;
+        assert!(get_log_contents().contains(&"out-b".to_string()));
+
+        clear_log_contents();
+        TestRunner::new(&repo).test("b").force(true).run().await?;
+        assert!(get_log_contents().is_empty());
+        Ok(())
+    }
+}
+
+mod test_output_limit {
+    use crate::test_git::{commit_file, setup_test};
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::capture::{parse_size, Capture};
+    use git_test::git::TestStatus;
+    use git_test::runner::{RunOutcome, NOTE_OUTPUT_LIMIT};
+    use git_test::TestRunner;
+
+    #[test]
+    fn test_capture_keeps_head_and_tail() -> Result<()> {
+        let mut capture = Capture::new(Some(4));
+        for chunk in ["abc", "defgh", "ij", "klmn"] {
+            capture.push(chunk.as_bytes())?;
+        }
+        let captured = capture.finish();
+        assert_eq!(captured.elided, 6);
+        assert_eq!(captured.text, "abcd\n[... 6 bytes elided ...]\nklmn");
+
+        let mut capture = Capture::new(Some(4));
+        capture.push(b"abcdefgh")?;
+        assert_eq!(capture.finish().text, "abcdefgh");
+
+        let mut capture = Capture::new(None);
+        capture.push(&[b'x'; 1000])?;
+        assert_eq!(capture.finish().elided, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("2M").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_output_limit_and_spill() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "one")]);
+        repo.set_test_command("default", "seq 1 1000; false")
+            .await?;
+        repo.set_config_value("test.outputLimit", "1k").await?;
+
+        let results = TestRunner::new(&repo)
+            .test("default")
+            .spill_output(true)
+            .run()
+            .await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        let full: String = (1..=1000).map(|i| format!("{}\n", i)).collect();
+        assert_eq!(result.stdout_elided, full.len() as u64 - 2048);
+        assert!(result.stdout.starts_with("1\n2\n3\n"));
+        assert!(result.stdout.ends_with("999\n1000\n"));
+        assert!(result.stdout.contains(" bytes elided ...]\n"));
+        let file = result.stdout_file.as_ref().expect("stdout was spilled");
+        assert_eq!(std::fs::read_to_string(file)?, full);
+        std::fs::remove_file(file)?;
+        std::fs::remove_file(result.stderr_file.as_ref().unwrap())?;
+
+        let note = repo
+            .get_test_command("default")
+            .await?
+            .get_result_note(results.entries[0].sha.as_str())
+            .await?
+            .unwrap();
+        assert_eq!(note.stdout.as_deref(), Some(result.stdout.as_str()));
+        assert_eq!(note.stdout_file, Some(file.display().to_string()));
+
+        let results = TestRunner::new(&repo)
+            .test("default")
+            .force(true)
+            .output_limit(0)
+            .run()
+            .await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        assert_eq!(result.stdout, full);
+        assert_eq!(result.stdout_file, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_limit_of_zero_in_config_keeps_everything() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "one")]);
+        repo.set_test_command("default", "seq 1 1000").await?;
+        repo.set_config_value("test.outputLimit", "0").await?;
+
+        let results = TestRunner::new(&repo).test("default").run().await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        let full: String = (1..=1000).map(|i| format!("{}\n", i)).collect();
+        assert_eq!(result.stdout, full);
+        assert_eq!(result.stdout_elided, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_larger_than_an_argument_is_recorded() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        // More than the 128 KiB Linux allows a single argument, but under the default limit.
+        repo.set_test_command("default", "head -c 200000 /dev/zero | tr '\\0' x; false")
+            .await?;
+
+        let results = TestRunner::new(&repo).test("default").run().await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        assert_eq!(result.stdout.len(), 200_000);
+        let test = repo.get_test_command("default").await?;
+        let note = test.get_result_note(&commit).await?.unwrap();
+        assert_eq!(note.status, TestStatus::Bad);
+        // The note only keeps the end of it.
+        let stdout = note.stdout.unwrap();
+        let tail = stdout
+            .strip_prefix("[... earlier output elided ...]\n")
+            .expect("elision marker");
+        assert_eq!(tail, "x".repeat(NOTE_OUTPUT_LIMIT));
+        Ok(())
+    }
+}
+
+mod test_definitions_file {
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::commands::remove::cmd_remove;
+    use git_test::definitions::{parse_definitions, parse_duration};
+    use git_test::git::TestSource;
+    use git_test::runner::RunOutcome;
+    use git_test::TestRunner;
+    use std::time::Duration;
+
+    const DEFINITIONS: &str = r#"
+[[test]]
+name = "unit"
+command = "echo $GREETING"
+timeout = "2m"
+env = { GREETING = "hello" }
+tags = ["fast"]
+
+[[test]]
+name = "docs"
+command = "make docs"
+paths = ["docs"]
+
+[[test]]
+name = "slow"
+command = "sleep 5"
+timeout = 1
+"#;
+
+    #[test]
+    fn test_parse_definitions() -> Result<()> {
+        let definitions = parse_definitions(DEFINITIONS)?;
+        assert_eq!(definitions.len(), 3);
+        assert_eq!(definitions[0].timeout, Some(Duration::from_secs(120)));
+        assert_eq!(
+            definitions[0].env,
+            [("GREETING".to_string(), "hello".to_string())]
+        );
+        assert_eq!(definitions[0].tags, ["fast"]);
+        assert_eq!(definitions[1].tree.as_deref(), Some("docs"));
+        assert_eq!(definitions[2].timeout, Some(Duration::from_secs(1)));
+
+        let duplicate = "[[test]]\nname = \"a\"\ncommand = \"true\"\n".repeat(2);
+        assert!(parse_definitions(&duplicate).is_err());
+        let two_paths = "[[test]]\nname = \"a\"\ncommand = \"true\"\npaths = [\"x\", \"y\"]\n";
+        assert!(parse_definitions(two_paths).is_err());
+        assert!(parse_definitions("[[test]]\nname = \"a\"\n").is_err());
+
+        assert_eq!(
+            parse_duration("90d")?,
+            Duration::from_secs(90 * 24 * 60 * 60)
+        );
+        assert!(parse_duration("soon").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_overrides_file() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "one"), (".gittest.toml", DEFINITIONS)]);
+        repo.set_test_command("docs", "make html").await?;
+        repo.set_test_command("local", "true").await?;
+
+        let tests = repo.list_tests().await?;
+        let summary: Vec<(&str, &str, TestSource)> = tests
+            .iter()
+            .map(|t| (t.test_name.as_str(), t.test_command.as_str(), t.source))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("unit", "echo $GREETING", TestSource::File),
+                ("docs", "make html", TestSource::ConfigOverFile),
+                ("slow", "sleep 5", TestSource::File),
+                ("local", "true", TestSource::Config),
+            ]
+        );
+        let docs = repo.get_test_command("docs").await?;
+        assert_eq!(docs.tree.as_deref(), Some("docs"));
+
+        assert!(cmd_remove(&repo, "unit").await.is_err());
+        cmd_remove(&repo, "docs").await?;
+        assert_eq!(
+            repo.get_test_command("docs").await?.test_command,
+            "make docs"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_env_and_timeout() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "one"), (".gittest.toml", DEFINITIONS)]);
+
+        let results = TestRunner::new(&repo)
+            .test("unit")
+            .test("slow")
+            .keep_going(true)
+            .run()
+            .await?;
+        let outcomes: Vec<_> = results
+            .entries
+            .iter()
+            .map(|entry| match &entry.outcome {
+                RunOutcome::Tested(result) => (result.success, result.stdout.clone()),
+                other => panic!("unexpected outcome {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            [(true, "hello\n".to_string()), (false, String::new())]
+        );
+        let RunOutcome::Tested(slow) = &results.entries[1].outcome else {
+            unreachable!()
+        };
+        assert!(slow.duration < Duration::from_secs(5));
+        assert_eq!(slow.stderr, "Timed out after 1s\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_definitions_come_from_the_tested_commit() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo, backend) = setup_mock();
+        let old = backend.commit(&[(
+            ".gittest.toml",
+            "[[test]]\nname = \"unit\"\ncommand = \"echo old\"\n",
+        )]);
+        let new = backend.commit(&[(
+            ".gittest.toml",
+            "[[test]]\nname = \"unit\"\ncommand = \"echo new\"\n\
+             [[test]]\nname = \"extra\"\ncommand = \"echo extra\"\n",
+        )]);
+        // Uncommitted edits don't count.
+        std::fs::write(
+            temp_dir.path().join(".gittest.toml"),
+            "[[test]]\nname = \"unit\"\ncommand = \"echo edited\"\n",
+        )?;
+        assert_eq!(
+            repo.get_test_command("unit").await?.test_command,
+            "echo new"
+        );
+
+        let results = TestRunner::new(&repo)
+            .test("unit")
+            .test("extra")
+            .commits([old.clone(), new.clone()])
+            .run()
+            .await?;
+        let outputs: Vec<_> = results
+            .entries
+            .iter()
+            .map(|entry| match &entry.outcome {
+                RunOutcome::Tested(result) => (entry.sha.as_str(), result.stdout.as_str()),
+                other => panic!("unexpected outcome {:?}", other),
+            })
+            .collect();
+        // A test that the tested commit doesn't define yet runs as defined now.
+        assert_eq!(
+            outputs,
+            [
+                (old.as_str(), "old\n"),
+                (old.as_str(), "extra\n"),
+                (new.as_str(), "new\n"),
+                (new.as_str(), "extra\n"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout_keeps_output_so_far() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[(
+            ".gittest.toml",
+            "[[test]]\nname = \"stuck\"\ncommand = \"echo started; echo waiting >&2; sleep 5\"\ntimeout = 1\n",
+        )]);
+
+        let results = TestRunner::new(&repo).test("stuck").run().await?;
+        let RunOutcome::Tested(stuck) = &results.entries[0].outcome else {
+            panic!("unexpected outcome {:?}", results.entries[0].outcome);
+        };
+        assert!(!stuck.success);
+        assert_eq!(stuck.exit_code, None);
+        assert_eq!(stuck.stdout, "started\n");
+        assert_eq!(stuck.stderr, "waiting\nTimed out after 1s\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_background_processes() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo, backend) = setup_mock();
+        let marker = temp_dir.path().join("marker");
+        backend.commit(&[(
+            ".gittest.toml",
+            &format!(
+                "[[test]]\nname = \"orphan\"\ncommand = \"(sleep 2; touch '{}') & sleep 5\"\ntimeout = 1\n",
+                marker.display()
+            ),
+        )]);
+
+        let results = TestRunner::new(&repo).test("orphan").run().await?;
+        let RunOutcome::Tested(orphan) = &results.entries[0].outcome else {
+            panic!("unexpected outcome {:?}", results.entries[0].outcome);
+        };
+        assert!(!orphan.success);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists());
+        Ok(())
+    }
+}
+
+mod test_command_templating {
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::runner::{RunFlags, RunOutcome};
+    use git_test::shell::expand;
+    use git_test::TestRunner;
+
+    #[test]
+    fn test_expand() {
+        let values = [("sha", "abc123"), ("test", "it's")];
+        assert_eq!(
+            expand("tag {sha} {test} {a,b} ${test} {unknown}", &values),
+            r"tag abc123 'it'\''s' {a,b} ${test} {unknown}"
+        );
+        assert_eq!(expand("{{sha}}", &values), "{abc123}");
+
+        // A value with spaces stays one word next to other text, but inside quotes the
+        // quoting it brings along is kept literally.
+        let values = [("test", "my test")];
+        assert_eq!(expand("out-{test}.log", &values), "out-'my test'.log");
+        assert_eq!(expand(r#""{test}.log""#, &values), r#""'my test'.log""#);
+    }
+
+    #[tokio::test]
+    async fn test_placeholders_and_environment() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo, backend) = setup_mock();
+        let sha = backend.commit(&[("README", "one")]);
+        let tree = repo
+            .backend()
+            .rev_parse(&format!("{}^{{tree}}", sha))
+            .await?
+            .unwrap();
+        repo.set_test_command(
+            "my test",
+            "echo {sha} {short} {tree} {worktree} {test}; \
+             echo $GIT_TEST_SHA $GIT_TEST_SHORT $GIT_TEST_TREE $GIT_TEST_WORKTREE $GIT_TEST_NAME",
+        )
+        .await?;
+
+        let results = TestRunner::new(&repo).test("my test").run().await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        let line = format!(
+            "{} {} {} {} my test\n",
+            sha,
+            &sha[..10],
+            tree,
+            temp_dir.path().display()
+        );
+        assert_eq!(result.stdout, format!("{}{}", line, line));
+
+        repo.set_test_command("my test", r#"echo out-{test}.log "{test}.log""#)
+            .await?;
+        let results = TestRunner::new(&repo)
+            .test("my test")
+            .flags(RunFlags {
+                force: true,
+                ..RunFlags::default()
+            })
+            .run()
+            .await?;
+        let RunOutcome::Tested(result) = &results.entries[0].outcome else {
+            panic!("expected a test result");
+        };
+        assert_eq!(result.stdout, "out-my test.log 'my test'.log\n");
+        Ok(())
+    }
+}
+
+mod test_matrix {
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::commands::forget_results::cmd_forget_results;
+    use git_test::commands::remove::cmd_remove;
+    use git_test::definitions::{matrix_combinations, parse_matrix_axis};
+    use git_test::git::TestStatus;
+    use git_test::runner::RunOutcome;
+    use git_test::TestRunner;
+
+    #[test]
+    fn test_matrix_axes() -> Result<()> {
+        let features = parse_matrix_axis("features=default, full")?;
+        assert_eq!(
+            features,
+            (
+                "features".to_string(),
+                vec!["default".into(), "full".into()]
+            )
+        );
+        assert!(parse_matrix_axis("features").is_err());
+        assert!(parse_matrix_axis("features=a,,b").is_err());
+        assert!(parse_matrix_axis("two words=a").is_err());
+        assert!(parse_matrix_axis("features=a b").is_err());
+        assert!(parse_matrix_axis("features=a/b").is_err());
+        assert!(parse_matrix_axis("features=1.0+beta,x_y-z").is_ok());
+        assert!(parse_matrix_axis("sha=a,b").is_err());
+        assert!(parse_matrix_axis("worktree=a").is_err());
+
+        let profile = parse_matrix_axis("profile=dev,release")?;
+        let combinations = matrix_combinations(&[features, profile]);
+        assert_eq!(combinations.len(), 4);
+        assert_eq!(
+            combinations[1],
+            [
+                ("features".to_string(), "default".to_string()),
+                ("profile".to_string(), "release".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matrix_variants() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        let sha = backend.commit(&[(
+            ".gittest.toml",
+            "[[test]]\nname = \"lint\"\ncommand = \"echo lint {level}\"\n\
+             matrix = { level = [\"warn\", \"deny\"] }\n",
+        )]);
+        repo.set_test_command("cargo", "echo {features} $GIT_TEST_PARAM_FEATURES")
+            .await?;
+        repo.set_config_value("test.cargo.matrix", "features=default,full,minimal")
+            .await?;
+
+        let names: Vec<String> = repo
+            .list_tests()
+            .await?
+            .into_iter()
+            .map(|t| t.test_name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "lint@level=warn",
+                "lint@level=deny",
+                "cargo@features=default",
+                "cargo@features=full",
+                "cargo@features=minimal",
+            ]
+        );
+
+        let results = TestRunner::new(&repo).test("cargo").run().await?;
+        let outputs: Vec<(String, String)> = results
+            .entries
+            .iter()
+            .map(|entry| match &entry.outcome {
+                RunOutcome::Tested(result) => (entry.test_name.clone(), result.stdout.clone()),
+                other => panic!("unexpected outcome {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            outputs[1],
+            ("cargo@features=full".into(), "full full\n".into())
+        );
+        assert_eq!(outputs.len(), 3);
+
+        let full = repo.get_test_command("cargo@features=full").await?;
+        assert!(full.get_result(&sha).await?.is_some());
+        let results = TestRunner::new(&repo)
+            .test("cargo@features=full")
+            .run()
+            .await?;
+        assert_eq!(results.entries[0].outcome.label(), "known-good");
+
+        assert!(cmd_remove(&repo, "cargo@features=full").await.is_err());
+        cmd_remove(&repo, "cargo").await?;
+        assert!(full.get_result(&sha).await?.is_none());
+        assert_eq!(repo.list_tests().await?.len(), 2);
+
+        let deny = repo.get_test_command("lint@level=deny").await?;
+        deny.set_result(&sha, TestStatus::Good).await?;
+        cmd_forget_results(&repo, "lint").await?;
+        assert!(deny.get_result(&sha).await?.is_none());
+
+        // An unreadable definitions file is reported rather than taken for no variants.
+        backend.commit(&[(".gittest.toml", "[[test]]\nname = ")]);
+        assert!(cmd_forget_results(&repo, "lint").await.is_err());
+        Ok(())
+    }
+}
+
+mod test_tags {
+    use crate::test_logging::setup_logger;
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use clap::Parser;
+    use git_test::cli::{Cli, Commands};
+    use git_test::git::GitRepository;
+    use git_test::runner::TestSelection;
+    use git_test::TestRunner;
+
+    async fn names(repo: &GitRepository, selection: TestSelection) -> Result<Vec<String>> {
+        Ok(selection
+            .resolve(repo)
+            .await?
+            .into_iter()
+            .map(|t| t.test_name)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_select_by_tag() -> Result<()> {
+        setup_logger();
+        let (_temp_dir, repo, backend) = setup_mock();
+        backend.commit(&[("README", "one")]);
+        for (name, tags) in [("unit", "fast"), ("e2e", "slow"), ("lint", "fast, style")] {
+            repo.set_test_command(name, "true").await?;
+            repo.set_config_value(&format!("test.{}.tags", name), tags)
+                .await?;
+        }
+        repo.set_test_command("docs", "true").await?;
+
+        let tags = |tags: &[&str], exclude: &[&str]| TestSelection {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            exclude_tags: exclude.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(names(&repo, tags(&["fast"], &[])).await?, ["unit", "lint"]);
+        assert_eq!(
+            names(&repo, tags(&[], &["slow"])).await?,
+            ["unit", "lint", "docs"]
+        );
+        assert_eq!(names(&repo, tags(&["fast"], &["style"])).await?, ["unit"]);
+        assert_eq!(
+            names(
+                &repo,
+                TestSelection {
+                    tests: vec!["docs".into(), "e2e".into()],
+                    ..tags(&["style"], &[])
+                }
+            )
+            .await?,
+            ["e2e", "lint", "docs"]
+        );
+        assert!(names(&repo, TestSelection::test("missing")).await.is_err());
+
+        let results = TestRunner::new(&repo)
+            .tag("fast")
+            .exclude_tag("style")
+            .run()
+            .await?;
+        assert_eq!(results.entries.len(), 1);
+        assert_eq!(results.entries[0].test_name, "unit");
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_arguments() {
+        let cli = Cli::try_parse_from([
+            "git-test",
+            "run",
+            "--test",
+            "a",
+            "-t",
+            "b",
+            "--tag",
+            "fast",
+            "--exclude-tag",
+            "slow",
+        ])
+        .unwrap();
+        let Commands::Run(args) = cli.command else {
+            panic!("expected run");
+        };
+        let selection = args.selection.to_selection();
+        assert_eq!(selection.tests, ["a", "b"]);
+        assert_eq!(selection.tags, ["fast"]);
+        assert_eq!(selection.exclude_tags, ["slow"]);
+
+        let cli = Cli::try_parse_from(["git-test", "results"]).unwrap();
+        let Commands::Results(args) = cli.command else {
+            panic!("expected results");
+        };
+        assert_eq!(args.selection.to_selection().tests, ["default"]);
+
+        assert!(Cli::try_parse_from(["git-test", "run", "--all", "--test", "a"]).is_err());
+    }
+}
+
+mod test_command_rename_copy {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::setup_logger;
+    use anyhow::Result;
+    use git_test::commands::{cmd_copy, cmd_rename};
+    use git_test::git::TestStatus;
+
+    #[tokio::test]
+    async fn test_rename_moves_definition_and_results() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("lint-fast", "make lint").await?;
+        repo.add_config_value("test.lint-fast.tags", "fast").await?;
+        repo.add_config_value("test.lint-fast.tags", "style")
+            .await?;
+        repo.set_config_value("test.other.command", "true").await?;
+        repo.get_test_command("lint-fast")
+            .await?
+            .set_result(&commit, TestStatus::Bad)
+            .await?;
+
+        assert!(cmd_rename(&repo, "lint-fast", "other").await.is_err());
+        assert!(cmd_rename(&repo, "missing", "new").await.is_err());
+
+        cmd_rename(&repo, "lint-fast", "lint").await?;
+        assert!(repo.get_test_command("lint-fast").await.is_err());
+        assert!(!repo.ref_exists("refs/notes/tests/lint-fast").await?);
+        let lint = repo.get_test_command("lint").await?;
+        assert_eq!(lint.test_command, "make lint");
+        assert_eq!(lint.tags, ["fast", "style"]);
+        assert_eq!(lint.get_result(&commit).await?, Some(TestStatus::Bad));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tests_with_dotted_names_are_left_alone() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("a", "make a").await?;
+        repo.set_test_command("a.b", "make a.b").await?;
+        for test in ["a", "a.b"] {
+            repo.get_test_command(test)
+                .await?
+                .set_result(&commit, TestStatus::Good)
+                .await?;
+        }
+
+        cmd_copy(&repo, "a", "copy", true).await?;
+        assert_eq!(repo.get_test_command("copy").await?.test_command, "make a");
+        assert!(repo.get_test_command("copy.b").await.is_err());
+
+        cmd_rename(&repo, "a", "c").await?;
+        assert_eq!(repo.get_test_command("c").await?.test_command, "make a");
+        let dotted = repo.get_test_command("a.b").await?;
+        assert_eq!(dotted.test_command, "make a.b");
+        assert_eq!(dotted.get_result(&commit).await?, Some(TestStatus::Good));
+        assert!(repo.get_test_command("c.b").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_test_named_with_regex_characters() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("unit-tests", "make test").await?;
+        repo.set_test_command("lint+fix", "make lint").await?;
+
+        cmd_copy(&repo, "unit-tests", "unit-copy", false).await?;
+        assert_eq!(
+            repo.get_test_command("unit-copy").await?.test_command,
+            "make test"
+        );
+        cmd_copy(&repo, "lint+fix", "lint-copy", false).await?;
+        assert_eq!(
+            repo.get_test_command("lint-copy").await?.test_command,
+            "make lint"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(temp_dir.path(), "README", "one");
+        repo.set_test_command("unit", "make test").await?;
+        repo.get_test_command("unit")
+            .await?
+            .set_result(&commit, TestStatus::Good)
+            .await?;
+
+        cmd_copy(&repo, "unit", "plain", false).await?;
+        assert_eq!(
+            repo.get_test_command("plain").await?.test_command,
+            "make test"
+        );
+        assert_eq!(
+            repo.get_test_command("plain")
+                .await?
+                .get_result(&commit)
+                .await?,
+            None
+        );
+
+        cmd_copy(&repo, "unit", "with-results", true).await?;
+        assert_eq!(
+            repo.get_test_command("with-results")
+                .await?
+                .get_result(&commit)
+                .await?,
+            Some(TestStatus::Good)
+        );
+        assert_eq!(
+            repo.get_test_command("unit")
+                .await?
+                .get_result(&commit)
+                .await?,
+            Some(TestStatus::Good)
+        );
+
+        // Results left behind under the new name would be mistaken for the copy's.
+        git(
+            temp_dir.path(),
+            &[
+                "update-ref",
+                "refs/notes/tests/stale",
+                "refs/notes/tests/unit",
+            ],
+        );
+        assert!(cmd_copy(&repo, "unit", "stale", false).await.is_err());
+        assert!(repo.get_test_command("stale").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rename_matrix_and_file_tests() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let commit = commit_file(
+            temp_dir.path(),
+            ".gittest.toml",
+            "[[test]]\nname = \"shared\"\ncommand = \"true\"\n",
+        );
+        repo.set_test_command("cargo", "cargo test --features {features}")
+            .await?;
+        repo.set_config_value("test.cargo.matrix", "features=a,b")
+            .await?;
+        repo.get_test_command("cargo@features=b")
+            .await?
+            .set_result(&commit, TestStatus::Good)
+            .await?;
+
+        assert!(cmd_rename(&repo, "shared", "mine").await.is_err());
+        assert!(cmd_rename(&repo, "cargo@features=b", "x").await.is_err());
+
+        cmd_rename(&repo, "cargo", "build").await?;
+        assert_eq!(
+            repo.get_test_command("build@features=b")
+                .await?
+                .get_result(&commit)
+                .await?,
+            Some(TestStatus::Good)
+        );
+        assert_eq!(repo.select_tests("build").await?.len(), 2);
+        assert!(repo.select_tests("cargo").await.is_err());
+        Ok(())
+    }
+}
+
+mod test_command_show {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::{
+        clear_log_contents, get_log_contents, set_color_enabled, setup_logger,
+    };
+    use anyhow::Result;
+    use git_test::commands::cmd_show;
+    use git_test::commands::show::ResultDetail;
+    use git_test::git::GitSha;
+    use git_test::output::{format_timestamp, OutputFormat};
+    use git_test::TestRunner;
+    use std::path::Path;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_827_696), "2000-02-29 12:34:56");
+        assert_eq!(format_timestamp(1_792_281_600), "2026-10-18 00:00:00");
+    }
+
+    #[tokio::test]
+    async fn test_show_details_and_inheritance() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (temp_dir, repo) = setup_test().await;
+        let first = commit_file(temp_dir.path(), "README", "one");
+        git(
+            temp_dir.path(),
+            &["commit", "-q", "--allow-empty", "-m", "Same tree"],
+        );
+        let second = git(temp_dir.path(), &["rev-parse", "HEAD"]);
+        repo.set_test_command("check", "seq 1 20; echo failed >&2; exit 3")
+            .await?;
+        repo.set_test_command("other", "true").await?;
+
+        TestRunner::new(&repo)
+            .test("check")
+            .commits([&first])
+            .linked_worktrees(Path::new(".worktrees"))
+            .keep_going(true)
+            .run()
+            .await?;
+
+        let check = repo.get_test_command("check").await?;
+        let sha = GitSha::new(second.clone());
+        let detail = ResultDetail::new(&check, &sha, check.get_result_note(&second).await?);
+        assert_eq!(detail.status, "bad");
+        assert_eq!(detail.exit_code, Some(3));
+        assert_eq!(detail.inherited_from.as_deref(), Some(first.as_str()));
+        assert_eq!(detail.fingerprint, Some(check.fingerprint()));
+        assert_eq!(detail.definition_changed, Some(false));
+        assert!(detail.host.is_some());
+        assert_eq!(
+            detail.stdout_tail.as_deref(),
+            Some(
+                (11..=20)
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .as_str()
+            )
+        );
+
+        let detail = ResultDetail::new(
+            &check,
+            &GitSha::new(first.clone()),
+            check.get_result_note(&first).await?,
+        );
+        assert_eq!(detail.inherited_from, None);
+
+        repo.set_test_command("check", "seq 1 20; exit 4").await?;
+        clear_log_contents();
+        cmd_show(&repo, "HEAD", OutputFormat::Human).await?;
+        let logs = get_log_contents();
+        assert_eq!(logs[0], format!("{} Same tree", second));
+        assert_eq!(logs[1], "check: bad");
+        assert_eq!(logs[2], "    exit code = 3");
+        assert!(logs.contains(&format!(
+            "    command = {} (the test has changed since)",
+            check.fingerprint()
+        )));
+        assert!(logs.contains(&format!("    inherited from {} (same tree)", first)));
+        assert!(logs.contains(&"    stdout (last 10 lines):".to_string()));
+        assert!(logs.contains(&"    stderr (last 1 line):".to_string()));
+        assert!(logs.contains(&"other: unknown".to_string()));
+
+        assert!(cmd_show(&repo, "HEAD~1..HEAD", OutputFormat::Human)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
+
+mod test_command_log {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::{
+        clear_log_contents, get_log_contents, set_color_enabled, setup_logger,
+    };
+    use anyhow::Result;
+    use git_test::commands::cmd_log;
+    use git_test::git::TestStatus;
+    use git_test::output::OutputFormat;
+    use git_test::runner::TestSelection;
+
+    #[tokio::test]
+    async fn test_log_graph_with_results() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (temp_dir, repo) = setup_test().await;
+        let dir = temp_dir.path();
+        let base = commit_file(dir, "README", "base");
+        git(dir, &["checkout", "-q", "-b", "side"]);
+        let side = commit_file(dir, "side.txt", "side");
+        git(dir, &["checkout", "-q", "-"]);
+        let main = commit_file(dir, "main.txt", "main");
+        git(dir, &["merge", "-q", "--no-edit", "side"]);
+        let merge = git(dir, &["rev-parse", "HEAD"]);
+
+        repo.set_test_command("build", "true").await?;
+        repo.set_test_command("lint", "true").await?;
+        let build = repo.get_test_command("build").await?;
+        let lint = repo.get_test_command("lint").await?;
+        build.set_result(&base, TestStatus::Good).await?;
+        build.set_result(&side, TestStatus::Bad).await?;
+        build.set_result(&main, TestStatus::Flaky).await?;
+        lint.set_result(&merge, TestStatus::Good).await?;
+
+        let short = |sha: &str| sha[..10].to_string();
+        clear_log_contents();
+        cmd_log(&repo, &TestSelection::default(), &[], OutputFormat::Human).await?;
+        assert_eq!(
+            get_log_contents(),
+            [
+                "Columns: build, lint".to_string(),
+                format!("? ✓ *   {} Merge branch 'side'", short(&merge)),
+                "    |\\".to_string(),
+                format!("✗ ? | * {} Update side.txt", short(&side)),
+                format!("~ ? * | {} Update main.txt", short(&main)),
+                "    |/".to_string(),
+                format!("✓ ? * {} Update README", short(&base)),
+            ]
+        );
+
+        clear_log_contents();
+        let range = format!("{}..{}", base, side);
+        cmd_log(
+            &repo,
+            &TestSelection::test("build"),
+            &[range, base.clone()],
+            OutputFormat::Human,
+        )
+        .await?;
+        assert_eq!(
+            get_log_contents(),
+            [
+                "Columns: build".to_string(),
+                format!("✗ * {} Update side.txt", short(&side)),
+                format!("✓ * {} Update README", short(&base)),
+            ]
+        );
+        Ok(())
+    }
+}
+
+mod test_command_stats {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::{
+        clear_log_contents, get_log_contents, set_color_enabled, setup_logger,
+    };
+    use crate::test_mock_git::setup_mock;
+    use anyhow::Result;
+    use git_test::commands::cmd_stats;
+    use git_test::git::{TestNote, TestStatus};
+    use git_test::output::OutputFormat;
+    use git_test::runner::TestSelection;
+
+    #[tokio::test]
+    async fn test_stats_for_range_and_repo() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (temp_dir, repo) = setup_test().await;
+        let dir = temp_dir.path();
+        let commits: Vec<String> = (1..=5)
+            .map(|i| commit_file(dir, "file.txt", &i.to_string()))
+            .collect();
+
+        repo.set_test_command("build", "true").await?;
+        repo.set_test_command("lint", "true").await?;
+        let build = repo.get_test_command("build").await?;
+        let statuses = [
+            TestStatus::Good,
+            TestStatus::Bad,
+            TestStatus::Bad,
+            TestStatus::Good,
+        ];
+        for (i, status) in statuses.into_iter().enumerate() {
+            let note = TestNote {
+                timestamp: 100 * (i as u64 + 1),
+                commit: Some(commits[i].clone()),
+                duration_ms: Some(1000 * (i as u64 + 1)),
+                ..TestNote::new(status)
+            };
+            build.set_result_note(&commits[i], &note).await?;
+        }
+
+        let short = |i: usize| commits[i][..10].to_string();
+        clear_log_contents();
+        let range = format!("{}..{}", commits[0], commits[4]);
+        cmd_stats(
+            &repo,
+            &TestSelection::test("build"),
+            &[commits[0].clone(), range],
+            OutputFormat::Human,
+        )
+        .await?;
+        assert_eq!(
+            get_log_contents(),
+            [
+                "build:".to_string(),
+                "    results = 2 good, 2 bad, 0 flaky, 1 unknown".to_string(),
+                "    duration = mean 2.5s, p95 4.0s (over 4 timed results)".to_string(),
+                "    trend = 133.3% slower (newer half against older half)".to_string(),
+                format!(
+                    "    slowest = {} 4.0s, {} 3.0s, {} 2.0s, {} 1.0s",
+                    short(3),
+                    short(2),
+                    short(1),
+                    short(0)
+                ),
+                format!(
+                    "    longest bad streak = 2 commits, {} to {}",
+                    short(1),
+                    short(2)
+                ),
+            ]
+        );
+
+        clear_log_contents();
+        cmd_stats(&repo, &TestSelection::default(), &[], OutputFormat::Human).await?;
+        let log = get_log_contents();
+        assert_eq!(log[1], "    results = 2 good, 2 bad, 0 flaky");
+        assert_eq!(log[6..], ["lint:", "    results = 0 good, 0 bad, 0 flaky"]);
+
+        // A commit with the same tree shares its result rather than counting it again.
+        build.set_result(&commits[4], TestStatus::Good).await?;
+        git(dir, &["commit", "-q", "--allow-empty", "-m", "Same tree"]);
+        clear_log_contents();
+        cmd_stats(
+            &repo,
+            &TestSelection::test("build"),
+            &[format!("{}..HEAD", commits[3])],
+            OutputFormat::Human,
+        )
+        .await?;
+        assert_eq!(
+            get_log_contents()[1],
+            "    results = 1 good, 0 bad, 0 flaky"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats_from_stored_results_on_mock_backend() -> Result<()> {
+        setup_logger();
+        set_color_enabled(false);
+        let (_temp_dir, repo, backend) = setup_mock();
+        let good = backend.commit(&[("README", "good")]);
+        let bad = backend.commit(&[("README", "bad")]);
+        repo.set_test_command("build", "true").await?;
+        let build = repo.get_test_command("build").await?;
+        build.set_result(&good, TestStatus::Good).await?;
+        build.set_result(&bad, TestStatus::Bad).await?;
+
+        clear_log_contents();
+        cmd_stats(&repo, &TestSelection::default(), &[], OutputFormat::Human).await?;
+        assert_eq!(
+            get_log_contents()[..2],
+            ["build:", "    results = 1 good, 1 bad, 0 flaky"]
+        );
+        Ok(())
+    }
+}
+
+mod test_command_gc {
+    use crate::test_git::{commit_file, git, setup_test};
+    use crate::test_logging::{clear_log_contents, get_log_contents, setup_logger};
+    use anyhow::Result;
+    use git_test::commands::cmd_gc;
+    use git_test::git::{TestNote, TestStatus};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_gc_prunes_unreachable_trees() -> Result<()> {
+        setup_logger();
+        let (temp_dir, repo) = setup_test().await;
+        let dir = temp_dir.path();
+        let base = commit_file(dir, "README", "base");
+        git(dir, &["checkout", "-q", "-b", "abandoned"]);
+        let old = commit_file(dir, "side.txt", "old");
+        let new = commit_file(dir, "side.txt", "new");
+        let newest = commit_file(dir, "side.txt", "newest");
+        git(dir, &["checkout", "-q", "-"]);
+        git(dir, &["branch", "-q", "-D", "abandoned"]);
+
+        repo.set_test_command("build", "true").await?;
+        let build = repo.get_test_command("build").await?;
+        build.set_result(&base, TestStatus::Good).await?;
+        let old_note = TestNote {
+            timestamp: 100,
+            ..TestNote::new(TestStatus::Bad)
+        };
+        let new_note = TestNote::new(TestStatus::Good);
+        build.set_result_note(&old, &old_note).await?;
+        build.set_result_note(&new, &new_note).await?;
+        build.set_result_note(&newest, &new_note).await?;
+        let note_bytes = |commit: &str| -> u64 {
+            let tree = git(dir, &["rev-parse", &format!("{}^{{tree}}", commit)]);
+            let note = format!("refs/notes/tests/build:{}", tree);
+            git(dir, &["cat-file", "-s", &note]).parse().unwrap()
+        };
+        let old_bytes = note_bytes(&old);
+        let new_bytes = note_bytes(&new) + note_bytes(&newest);
+        let day = Some(Duration::from_secs(24 * 60 * 60));
+
+        clear_log_contents();
+        cmd_gc(&repo, day, true).await?;
+        assert_eq!(
+            get_log_contents(),
+            [
+                format!(
+                    "Would prune 1 results ({} bytes) for test 'build'",
+                    old_bytes
+                ),
+                format!("Would prune 1 results ({} bytes) in total", old_bytes),
+            ]
+        );
+        assert_eq!(build.get_result(&old).await?, Some(TestStatus::Bad));
+
+        cmd_gc(&repo, day, false).await?;
+        assert_eq!(build.get_result(&old).await?, None);
+        assert_eq!(build.get_result(&new).await?, Some(TestStatus::Good));
+
+        let notes_commits = || git(dir, &["rev-list", "--count", "refs/notes/tests/build"]);
+        let before = notes_commits();
+        clear_log_contents();
+        cmd_gc(&repo, None, false).await?;
+        let log = get_log_contents();
+        assert_eq!(
+            log[..2],
+            [
+                format!("Pruned 2 results ({} bytes) for test 'build'", new_bytes),
+                format!("Pruned 2 results ({} bytes) in total", new_bytes),
+            ]
+        );
+        assert!(log[2].contains("only freed once that history is rewritten"));
+        assert_eq!(notes_commits().parse::<u32>()?, before.parse::<u32>()? + 1);
+        assert_eq!(build.get_result(&new).await?, None);
+        assert_eq!(build.get_result(&newest).await?, None);
+        assert_eq!(build.get_result(&base).await?, Some(TestStatus::Good));
+        Ok(())
+    }
+}